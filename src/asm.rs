@@ -0,0 +1,886 @@
+// Turns assembly into rom bytes. The mnemonics are the ones the disassembler
+// prints, so its listings assemble back into the rom they came from.
+//
+//     loop:   LD V0, K            ; labels end with a colon
+//             DRW V1, V2, 5
+//     WIDTH   EQU 64              ; constants, `WIDTH = 64` works too
+//             LD I, digits + 5 * 2
+//             DB 0xf0, 0x90, -1   ; data, DW for 16 bit big endian words
+//             INCLUDE "font.asm"  ; relative to the including file
+//     MACRO   draw x, y           ; parameters are swapped in word by word
+//             DRW x, y, 5
+//     ENDM
+//
+// Mnemonics and registers ignore case, symbols don't. Expressions are
+// described in `expr`. Labels can be used before they are defined, as the
+// size of every line is known without evaluating anything.
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::instruction::Instruction;
+use crate::platform::{Platform, PLATFORMS};
+use crate::processor::PROGRAM_START;
+
+mod expr;
+pub mod octo;
+use expr::Expr;
+
+// Includes, macros and constants nested deeper than this are assumed to be
+// recursive
+const MAX_DEPTH: usize = 16;
+
+const MNEMONICS: [&str; 33] = [
+    "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE",
+    "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW",
+    "SKP", "SKNP", "PLANE", "AUDIO", "PITCH", "DB", "DW",
+];
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    Io(PathBuf, io::Error),
+    UnknownInstruction(String),
+    InvalidOperands(String),
+    Unsupported(String, Platform),
+    UnknownSymbol(String),
+    DuplicateSymbol(String),
+    InvalidExpression(String),
+    OutOfRange(i64),
+    InvalidDirective(String),
+    TooDeep,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Io(path, err) => write!(f, "could not read {}: {}", path.display(), err),
+            ErrorKind::UnknownInstruction(name) => write!(f, "unknown instruction '{}'", name),
+            ErrorKind::InvalidOperands(line) => write!(f, "invalid operands in '{}'", line),
+            ErrorKind::Unsupported(line, platform) => {
+                let name = PLATFORMS
+                    .iter()
+                    .find(|(_, other)| other == platform)
+                    .map_or("this platform", |(name, _)| name);
+                write!(f, "'{}' is not available on {}", line, name)
+            }
+            ErrorKind::UnknownSymbol(name) => write!(f, "unknown symbol '{}'", name),
+            ErrorKind::DuplicateSymbol(name) => write!(f, "'{}' is already defined", name),
+            ErrorKind::InvalidExpression(reason) => write!(f, "invalid expression: {}", reason),
+            ErrorKind::OutOfRange(value) => write!(f, "{} is out of range", value),
+            ErrorKind::InvalidDirective(reason) => write!(f, "{}", reason),
+            ErrorKind::TooDeep => {
+                write!(f, "includes, macros or constants nested too deeply")
+            }
+        }
+    }
+}
+
+// An error and the line it was found on, lines from a macro are blamed on
+// the line using the macro
+#[derive(Debug)]
+pub struct AsmError {
+    pub file: String,
+    pub line: usize,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "{}: {}", self.file, self.kind),
+            line => write!(f, "{}:{}: {}", self.file, line, self.kind),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Debug)]
+pub struct Program {
+    pub bytes: Vec<u8>,
+    // Labels and constants
+    pub symbols: BTreeMap<String, i64>,
+}
+
+impl Program {
+    // One `0x204 loop` line per symbol, in order of value
+    pub fn symbol_file(&self) -> String {
+        let mut symbols: Vec<(&String, &i64)> = self.symbols.iter().collect();
+        symbols.sort_by_key(|&(name, value)| (*value, name));
+        symbols
+            .iter()
+            .map(|(name, value)| format!("{:#05x} {}\n", value, name))
+            .collect()
+    }
+}
+
+// Assembles `source`, includes are found relative to the working directory
+pub fn assemble(source: &str, platform: Platform) -> Result<Program, AsmError> {
+    assemble_with(Path::new("<source>"), source, platform, &mut |path| {
+        fs::read_to_string(path)
+    })
+}
+
+pub fn assemble_file<P: AsRef<Path>>(path: P, platform: Platform) -> Result<Program, AsmError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        kind: ErrorKind::Io(path.to_path_buf(), err),
+    })?;
+    assemble_with(path, &source, platform, &mut |path| {
+        fs::read_to_string(path)
+    })
+}
+
+// Assembles `source` read from `path`, with `load` reading included files
+pub fn assemble_with(
+    path: &Path,
+    source: &str,
+    platform: Platform,
+    load: &mut dyn FnMut(&Path) -> io::Result<String>,
+) -> Result<Program, AsmError> {
+    let mut expander = Expander {
+        load,
+        macros: HashMap::new(),
+        lines: Vec::new(),
+    };
+    expander.source(Rc::new(path.to_path_buf()), source, 0)?;
+    let layout = Layout::new(expander.lines)?;
+    layout.encode(platform)
+}
+
+// A line of source with its comment removed
+#[derive(Debug, Clone)]
+struct Line {
+    file: Rc<PathBuf>,
+    number: usize,
+    text: String,
+}
+
+impl Line {
+    fn error(&self, kind: ErrorKind) -> AsmError {
+        AsmError {
+            file: self.file.display().to_string(),
+            line: self.number,
+            kind,
+        }
+    }
+
+    // The same place in the source with different text
+    fn with_text(&self, text: &str) -> Line {
+        Line {
+            file: self.file.clone(),
+            number: self.number,
+            text: text.to_string(),
+        }
+    }
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Line>,
+}
+
+// Pulls in includes and expands macros, leaving only labels, constants,
+// instructions and data
+struct Expander<'a> {
+    load: &'a mut dyn FnMut(&Path) -> io::Result<String>,
+    macros: HashMap<String, Macro>,
+    lines: Vec<Line>,
+}
+
+impl<'a> Expander<'a> {
+    fn source(&mut self, file: Rc<PathBuf>, source: &str, depth: usize) -> Result<(), AsmError> {
+        let lines = source
+            .lines()
+            .enumerate()
+            .map(|(i, text)| Line {
+                file: file.clone(),
+                number: i + 1,
+                text: strip_comment(text).trim().to_string(),
+            })
+            .collect();
+        self.expand(lines, depth)
+    }
+
+    fn expand(&mut self, lines: Vec<Line>, depth: usize) -> Result<(), AsmError> {
+        let mut lines = lines.into_iter();
+        while let Some(line) = lines.next() {
+            let (label, rest) = split_label(&line.text);
+            if let Some(label) = label {
+                self.lines.push(line.with_text(&format!("{}:", label)));
+            }
+            let (word, args) = split_word(rest);
+            match word.to_uppercase().as_str() {
+                "" => {}
+                "INCLUDE" => {
+                    if depth >= MAX_DEPTH {
+                        return Err(line.error(ErrorKind::TooDeep));
+                    }
+                    let name = unquote(args).ok_or_else(|| {
+                        line.error(ErrorKind::InvalidDirective(
+                            "INCLUDE needs a quoted path".to_string(),
+                        ))
+                    })?;
+                    let path = line.file.parent().unwrap_or(Path::new("")).join(name);
+                    let source = (self.load)(&path)
+                        .map_err(|err| line.error(ErrorKind::Io(path.clone(), err)))?;
+                    self.source(Rc::new(path), &source, depth + 1)?;
+                }
+                "MACRO" => {
+                    let (name, params) = split_word(args);
+                    if name.is_empty() {
+                        return Err(line.error(ErrorKind::InvalidDirective(
+                            "MACRO needs a name".to_string(),
+                        )));
+                    }
+                    let mut body = Vec::new();
+                    loop {
+                        match lines.next() {
+                            Some(inner) if inner.text.eq_ignore_ascii_case("ENDM") => break,
+                            Some(inner) => body.push(inner),
+                            None => {
+                                return Err(line.error(ErrorKind::InvalidDirective(format!(
+                                    "MACRO {} has no ENDM",
+                                    name
+                                ))))
+                            }
+                        }
+                    }
+                    let params = split_operands(params)
+                        .into_iter()
+                        .filter(|param| !param.is_empty())
+                        .collect();
+                    self.macros.insert(name.to_string(), Macro { params, body });
+                }
+                "ENDM" => {
+                    return Err(line.error(ErrorKind::InvalidDirective(
+                        "ENDM without MACRO".to_string(),
+                    )))
+                }
+                _ if self.macros.contains_key(word) => {
+                    if depth >= MAX_DEPTH {
+                        return Err(line.error(ErrorKind::TooDeep));
+                    }
+                    let called = &self.macros[word];
+                    let args = match args.is_empty() {
+                        true => Vec::new(),
+                        false => split_operands(args),
+                    };
+                    if args.len() != called.params.len() {
+                        return Err(line.error(ErrorKind::InvalidOperands(line.text.clone())));
+                    }
+                    let body = called
+                        .body
+                        .iter()
+                        .map(|inner| {
+                            line.with_text(&substitute(&inner.text, &called.params, &args))
+                        })
+                        .collect();
+                    self.expand(body, depth + 1)?;
+                }
+                _ => self.lines.push(line.with_text(rest)),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+enum Item {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+struct Statement {
+    line: Line,
+    address: usize,
+    item: Item,
+}
+
+enum Symbol {
+    Label(usize),
+    // The expression and the address `$` stands for in it
+    Constant(Expr, usize, Line),
+}
+
+// Every line placed at its address, with the labels pointing at them
+struct Layout {
+    statements: Vec<Statement>,
+    symbols: HashMap<String, Symbol>,
+}
+
+impl Layout {
+    fn new(lines: Vec<Line>) -> Result<Layout, AsmError> {
+        let mut layout = Layout {
+            statements: Vec::new(),
+            symbols: HashMap::new(),
+        };
+        let mut address = PROGRAM_START;
+        for line in lines {
+            let (label, rest) = split_label(&line.text);
+            if let Some(label) = label {
+                layout.define(&line, label, Symbol::Label(address))?;
+            }
+            let (word, args) = split_word(rest);
+            let (second, value) = split_word(args);
+            let constant = match second {
+                _ if second.eq_ignore_ascii_case("EQU") => Some(value),
+                "=" => Some(value),
+                _ => args.strip_prefix('=').filter(|_| !word.is_empty()),
+            };
+            let item = match (constant, word.to_uppercase().as_str()) {
+                (Some(value), _) => {
+                    let expr = Expr::parse(value).map_err(|kind| line.error(kind))?;
+                    layout.define(&line, word, Symbol::Constant(expr, address, line.clone()))?;
+                    continue;
+                }
+                (None, "") => continue,
+                (None, "DB") => Item::Bytes(expressions(&line, args)?),
+                (None, "DW") => Item::Words(expressions(&line, args)?),
+                (None, mnemonic) => Item::Instruction {
+                    mnemonic: mnemonic.to_string(),
+                    operands: split_operands(args),
+                },
+            };
+            let size = match &item {
+                Item::Instruction { mnemonic, operands } => {
+                    match mnemonic == "LD" && operands.get(1).is_some_and(|op| is_long(op)) {
+                        true => 4,
+                        false => 2,
+                    }
+                }
+                Item::Bytes(exprs) => exprs.len(),
+                Item::Words(exprs) => exprs.len() * 2,
+            };
+            layout.statements.push(Statement {
+                line,
+                address,
+                item,
+            });
+            address += size;
+        }
+        Ok(layout)
+    }
+
+    fn define(&mut self, line: &Line, name: &str, symbol: Symbol) -> Result<(), AsmError> {
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+            || !name.chars().all(is_symbol_char)
+        {
+            return Err(line.error(ErrorKind::InvalidDirective(format!(
+                "'{}' is not a valid symbol name",
+                name
+            ))));
+        }
+        if self.symbols.contains_key(name) {
+            return Err(line.error(ErrorKind::DuplicateSymbol(name.to_string())));
+        }
+        self.symbols.insert(name.to_string(), symbol);
+        Ok(())
+    }
+
+    fn resolve(&self, name: &str, depth: usize) -> Result<i64, ErrorKind> {
+        match self.symbols.get(name) {
+            None => Err(ErrorKind::UnknownSymbol(name.to_string())),
+            Some(Symbol::Label(address)) => Ok(*address as i64),
+            Some(_) if depth >= MAX_DEPTH => Err(ErrorKind::TooDeep),
+            Some(Symbol::Constant(expr, here, _)) => {
+                expr.eval(*here, &mut |name| self.resolve(name, depth + 1))
+            }
+        }
+    }
+
+    fn eval(&self, expr: &Expr, here: usize) -> Result<i64, ErrorKind> {
+        expr.eval(here, &mut |name| self.resolve(name, 0))
+    }
+
+    fn encode(&self, platform: Platform) -> Result<Program, AsmError> {
+        let mut bytes = Vec::new();
+        for statement in self.statements.iter() {
+            let line = &statement.line;
+            let here = statement.address;
+            match &statement.item {
+                Item::Instruction { mnemonic, operands } => {
+                    let words = self
+                        .instruction(mnemonic, operands, here, platform)
+                        .map_err(|kind| line.error(kind))?;
+                    for word in words {
+                        bytes.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+                Item::Bytes(exprs) => {
+                    for expr in exprs {
+                        let value = self.eval(expr, here).and_then(|v| in_range(v, -0x80, 0xff));
+                        bytes.push(value.map_err(|kind| line.error(kind))? as u8);
+                    }
+                }
+                Item::Words(exprs) => {
+                    for expr in exprs {
+                        let value = self
+                            .eval(expr, here)
+                            .and_then(|v| in_range(v, -0x8000, 0xffff));
+                        let word = value.map_err(|kind| line.error(kind))? as u16;
+                        bytes.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+            }
+        }
+
+        let mut symbols = BTreeMap::new();
+        let mut names: Vec<&String> = self.symbols.keys().collect();
+        names.sort();
+        for name in names {
+            let value = match &self.symbols[name] {
+                Symbol::Label(address) => *address as i64,
+                Symbol::Constant(_, _, line) => {
+                    self.resolve(name, 0).map_err(|kind| line.error(kind))?
+                }
+            };
+            symbols.insert(name.clone(), value);
+        }
+        Ok(Program { bytes, symbols })
+    }
+
+    // The opcode and, for F000 NNNN, the address after it
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        operands: &[String],
+        here: usize,
+        platform: Platform,
+    ) -> Result<Vec<u16>, ErrorKind> {
+        use Instruction::*;
+        use Operand::*;
+
+        let text = || {
+            format!("{} {}", mnemonic, operands.join(", "))
+                .trim()
+                .to_string()
+        };
+        if !MNEMONICS.contains(&mnemonic) {
+            return Err(ErrorKind::UnknownInstruction(mnemonic.to_string()));
+        }
+        let ops = operands
+            .iter()
+            .map(|op| self.operand(op, here))
+            .collect::<Result<Vec<Operand>, ErrorKind>>()?;
+        let byte = |value: i64| in_range(value, -0x80, 0xff).map(|value| value as u8);
+        let nibble = |value: i64| in_range(value, 0, 0xf).map(|value| value as u8);
+        let address = |value: i64| in_range(value, 0, 0xfff).map(|value| value as u16);
+
+        let instruction = match (mnemonic, ops.as_slice()) {
+            ("CLS", []) => Clear,
+            ("RET", []) => Return,
+            ("SCD", [Value(n)]) => ScrollDown(nibble(*n)?),
+            ("SCU", [Value(n)]) => ScrollUp(nibble(*n)?),
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => Lores,
+            ("HIGH", []) => Hires,
+            ("JP", [Value(nnn)]) => Jump(address(*nnn)?),
+            ("JP", [V(0), Value(nnn)]) => JumpOffset(address(*nnn)?),
+            ("CALL", [Value(nnn)]) => Call(address(*nnn)?),
+            ("SE", [V(x), Value(nn)]) => SkipEqual(*x, byte(*nn)?),
+            ("SE", [V(x), V(y)]) => SkipEqualRegister(*x, *y),
+            ("SNE", [V(x), Value(nn)]) => SkipNotEqual(*x, byte(*nn)?),
+            ("SNE", [V(x), V(y)]) => SkipNotEqualRegister(*x, *y),
+            ("SAVE", [V(x), V(y)]) => SaveRange(*x, *y),
+            ("LOAD", [V(x), V(y)]) => LoadRange(*x, *y),
+            ("LD", [V(x), Value(nn)]) => Set(*x, byte(*nn)?),
+            ("LD", [V(x), V(y)]) => Copy(*x, *y),
+            ("LD", [I, Value(nnn)]) => SetIndex(address(*nnn)?),
+            ("LD", [I, Long(nnnn)]) => {
+                let nnnn = in_range(*nnnn, 0, 0xffff)? as u16;
+                return check(SetIndexLong, platform, text).map(|op| vec![op, nnnn]);
+            }
+            ("LD", [V(x), Dt]) => GetDelay(*x),
+            ("LD", [V(x), K]) => WaitKey(*x),
+            ("LD", [Dt, V(x)]) => SetDelay(*x),
+            ("LD", [St, V(x)]) => SetSound(*x),
+            ("LD", [F, V(x)]) => Font(*x),
+            ("LD", [Hf, V(x)]) => BigFont(*x),
+            ("LD", [B, V(x)]) => Bcd(*x),
+            ("LD", [IndirectI, V(x)]) => Store(*x),
+            ("LD", [V(x), IndirectI]) => Load(*x),
+            ("LD", [R, V(x)]) => SaveFlags(*x),
+            ("LD", [V(x), R]) => LoadFlags(*x),
+            ("ADD", [V(x), Value(nn)]) => Add(*x, byte(*nn)?),
+            ("ADD", [V(x), V(y)]) => AddRegister(*x, *y),
+            ("ADD", [I, V(x)]) => AddIndex(*x),
+            ("OR", [V(x), V(y)]) => Or(*x, *y),
+            ("AND", [V(x), V(y)]) => And(*x, *y),
+            ("XOR", [V(x), V(y)]) => Xor(*x, *y),
+            ("SUB", [V(x), V(y)]) => Sub(*x, *y),
+            ("SUBN", [V(x), V(y)]) => SubReverse(*x, *y),
+            // VY can be left out, it's only read with the `shift_vy` quirk
+            ("SHR", [V(x)]) => ShiftRight(*x, *x),
+            ("SHR", [V(x), V(y)]) => ShiftRight(*x, *y),
+            ("SHL", [V(x)]) => ShiftLeft(*x, *x),
+            ("SHL", [V(x), V(y)]) => ShiftLeft(*x, *y),
+            ("RND", [V(x), Value(nn)]) => Random(*x, byte(*nn)?),
+            ("DRW", [V(x), V(y), Value(n)]) => Draw(*x, *y, nibble(*n)?),
+            ("SKP", [V(x)]) => SkipKey(*x),
+            ("SKNP", [V(x)]) => SkipNotKey(*x),
+            ("PLANE", [Value(n)]) => Planes(nibble(*n)?),
+            ("AUDIO", []) => LoadAudio,
+            ("PITCH", [V(x)]) => Pitch(*x),
+            _ => return Err(ErrorKind::InvalidOperands(text())),
+        };
+        check(instruction, platform, text).map(|op| vec![op])
+    }
+
+    fn operand(&self, text: &str, here: usize) -> Result<Operand, ErrorKind> {
+        let upper = text.to_uppercase();
+        let operand = match upper.as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::Hf,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            _ if upper.len() == 2 && upper.starts_with('V') => {
+                match usize::from_str_radix(&upper[1..], 16) {
+                    Ok(x) => Operand::V(x),
+                    Err(_) => Operand::Value(self.eval(&Expr::parse(text)?, here)?),
+                }
+            }
+            _ if is_long(text) => Operand::Long(self.eval(&Expr::parse(&text[4..])?, here)?),
+            _ => Operand::Value(self.eval(&Expr::parse(text)?, here)?),
+        };
+        Ok(operand)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    V(usize),
+    I,
+    // [I]
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    // The address of LD I, LONG NNNN
+    Long(i64),
+    Value(i64),
+}
+
+// Encodes `instruction` if `platform` has it
+fn check(
+    instruction: Instruction,
+    platform: Platform,
+    text: impl Fn() -> String,
+) -> Result<u16, ErrorKind> {
+    match instruction.platform() <= platform {
+        true => Ok(instruction.encode()),
+        false => Err(ErrorKind::Unsupported(text(), platform)),
+    }
+}
+
+fn in_range(value: i64, min: i64, max: i64) -> Result<i64, ErrorKind> {
+    match (min..=max).contains(&value) {
+        true => Ok(value),
+        false => Err(ErrorKind::OutOfRange(value)),
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn is_long(operand: &str) -> bool {
+    operand
+        .get(..5)
+        .is_some_and(|word| word.eq_ignore_ascii_case("LONG "))
+}
+
+// Drops everything after a `;` that isn't inside quotes
+fn strip_comment(text: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &text[..i],
+            _ => {}
+        }
+    }
+    text
+}
+
+// Splits `name: rest` into the label and the rest of the line
+fn split_label(text: &str) -> (Option<&str>, &str) {
+    match text.find(':') {
+        Some(i) if i > 0 && text[..i].chars().all(is_symbol_char) => {
+            (Some(&text[..i]), text[i + 1..].trim())
+        }
+        _ => (None, text),
+    }
+}
+
+// Splits off the first word
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim()),
+        None => (text, ""),
+    }
+}
+
+// Splits operands on the commas that aren't inside parentheses
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                operands.push(text[start..i].trim().to_string());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !text.trim().is_empty() {
+        operands.push(text[start..].trim().to_string());
+    }
+    operands
+}
+
+fn expressions(line: &Line, text: &str) -> Result<Vec<Expr>, AsmError> {
+    split_operands(text)
+        .iter()
+        .map(|operand| Expr::parse(operand).map_err(|kind| line.error(kind)))
+        .collect()
+}
+
+fn unquote(text: &str) -> Option<&str> {
+    text.strip_prefix('"')?.strip_suffix('"')
+}
+
+// Replaces whole words matching a macro parameter with its argument
+fn substitute(text: &str, params: &[String], args: &[String]) -> String {
+    let mut result = String::new();
+    let mut word = String::new();
+    for c in text.chars().chain(Some('\n')) {
+        if is_symbol_char(c) {
+            word.push(c);
+            continue;
+        }
+        match params.iter().position(|param| *param == word) {
+            Some(i) => result += &args[i],
+            None => result += &word,
+        }
+        word.clear();
+        if c != '\n' {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io, path::Path};
+
+    use crate::asm::{assemble, assemble_with, AsmError, ErrorKind, Program};
+    use crate::platform::Platform;
+
+    fn chip8(source: &str) -> Result<Program, AsmError> {
+        assemble(source, Platform::Chip8)
+    }
+
+    fn bytes(source: &str) -> Vec<u8> {
+        chip8(source).unwrap().bytes
+    }
+
+    #[test]
+    fn instructions() {
+        let source = "
+            CLS
+            ld v1, 0x0c         ; lower case works too
+            LD I, 0x260
+            DRW V1, V2, 5
+            ADD V1, 8
+            ADD V1, -1
+            SE V1, V2
+            SHR V3
+            LD [I], VF
+            LD VA, K
+            JP V0, 0x300
+        ";
+        assert_eq!(
+            bytes(source),
+            [
+                0x00, 0xe0, 0x61, 0x0c, 0xa2, 0x60, 0xd1, 0x25, 0x71, 0x08, 0x71, 0xff, 0x51, 0x20,
+                0x83, 0x36, 0xff, 0x55, 0xfa, 0x0a, 0xb3, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_and_data() {
+        let source = "
+            start:  JP main
+            sprite: DB 0xf0, 0x90, 0b11110000
+                    DW 0x1234, sprite
+            main:   LD I, sprite
+            loop:   JP loop
+        ";
+        let program = chip8(source).unwrap();
+        assert_eq!(
+            program.bytes,
+            [0x12, 0x09, 0xf0, 0x90, 0xf0, 0x12, 0x34, 0x02, 0x02, 0xa2, 0x02, 0x12, 0x0b]
+        );
+        assert_eq!(program.symbols["main"], 0x209);
+        assert_eq!(
+            program.symbol_file(),
+            "0x200 start\n0x202 sprite\n0x209 main\n0x20b loop\n"
+        );
+    }
+
+    #[test]
+    fn constants_and_expressions() {
+        let source = "
+            SIZE EQU 5
+            ROW = digits + SIZE * 2
+                LD I, ROW
+                LD V0, SIZE << 1 | 1
+                DB $ - 0x200, (SIZE + 1) * 2
+            digits: DB 0
+        ";
+        assert_eq!(bytes(source), [0xa2, 0x10, 0x60, 0x0b, 0x04, 0x0c, 0x00]);
+    }
+
+    #[test]
+    fn macros() {
+        let source = "
+            MACRO draw x, y, sprite
+                LD I, sprite
+                DRW x, y, 5
+            ENDM
+            MACRO twice
+                draw V1, V2, 0x300
+                draw V3, V4, 0x305
+            ENDM
+            top: twice
+                 JP top
+        ";
+        assert_eq!(
+            bytes(source),
+            [0xa3, 0x00, 0xd1, 0x25, 0xa3, 0x05, 0xd3, 0x45, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn includes() {
+        let files: HashMap<&str, &str> = [
+            ("game/font.asm", "font: DB 0xf0\nINCLUDE \"more/extra.asm\""),
+            ("game/more/extra.asm", "extra: DB 0x90"),
+        ]
+        .iter()
+        .copied()
+        .collect();
+        let mut load = |path: &Path| {
+            files
+                .get(path.to_str().unwrap())
+                .map(|source| source.to_string())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+        };
+        let source = "LD I, extra\nINCLUDE \"font.asm\"";
+        let program = assemble_with(
+            Path::new("game/main.asm"),
+            source,
+            Platform::Chip8,
+            &mut load,
+        )
+        .unwrap();
+        assert_eq!(program.bytes, [0xa2, 0x03, 0xf0, 0x90]);
+
+        let err = assemble_with(
+            Path::new("game/main.asm"),
+            "INCLUDE \"gone.asm\"",
+            Platform::Chip8,
+            &mut load,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "game/main.asm:1: could not read game/gone.asm: not found"
+        );
+    }
+
+    #[test]
+    fn platforms() {
+        assert!(matches!(
+            chip8("HIGH").unwrap_err().kind,
+            ErrorKind::Unsupported(..)
+        ));
+        assert_eq!(
+            assemble("HIGH\nLD HF, V1", Platform::SuperChip)
+                .unwrap()
+                .bytes,
+            [0x00, 0xff, 0xf1, 0x30]
+        );
+        let program = assemble("LD I, LONG data\nPLANE 3\ndata: DB 1", Platform::XoChip).unwrap();
+        assert_eq!(program.bytes, [0xf0, 0x00, 0x02, 0x06, 0xf3, 0x01, 0x01]);
+        assert_eq!(
+            chip8("LD I, LONG 0x1234").unwrap_err().to_string(),
+            "<source>:1: 'LD I, LONG 0x1234' is not available on chip8"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("FOO V1", 1, "unknown instruction 'FOO'"),
+            (
+                "CLS\nLD V1, V2, V3",
+                2,
+                "invalid operands in 'LD V1, V2, V3'",
+            ),
+            ("JP nowhere", 1, "unknown symbol 'nowhere'"),
+            ("a: CLS\na: CLS", 2, "'a' is already defined"),
+            ("LD V0, 256", 1, "256 is out of range"),
+            ("DRW V0, V1, 16", 1, "16 is out of range"),
+            ("JP 0x1000", 1, "4096 is out of range"),
+            ("DB 1 +", 1, "invalid expression: expression ends too early"),
+            ("MACRO m\nCLS", 1, "MACRO m has no ENDM"),
+            ("ENDM", 1, "ENDM without MACRO"),
+            (
+                "MACRO m\nm\nENDM\nm",
+                4,
+                "includes, macros or constants nested too deeply",
+            ),
+            (
+                "A EQU B\nB EQU A",
+                1,
+                "includes, macros or constants nested too deeply",
+            ),
+            ("MACRO m x\nCLS\nENDM\nm", 4, "invalid operands in 'm'"),
+        ];
+        for (source, line, message) in cases.iter() {
+            let err = chip8(source).unwrap_err();
+            assert_eq!(err.line, *line, "{}", source);
+            assert_eq!(err.kind.to_string(), *message, "{}", source);
+        }
+    }
+}
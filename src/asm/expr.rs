@@ -0,0 +1,248 @@
+use crate::asm::ErrorKind;
+
+// A constant expression such as `sprites + 5 * 2`. Numbers are decimal, or
+// hex and binary with 0x and 0b, symbols are labels or constants and `$` is
+// the address of the current line. Operators bind like they do in C.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String),
+    Here,
+    Negate(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Or,
+    Xor,
+    And,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+// Operators from loosest to tightest
+const LEVELS: [&[(&str, Op)]; 5] = [
+    &[("|", Op::Or)],
+    &[("^", Op::Xor)],
+    &[("&", Op::And)],
+    &[("<<", Op::Shl), (">>", Op::Shr)],
+    &[("+", Op::Add), ("-", Op::Sub)],
+];
+const PRODUCT: [(&str, Op); 3] = [("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)];
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, ErrorKind> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let expr = parser.level(0)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(invalid(&format!("unexpected '{}' in '{}'", token, text))),
+        }
+    }
+
+    // `here` is the value of `$`, `symbol` looks up labels and constants
+    pub fn eval(
+        &self,
+        here: usize,
+        symbol: &mut dyn FnMut(&str) -> Result<i64, ErrorKind>,
+    ) -> Result<i64, ErrorKind> {
+        let value = match self {
+            Expr::Number(n) => *n,
+            Expr::Symbol(name) => symbol(name)?,
+            Expr::Here => here as i64,
+            Expr::Negate(expr) => expr.eval(here, symbol)?.wrapping_neg(),
+            Expr::Not(expr) => !expr.eval(here, symbol)?,
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.eval(here, symbol)?, right.eval(here, symbol)?);
+                match op {
+                    Op::Or => left | right,
+                    Op::Xor => left ^ right,
+                    Op::And => left & right,
+                    Op::Shl => left.wrapping_shl(right as u32),
+                    Op::Shr => left.wrapping_shr(right as u32),
+                    Op::Add => left.wrapping_add(right),
+                    Op::Sub => left.wrapping_sub(right),
+                    Op::Mul => left.wrapping_mul(right),
+                    Op::Div | Op::Rem if right == 0 => return Err(invalid("division by zero")),
+                    Op::Div => left
+                        .checked_div(right)
+                        .ok_or_else(|| invalid("division overflows"))?,
+                    Op::Rem => left
+                        .checked_rem(right)
+                        .ok_or_else(|| invalid("division overflows"))?,
+                }
+            }
+        };
+        Ok(value)
+    }
+}
+
+fn invalid(reason: &str) -> ErrorKind {
+    ErrorKind::InvalidExpression(reason.to_string())
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+// Splits an expression into numbers, symbols and operators
+fn tokenize(text: &str) -> Result<Vec<String>, ErrorKind> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let len = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            _ if is_symbol_char(c) => chars[i..]
+                .iter()
+                .take_while(|&&c| is_symbol_char(c))
+                .count(),
+            '<' | '>' => match chars.get(i + 1) {
+                Some(&next) if next == c => 2,
+                _ => return Err(invalid(&format!("unexpected '{}' in '{}'", c, text))),
+            },
+            '|' | '^' | '&' | '+' | '-' | '*' | '/' | '%' | '~' | '(' | ')' | '$' => 1,
+            _ => return Err(invalid(&format!("unexpected '{}' in '{}'", c, text))),
+        };
+        tokens.push(chars[i..i + len].iter().collect());
+        i += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.next).map(String::as_str)
+    }
+
+    fn take(&mut self) -> Result<String, ErrorKind> {
+        let token = self
+            .tokens
+            .get(self.next)
+            .cloned()
+            .ok_or_else(|| invalid("expression ends too early"))?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    // Binary operators at `LEVELS[level]` and tighter
+    fn level(&mut self, level: usize) -> Result<Expr, ErrorKind> {
+        let ops: &[(&str, Op)] = match LEVELS.get(level) {
+            Some(ops) => ops,
+            None => &PRODUCT,
+        };
+        let operand = |parser: &mut Parser| match level < LEVELS.len() {
+            true => parser.level(level + 1),
+            false => parser.unary(),
+        };
+        let mut expr = operand(self)?;
+        while let Some(&(_, op)) = ops.iter().find(|(token, _)| self.peek() == Some(token)) {
+            self.next += 1;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(operand(self)?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ErrorKind> {
+        let token = self.take()?;
+        let expr = match token.as_str() {
+            "-" => Expr::Negate(Box::new(self.unary()?)),
+            "~" => Expr::Not(Box::new(self.unary()?)),
+            "+" => self.unary()?,
+            "$" => Expr::Here,
+            "(" => {
+                let expr = self.level(0)?;
+                match self.take()?.as_str() {
+                    ")" => expr,
+                    token => return Err(invalid(&format!("expected ')' but found '{}'", token))),
+                }
+            }
+            _ if token.starts_with(|c: char| c.is_ascii_digit()) => Expr::Number(number(&token)?),
+            _ if token.starts_with(is_symbol_char) => Expr::Symbol(token),
+            _ => return Err(invalid(&format!("unexpected '{}'", token))),
+        };
+        Ok(expr)
+    }
+}
+
+fn number(token: &str) -> Result<i64, ErrorKind> {
+    let lower = token.to_lowercase();
+    let (digits, radix) = match lower.get(..2) {
+        Some("0x") => (&lower[2..], 16),
+        Some("0b") => (&lower[2..], 2),
+        _ => (&lower[..], 10),
+    };
+    i64::from_str_radix(digits, radix).map_err(|_| invalid(&format!("bad number '{}'", token)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::expr::Expr;
+    use crate::asm::ErrorKind;
+
+    fn eval(text: &str) -> Result<i64, ErrorKind> {
+        Expr::parse(text)?.eval(0x210, &mut |name| match name {
+            "sprites" => Ok(0x300),
+            _ => Err(ErrorKind::UnknownSymbol(name.to_string())),
+        })
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(eval("1 + 2 * 3").unwrap(), 7);
+        assert_eq!(eval("(1 + 2) * 3").unwrap(), 9);
+        assert_eq!(eval("sprites + 5 * 2").unwrap(), 0x30a);
+        assert_eq!(eval("0x10 | 0b11 << 1").unwrap(), 0x16);
+        assert_eq!(eval("-1 & 0xff").unwrap(), 0xff);
+        assert_eq!(eval("~0 ^ 0x0F").unwrap(), -16);
+        assert_eq!(eval("$ - 0x200").unwrap(), 0x10);
+        assert_eq!(eval("17 / 5 + 17 % 5").unwrap(), 5);
+        assert_eq!(eval("sprites >> 8").unwrap(), 3);
+        assert_eq!(eval("(-0x7fffffffffffffff - 1) / 1").unwrap(), i64::MIN);
+    }
+
+    #[test]
+    fn bad_expressions() {
+        for text in [
+            "",
+            "1 +",
+            "(1",
+            "1 2",
+            "1 < 2",
+            "0xg",
+            "@",
+            "4 / 0",
+            "(-0x7fffffffffffffff - 1) / -1",
+            "(-0x7fffffffffffffff - 1) % -1",
+        ]
+        .iter()
+        {
+            assert!(
+                matches!(eval(text), Err(ErrorKind::InvalidExpression(_))),
+                "{}",
+                text
+            );
+        }
+        assert!(matches!(
+            eval("missing + 1"),
+            Err(ErrorKind::UnknownSymbol(name)) if name == "missing"
+        ));
+    }
+}
@@ -0,0 +1,1138 @@
+// Compiles Octo, the language most modern chip-8 programs are written in.
+//
+//     : main                      # labels, execution starts at main
+//         v0 := 0                 # register syntax for every instruction
+//         i := digits
+//         loop
+//             sprite v0 v1 5
+//             v0 += 5
+//             if v0 == 40 then v1 += 6
+//             while v1 < 24
+//         again
+//     : digits 0xF0 0x90 0xF0     # bare numbers are data bytes
+//
+// `:alias`, `:const`, `:calc`, `:macro`, `:next`, `:org`, `:byte` and
+// `:call` work as in Octo, as do `if ... then`, `if ... begin ... else ...
+// end` and `loop ... while ... again`. Labels can be used before they are
+// defined anywhere an address is expected. The rom starts with a jump to
+// main.
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    f64::consts,
+    fs,
+    path::Path,
+};
+
+use crate::asm::{AsmError, ErrorKind, Program, MAX_DEPTH};
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use crate::processor::PROGRAM_START;
+
+// Registers VF is used as scratch by `<`, `>`, `<=` and `>=` conditions
+const VF: usize = 0xf;
+
+pub fn compile(source: &str, platform: Platform) -> Result<Program, AsmError> {
+    compile_named("<source>", source, platform)
+}
+
+pub fn compile_file<P: AsRef<Path>>(path: P, platform: Platform) -> Result<Program, AsmError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.display().to_string(),
+        line: 0,
+        kind: ErrorKind::Io(path.to_path_buf(), err),
+    })?;
+    compile_named(&path.display().to_string(), &source, platform)
+}
+
+fn compile_named(file: &str, source: &str, platform: Platform) -> Result<Program, AsmError> {
+    let compiler = Compiler {
+        file: file.to_string(),
+        platform,
+        tokens: tokenize(source),
+        line: 0,
+        rom: Vec::new(),
+        here: PROGRAM_START,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+        keyword: String::new(),
+    };
+    compiler.compile()
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    // How many macros deep the token came from
+    depth: usize,
+}
+
+// Splits on whitespace, with braces always tokens of their own and `#`
+// starting a comment
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (i, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or_default();
+        let spaced = code.replace('{', " { ").replace('}', " } ");
+        for word in spaced.split_whitespace() {
+            tokens.push_back(Token {
+                text: word.to_string(),
+                line: i + 1,
+                depth: 0,
+            });
+        }
+    }
+    tokens
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+// An address written before its label was defined
+struct Fixup {
+    address: usize,
+    name: String,
+    line: usize,
+    // F000 NNNN takes the whole next word rather than the low 12 bits
+    long: bool,
+}
+
+// Control flow waiting for its end, with the address of the jump to patch
+enum Block {
+    If(usize),
+    Else(usize),
+    Loop { start: usize, exits: Vec<usize> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Test {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Key,
+    NotKey,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Register(usize),
+    Value(u8),
+}
+
+struct Condition {
+    x: usize,
+    test: Test,
+    operand: Operand,
+}
+
+struct Compiler {
+    file: String,
+    platform: Platform,
+    tokens: VecDeque<Token>,
+    // Line of the last token taken, errors are blamed on it
+    line: usize,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, usize)>,
+    // The word naming the instruction being compiled, for errors
+    keyword: String,
+}
+
+impl Compiler {
+    fn compile(mut self) -> Result<Program, AsmError> {
+        // Jumps to main, patched like any other forward reference
+        self.emit_word(0x1000)?;
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+        if let Some((_, line)) = self.blocks.last() {
+            self.line = *line;
+            return Err(self.directive("block is never closed"));
+        }
+
+        self.fixups.push(Fixup {
+            address: PROGRAM_START,
+            name: "main".to_string(),
+            line: 0,
+            long: false,
+        });
+        for fixup in std::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+            // Constants defined after their first use count too
+            let address = match self.labels.get(&fixup.name) {
+                Some(&address) => address as i64,
+                None => match self.constants.get(&fixup.name) {
+                    Some(value) => value.floor() as i64,
+                    None => return Err(self.error(ErrorKind::UnknownSymbol(fixup.name))),
+                },
+            };
+            let i = fixup.address - PROGRAM_START;
+            match fixup.long {
+                true => {
+                    let address = self.check(address, 0, 0xffff)? as u16;
+                    self.rom[i..i + 2].copy_from_slice(&address.to_be_bytes());
+                }
+                false => {
+                    let address = self.check(address, 0, 0xfff)? as u16;
+                    let opcode = u16::from_be_bytes([self.rom[i], self.rom[i + 1]]) | address;
+                    self.rom[i..i + 2].copy_from_slice(&opcode.to_be_bytes());
+                }
+            }
+        }
+
+        let mut symbols: BTreeMap<String, i64> = self
+            .labels
+            .iter()
+            .map(|(name, &address)| (name.clone(), address as i64))
+            .collect();
+        for (name, value) in self.constants.iter() {
+            symbols.insert(name.clone(), value.floor() as i64);
+        }
+        Ok(Program {
+            bytes: self.rom,
+            symbols,
+        })
+    }
+
+    fn error(&self, kind: ErrorKind) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            kind,
+        }
+    }
+
+    fn directive(&self, reason: &str) -> AsmError {
+        self.error(ErrorKind::InvalidDirective(reason.to_string()))
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token)
+            }
+            None => Err(self.directive("source ends too early")),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match token.text == text {
+            true => Ok(()),
+            false => {
+                Err(self.directive(&format!("expected '{}' but found '{}'", text, token.text)))
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        self.keyword = token.text.clone();
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define(name, self.here)?;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define(name, self.here + 1)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let x = self.register()?;
+                self.aliases.insert(name, x);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()? as f64;
+                self.constant(name, value)?;
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.constant(name, value)?;
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let value = self.value()?;
+                self.here = self.check(value, PROGRAM_START as i64, 0xffff)? as usize;
+            }
+            ":byte" => {
+                let value = match self.peek() {
+                    Some("{") => self.calc()?.floor() as i64,
+                    _ => self.value()?,
+                };
+                let byte = self.check(value, -0x80, 0xff)? as u8;
+                self.emit_bytes(&[byte])?;
+            }
+            ":call" => {
+                let opcode = self.address_operand(0x2000)?;
+                self.emit_word(opcode)?;
+            }
+            ";" | "return" => self.emit(Instruction::Return)?,
+            "clear" => self.emit(Instruction::Clear)?,
+            "exit" => self.emit(Instruction::Exit)?,
+            "lores" => self.emit(Instruction::Lores)?,
+            "hires" => self.emit(Instruction::Hires)?,
+            "scroll-left" => self.emit(Instruction::ScrollLeft)?,
+            "scroll-right" => self.emit(Instruction::ScrollRight)?,
+            "audio" => self.emit(Instruction::LoadAudio)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollDown(n))?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::ScrollUp(n))?;
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(Instruction::Planes(n))?;
+            }
+            "jump" => {
+                let opcode = self.address_operand(0x1000)?;
+                self.emit_word(opcode)?;
+            }
+            "jump0" => {
+                let opcode = self.address_operand(0xb000)?;
+                self.emit_word(opcode)?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(Instruction::Bcd(x))?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let instruction = match (self.peek() == Some("-"), token.text == "save") {
+                    (true, save) => {
+                        self.next()?;
+                        let y = self.register()?;
+                        match save {
+                            true => Instruction::SaveRange(x, y),
+                            false => Instruction::LoadRange(x, y),
+                        }
+                    }
+                    (false, true) => Instruction::Store(x),
+                    (false, false) => Instruction::Load(x),
+                };
+                self.emit(instruction)?;
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::SaveFlags(x))?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(Instruction::LoadFlags(x))?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(Instruction::Draw(x, y, n))?;
+            }
+            "i" => self.index()?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match token.text.as_str() {
+                    "delay" => Instruction::SetDelay(x),
+                    "buzzer" => Instruction::SetSound(x),
+                    _ => Instruction::Pitch(x),
+                })?;
+            }
+            "loop" => self.blocks.push((
+                Block::Loop {
+                    start: self.here,
+                    exits: Vec::new(),
+                },
+                token.line,
+            )),
+            "while" => {
+                let condition = self.condition()?;
+                self.skip(&condition, true)?;
+                let exit = self.here;
+                self.emit_word(0x1000)?;
+                let exits = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(block, _)| match block {
+                        Block::Loop { exits, .. } => Some(exits),
+                        _ => None,
+                    });
+                match exits {
+                    Some(exits) => exits.push(exit),
+                    None => return Err(self.directive("while outside a loop")),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start, exits }, _)) => {
+                    let start = self.check(start as i64, 0, 0xfff)? as u16;
+                    self.emit_word(0x1000 | start)?;
+                    for exit in exits {
+                        self.patch_jump(exit)?;
+                    }
+                }
+                _ => return Err(self.directive("again without loop")),
+            },
+            "if" => {
+                let condition = self.condition()?;
+                match self.next()?.text.as_str() {
+                    "then" => self.skip(&condition, false)?,
+                    "begin" => {
+                        self.skip(&condition, true)?;
+                        self.blocks.push((Block::If(self.here), token.line));
+                        self.emit_word(0x1000)?;
+                    }
+                    other => {
+                        return Err(self.directive(&format!(
+                            "expected 'then' or 'begin' but found '{}'",
+                            other
+                        )))
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((Block::If(jump), line)) => {
+                    let skip_else = self.here;
+                    self.emit_word(0x1000)?;
+                    self.patch_jump(jump)?;
+                    self.blocks.push((Block::Else(skip_else), line));
+                }
+                _ => return Err(self.directive("else without if ... begin")),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::If(jump), _)) | Some((Block::Else(jump), _)) => {
+                    self.patch_jump(jump)?
+                }
+                _ => return Err(self.directive("end without if ... begin")),
+            },
+            text if self.register_number(text).is_some() => {
+                self.assignment(self.register_number(text).unwrap_or_default())?
+            }
+            text if self.macros.contains_key(text) => self.expand(&token)?,
+            text if text.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+                let value = self.number(&token.text)?;
+                let byte = self.check(value, -0x80, 0xff)? as u8;
+                self.emit_bytes(&[byte])?;
+            }
+            text if text.starts_with(':') => {
+                return Err(self.error(ErrorKind::UnknownInstruction(token.text.clone())))
+            }
+            // Anything else names a subroutine to call
+            _ => {
+                self.tokens.push_front(token);
+                let opcode = self.address_operand(0x2000)?;
+                self.emit_word(opcode)?;
+            }
+        }
+        Ok(())
+    }
+
+    // `i := NNN`, `i := long NNNN`, `i := hex vx`, `i := bighex vx` and
+    // `i += vx`
+    fn index(&mut self) -> Result<(), AsmError> {
+        match self.next()?.text.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") | Some("bighex") => {
+                    self.keyword = self.next()?.text;
+                    let big = self.keyword == "bighex";
+                    let x = self.register()?;
+                    self.emit(match big {
+                        true => Instruction::BigFont(x),
+                        false => Instruction::Font(x),
+                    })
+                }
+                Some("long") => {
+                    self.keyword = self.next()?.text;
+                    self.check_platform(Instruction::SetIndexLong)?;
+                    self.emit_word(0xf000)?;
+                    let token = self.next()?;
+                    match self.resolve(&token.text)? {
+                        Some(value) => {
+                            let address = self.check(value, 0, 0xffff)? as u16;
+                            self.emit_word(address)
+                        }
+                        None => {
+                            self.fixup(&token, true);
+                            self.emit_word(0)
+                        }
+                    }
+                }
+                _ => {
+                    let opcode = self.address_operand(0xa000)?;
+                    self.emit_word(opcode)
+                }
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit(Instruction::AddIndex(x))
+            }
+            other => Err(self.error(ErrorKind::InvalidOperands(format!("i {}", other)))),
+        }
+    }
+
+    // Everything starting with a register, `vx := vy`, `vx += 5` and so on
+    fn assignment(&mut self, x: usize) -> Result<(), AsmError> {
+        use Instruction::*;
+
+        let op = self.next()?.text;
+        let instruction = match (op.as_str(), self.peek()) {
+            (":=", Some("random")) => {
+                self.next()?;
+                Random(x, self.byte()?)
+            }
+            (":=", Some("delay")) => {
+                self.next()?;
+                GetDelay(x)
+            }
+            (":=", Some("key")) => {
+                self.next()?;
+                WaitKey(x)
+            }
+            _ => match (op.as_str(), self.operand()?) {
+                (":=", Operand::Register(y)) => Copy(x, y),
+                (":=", Operand::Value(nn)) => Set(x, nn),
+                ("+=", Operand::Register(y)) => AddRegister(x, y),
+                ("+=", Operand::Value(nn)) => Add(x, nn),
+                ("-=", Operand::Register(y)) => Sub(x, y),
+                ("-=", Operand::Value(nn)) => Add(x, nn.wrapping_neg()),
+                ("=-", Operand::Register(y)) => SubReverse(x, y),
+                ("|=", Operand::Register(y)) => Or(x, y),
+                ("&=", Operand::Register(y)) => And(x, y),
+                ("^=", Operand::Register(y)) => Xor(x, y),
+                (">>=", Operand::Register(y)) => ShiftRight(x, y),
+                ("<<=", Operand::Register(y)) => ShiftLeft(x, y),
+                (op, _) => {
+                    return Err(self.error(ErrorKind::InvalidOperands(format!("v{:x} {}", x, op))))
+                }
+            },
+        };
+        self.emit(instruction)
+    }
+
+    // `vx == 5`, `vx != vy`, `vx key`, `vx < 10` ...
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.register()?;
+        let op = self.next()?.text;
+        let test = match op.as_str() {
+            "==" => Test::Equal,
+            "!=" => Test::NotEqual,
+            "<" => Test::Less,
+            ">" => Test::Greater,
+            "<=" => Test::LessEqual,
+            ">=" => Test::GreaterEqual,
+            "key" => Test::Key,
+            "-key" => Test::NotKey,
+            _ => return Err(self.error(ErrorKind::InvalidOperands(format!("v{:x} {}", x, op)))),
+        };
+        let operand = match test {
+            Test::Key | Test::NotKey => Operand::Register(x),
+            _ => self.operand()?,
+        };
+        Ok(Condition { x, test, operand })
+    }
+
+    // Emits code that skips the next instruction when `condition` is `when`.
+    // The ordering tests subtract in VF and look at the borrow flag.
+    fn skip(&mut self, condition: &Condition, when: bool) -> Result<(), AsmError> {
+        use Instruction::*;
+
+        let x = condition.x;
+        let (test, when) = match condition.test {
+            Test::NotEqual => (Test::Equal, !when),
+            Test::NotKey => (Test::Key, !when),
+            test => (test, when),
+        };
+        let instruction = match (test, condition.operand, when) {
+            (Test::Equal, Operand::Value(nn), true) => SkipEqual(x, nn),
+            (Test::Equal, Operand::Value(nn), false) => SkipNotEqual(x, nn),
+            (Test::Equal, Operand::Register(y), true) => SkipEqualRegister(x, y),
+            (Test::Equal, Operand::Register(y), false) => SkipNotEqualRegister(x, y),
+            (Test::Key, _, true) => SkipKey(x),
+            (Test::Key, _, false) => SkipNotKey(x),
+            (test, operand, when) => {
+                self.emit(match operand {
+                    Operand::Register(y) => Copy(VF, y),
+                    Operand::Value(nn) => Set(VF, nn),
+                })?;
+                // VF ends up 1 when there's no borrow
+                let (subtract, holds_when_borrowed) = match test {
+                    Test::Less => (SubReverse(VF, x), true),
+                    Test::GreaterEqual => (SubReverse(VF, x), false),
+                    Test::Greater => (Sub(VF, x), true),
+                    _ => (Sub(VF, x), false),
+                };
+                self.emit(subtract)?;
+                match holds_when_borrowed == when {
+                    true => SkipEqual(VF, 0),
+                    false => SkipNotEqual(VF, 0),
+                }
+            }
+        };
+        self.emit(instruction)
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        while self.peek() != Some("{") {
+            params.push(self.name()?);
+        }
+        self.next()?;
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    // Puts the macro's body in place of its name and arguments
+    fn expand(&mut self, call: &Token) -> Result<(), AsmError> {
+        if call.depth >= MAX_DEPTH {
+            return Err(self.error(ErrorKind::TooDeep));
+        }
+        let count = self.macros[&call.text].params.len();
+        let mut args = Vec::new();
+        for _ in 0..count {
+            args.push(self.next()?.text);
+        }
+        let called = &self.macros[&call.text];
+        let body: Vec<Token> = called
+            .body
+            .iter()
+            .map(|token| Token {
+                text: match called.params.iter().position(|param| *param == token.text) {
+                    Some(i) => args[i].clone(),
+                    None => token.text.clone(),
+                },
+                line: call.line,
+                depth: call.depth + 1,
+            })
+            .collect();
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    // `:calc` expressions are evaluated right to left with no precedence,
+    // like Octo does, so `2 * 3 + 1` is 8
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        self.expect("{")?;
+        let value = self.calc_expr()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_expr(&mut self) -> Result<f64, AsmError> {
+        let left = self.calc_term()?;
+        let op = match self.peek() {
+            Some(op) if BINARY.contains(&op) => op.to_string(),
+            _ => return Ok(left),
+        };
+        self.next()?;
+        let right = self.calc_expr()?;
+        let truth = |holds: bool| holds as i64 as f64;
+        let int = |value: f64| value.floor() as i64;
+        let value = match op.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "&" => (int(left) & int(right)) as f64,
+            "|" => (int(left) | int(right)) as f64,
+            "^" => (int(left) ^ int(right)) as f64,
+            "<<" => int(left).wrapping_shl(int(right) as u32) as f64,
+            ">>" => int(left).wrapping_shr(int(right) as u32) as f64,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => truth(left < right),
+            ">" => truth(left > right),
+            "<=" => truth(left <= right),
+            ">=" => truth(left >= right),
+            "==" => truth(left == right),
+            _ => truth(left != right),
+        };
+        Ok(value)
+    }
+
+    fn calc_term(&mut self) -> Result<f64, AsmError> {
+        let token = self.next()?;
+        let value = match token.text.as_str() {
+            "(" => {
+                let value = self.calc_expr()?;
+                self.expect(")")?;
+                value
+            }
+            "-" => -self.calc_term()?,
+            "~" => !(self.calc_term()?.floor() as i64) as f64,
+            "!" => (self.calc_term()? == 0.0) as i64 as f64,
+            "sin" => self.calc_term()?.sin(),
+            "cos" => self.calc_term()?.cos(),
+            "tan" => self.calc_term()?.tan(),
+            "exp" => self.calc_term()?.exp(),
+            "log" => self.calc_term()?.ln(),
+            "abs" => self.calc_term()?.abs(),
+            "sqrt" => self.calc_term()?.sqrt(),
+            "sign" => self.calc_term()?.signum(),
+            "ceil" => self.calc_term()?.ceil(),
+            "floor" => self.calc_term()?.floor(),
+            // The byte already compiled at an address
+            "@" => {
+                let address = self.calc_term()?.floor() as usize;
+                match address.checked_sub(PROGRAM_START) {
+                    Some(i) => *self.rom.get(i).unwrap_or(&0) as f64,
+                    None => 0.0,
+                }
+            }
+            "HERE" => self.here as f64,
+            "PI" => consts::PI,
+            "E" => consts::E,
+            text => match self.constants.get(text) {
+                Some(value) => *value,
+                None => match self.resolve(text)? {
+                    Some(value) => value as f64,
+                    None => return Err(self.error(ErrorKind::UnknownSymbol(text.to_string()))),
+                },
+            },
+        };
+        Ok(value)
+    }
+
+    fn name(&mut self) -> Result<String, AsmError> {
+        let token = self.next()?;
+        match token
+            .text
+            .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && self.register_number(&token.text).is_none()
+        {
+            true => Ok(token.text),
+            false => Err(self.directive(&format!("'{}' is not a valid name", token.text))),
+        }
+    }
+
+    fn define(&mut self, name: String, address: usize) -> Result<(), AsmError> {
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return Err(self.error(ErrorKind::DuplicateSymbol(name)));
+        }
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    // Constants can be redefined, `:calc` often steps one along
+    fn constant(&mut self, name: String, value: f64) -> Result<(), AsmError> {
+        if self.labels.contains_key(&name) {
+            return Err(self.error(ErrorKind::DuplicateSymbol(name)));
+        }
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    // v0-vf in either case, or an alias for one
+    fn register_number(&self, text: &str) -> Option<usize> {
+        let lower = text.to_lowercase();
+        match lower.strip_prefix('v') {
+            Some(digit) if digit.len() == 1 => usize::from_str_radix(digit, 16).ok(),
+            _ => self.aliases.get(text).copied(),
+        }
+    }
+
+    fn register(&mut self) -> Result<usize, AsmError> {
+        let token = self.next()?;
+        self.register_number(&token.text).ok_or_else(|| {
+            self.error(ErrorKind::InvalidOperands(format!(
+                "expected a register but found '{}'",
+                token.text
+            )))
+        })
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        match self.peek().and_then(|text| self.register_number(text)) {
+            Some(y) => {
+                self.next()?;
+                Ok(Operand::Register(y))
+            }
+            None => Ok(Operand::Value(self.byte()?)),
+        }
+    }
+
+    fn number(&self, text: &str) -> Result<i64, AsmError> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let lower = digits.to_lowercase();
+        let parsed = match lower.get(..2) {
+            Some("0x") => i64::from_str_radix(&lower[2..], 16),
+            Some("0b") => i64::from_str_radix(&lower[2..], 2),
+            _ => lower.parse(),
+        };
+        match (parsed, negative) {
+            (Ok(value), true) => Ok(-value),
+            (Ok(value), false) => Ok(value),
+            (Err(_), _) => Err(self.error(ErrorKind::InvalidExpression(format!(
+                "bad number '{}'",
+                text
+            )))),
+        }
+    }
+
+    // A number, constant or label that's already defined
+    fn resolve(&self, text: &str) -> Result<Option<i64>, AsmError> {
+        if text.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+            return self.number(text).map(Some);
+        }
+        let value = match self.constants.get(text) {
+            Some(value) => Some(value.floor() as i64),
+            None => self.labels.get(text).map(|&address| address as i64),
+        };
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        self.resolve(&token.text)?
+            .ok_or_else(|| self.error(ErrorKind::UnknownSymbol(token.text.clone())))
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        let value = self.value()?;
+        Ok(self.check(value, -0x80, 0xff)? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        let value = self.value()?;
+        Ok(self.check(value, 0, 0xf)? as u8)
+    }
+
+    // `base` with a 12 bit address, patched later when the label isn't
+    // defined yet
+    fn address_operand(&mut self, base: u16) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        match self.resolve(&token.text)? {
+            Some(value) => Ok(base | self.check(value, 0, 0xfff)? as u16),
+            None => {
+                self.fixup(&token, false);
+                Ok(base)
+            }
+        }
+    }
+
+    fn fixup(&mut self, token: &Token, long: bool) {
+        self.fixups.push(Fixup {
+            address: self.here,
+            name: token.text.clone(),
+            line: token.line,
+            long,
+        });
+    }
+
+    fn check(&self, value: i64, min: i64, max: i64) -> Result<i64, AsmError> {
+        match (min..=max).contains(&value) {
+            true => Ok(value),
+            false => Err(self.error(ErrorKind::OutOfRange(value))),
+        }
+    }
+
+    fn check_platform(&self, instruction: Instruction) -> Result<(), AsmError> {
+        match instruction.platform() <= self.platform {
+            true => Ok(()),
+            false => Err(self.error(ErrorKind::Unsupported(self.keyword.clone(), self.platform))),
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> Result<(), AsmError> {
+        self.check_platform(instruction)?;
+        self.emit_word(instruction.encode())
+    }
+
+    fn emit_word(&mut self, word: u16) -> Result<(), AsmError> {
+        self.emit_bytes(&word.to_be_bytes())
+    }
+
+    fn emit_bytes(&mut self, bytes: &[u8]) -> Result<(), AsmError> {
+        self.write(self.here, bytes)?;
+        self.here += bytes.len();
+        Ok(())
+    }
+
+    fn write(&mut self, address: usize, bytes: &[u8]) -> Result<(), AsmError> {
+        let start = address
+            .checked_sub(PROGRAM_START)
+            .ok_or_else(|| self.error(ErrorKind::OutOfRange(address as i64)))?;
+        let end = start + bytes.len();
+        if self.rom.len() < end {
+            self.rom.resize(end, 0);
+        }
+        self.rom[start..end].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    // Points the jump at `address` to here
+    fn patch_jump(&mut self, address: usize) -> Result<(), AsmError> {
+        let here = self.check(self.here as i64, 0, 0xfff)? as u16;
+        self.write(address, &(0x1000 | here).to_be_bytes())
+    }
+}
+
+const BINARY: [&str; 19] = [
+    "+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", ">", "<=", ">=",
+    "==", "!=",
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::octo::compile;
+    use crate::asm::{AsmError, Program};
+    use crate::platform::Platform;
+    use crate::processor::Processor;
+
+    fn chip8(source: &str) -> Result<Program, AsmError> {
+        compile(source, Platform::Chip8)
+    }
+
+    fn bytes(source: &str) -> Vec<u8> {
+        chip8(source).unwrap().bytes
+    }
+
+    // Runs a compiled program for `cycles` instructions
+    fn run(source: &str, cycles: usize) -> Processor {
+        let mut cpu = Processor::initialize();
+        cpu.load_rom(&bytes(source)).unwrap();
+        for _ in 0..cycles {
+            cpu.run_cycle([false; 16]).unwrap();
+        }
+        cpu
+    }
+
+    #[test]
+    fn instructions() {
+        let source = "
+            : main
+                clear
+                v1 := 0x0c  v2 := v1  v3 := random 0xff
+                v1 += 8  v1 += v2  v1 -= 1  v1 -= v2  v1 =- v2
+                v1 |= v2  v1 &= v2  v1 ^= v2  v1 >>= v2  v1 <<= v2
+                i := 0x260  i += v1  i := hex v1
+                v4 := delay  v4 := key  delay := v4  buzzer := v4
+                sprite v1 v2 5  bcd v3  save v3  load v3
+                jump0 0x300
+                ;
+        ";
+        assert_eq!(
+            bytes(source),
+            [
+                0x12, 0x02, 0x00, 0xe0, 0x61, 0x0c, 0x82, 0x10, 0xc3, 0xff, 0x71, 0x08, 0x81, 0x24,
+                0x71, 0xff, 0x81, 0x25, 0x81, 0x27, 0x81, 0x21, 0x81, 0x22, 0x81, 0x23, 0x81, 0x26,
+                0x81, 0x2e, 0xa2, 0x60, 0xf1, 0x1e, 0xf1, 0x29, 0xf4, 0x07, 0xf4, 0x0a, 0xf4, 0x15,
+                0xf4, 0x18, 0xd1, 0x25, 0xf3, 0x33, 0xf3, 0x55, 0xf3, 0x65, 0xb3, 0x00, 0x00, 0xee
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_and_data() {
+        let source = "
+            : sprite 0xF0 0x90 0b11110000 -1   # data before main
+            : main
+                i := sprite
+                draw
+            : draw
+                jump later
+            : later
+                :call draw
+        ";
+        let program = chip8(source).unwrap();
+        assert_eq!(
+            program.bytes,
+            [0x12, 0x06, 0xf0, 0x90, 0xf0, 0xff, 0xa2, 0x02, 0x22, 0x0a, 0x12, 0x0c, 0x22, 0x0a]
+        );
+        assert_eq!(program.symbols["main"], 0x206);
+        assert_eq!(program.symbols["later"], 0x20c);
+    }
+
+    #[test]
+    fn directives() {
+        let source = "
+            :alias x v3
+            :const SPEED 4
+            :calc TWICE { SPEED * 2 + 1 }
+            : main
+                x := SPEED
+                x += TWICE
+            :next target
+                v0 := 0
+                :byte { target - 0x200 }
+                :byte 7
+            :org 0x210
+                exit
+        ";
+        let program = compile(source, Platform::SuperChip).unwrap();
+        assert_eq!(
+            program.bytes,
+            [
+                0x12, 0x02, 0x63, 0x04, 0x73, 0x0c, 0x60, 0x00, 0x07, 0x07, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0xfd
+            ]
+        );
+        // Right to left, so 4 * (2 + 1)
+        assert_eq!(program.symbols["TWICE"], 12);
+        assert_eq!(program.symbols["target"], 0x207);
+
+        // Constants can be used as addresses before they're defined
+        let source = ": main jump0 TABLE i := TABLE :const TABLE 0x300";
+        assert_eq!(
+            compile(source, Platform::Chip8).unwrap().bytes,
+            [0x12, 0x02, 0xb3, 0x00, 0xa3, 0x00]
+        );
+    }
+
+    #[test]
+    fn macros() {
+        let source = "
+            :macro draw-at X Y { v0 := X v1 := Y sprite v0 v1 5 }
+            :macro twice { draw-at 1 2 draw-at 3 4 }
+            : main twice
+        ";
+        assert_eq!(
+            bytes(source),
+            [0x12, 0x02, 0x60, 0x01, 0x61, 0x02, 0xd0, 0x15, 0x60, 0x03, 0x61, 0x04, 0xd0, 0x15]
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        // Sums 1 to 10 into v1, counting down v0 by 2 above 5
+        let source = "
+            : main
+                v0 := 0
+                v1 := 0
+                loop
+                    v0 += 1
+                    v1 += v0
+                    if v0 == 10 then jump done
+                    if v0 > 5 begin
+                        v2 += 2
+                    else
+                        v3 += 1
+                    end
+                    while v0 != 20
+                again
+            : done
+                v4 := 1
+                loop again
+        ";
+        let cpu = run(source, 200);
+        assert_eq!(cpu.registers()[0], 10);
+        assert_eq!(cpu.registers()[1], 55);
+        assert_eq!(cpu.registers()[2], 8);
+        assert_eq!(cpu.registers()[3], 5);
+        assert_eq!(cpu.registers()[4], 1);
+    }
+
+    #[test]
+    fn comparisons() {
+        // Each test that holds sets a bit in v5
+        let source = "
+            : main
+                v0 := 3
+                v1 := 7
+                if v0 < v1 then v5 += 1
+                if v0 > v1 then v5 += 2
+                if v0 <= 3 then v5 += 4
+                if v0 >= 4 then v5 += 8
+                if v1 >= v1 then v5 += 16
+                if v1 != 7 then v5 += 32
+                if v1 key then v5 += 64
+                if v1 -key then v5 += 128
+                loop again
+        ";
+        let cpu = run(source, 40);
+        assert_eq!(cpu.registers()[5], 1 | 4 | 16 | 128);
+    }
+
+    #[test]
+    fn platforms() {
+        let source = ": main hires plane 3 i := long data scroll-up 2 : data 1";
+        assert_eq!(
+            chip8(source).unwrap_err().to_string(),
+            "<source>:1: 'hires' is not available on chip8"
+        );
+        assert_eq!(
+            compile(source, Platform::SuperChip)
+                .unwrap_err()
+                .to_string(),
+            "<source>:1: 'plane' is not available on schip"
+        );
+        assert_eq!(
+            compile(source, Platform::XoChip).unwrap().bytes,
+            [0x12, 0x02, 0x00, 0xff, 0xf3, 0x01, 0xf0, 0x00, 0x02, 0x0c, 0x00, 0xd2, 0x01]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("clear", 0, "unknown symbol 'main'"),
+            (": main\njump nowhere", 2, "unknown symbol 'nowhere'"),
+            (": main\n: main", 2, "'main' is already defined"),
+            (": main v0 := 256", 1, "256 is out of range"),
+            (": main\nv0 := SIZE", 2, "unknown symbol 'SIZE'"),
+            (": main\nloop", 2, "block is never closed"),
+            (": main again", 1, "again without loop"),
+            (": main\n:org 0x1200\nloop again", 3, "4608 is out of range"),
+            (": main end", 1, "end without if ... begin"),
+            (
+                ": main\nif v0 == 1 clear",
+                2,
+                "expected 'then' or 'begin' but found 'clear'",
+            ),
+            (": main va %= v1", 1, "invalid operands in 'va %='"),
+            (
+                ":macro m { m } : main m",
+                1,
+                "includes, macros or constants nested too deeply",
+            ),
+            (": main :unpack", 1, "unknown instruction ':unpack'"),
+        ];
+        for (source, line, message) in cases.iter() {
+            let err = chip8(source).unwrap_err();
+            assert_eq!(err.line, *line, "{}", source);
+            assert_eq!(err.kind.to_string(), *message, "{}", source);
+        }
+    }
+}
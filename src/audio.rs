@@ -0,0 +1,329 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{platform::Platform, processor::Processor, timer::TIMER_HZ};
+
+pub const SAMPLE_RATE: u32 = 44_100;
+// Pitch of the buzzer on platforms without XO-CHIP audio patterns
+pub const DEFAULT_TONE: f64 = 440.0;
+// Most samples a wav file can hold, its sizes are 32 bit and the RIFF size
+// also counts 36 bytes of header
+const MAX_WAV_SAMPLES: u32 = (u32::MAX - 36) / 2;
+
+// Somewhere for generated audio to go, samples are mono in the range -1..1
+pub trait AudioSink {
+    fn play(&mut self, samples: &[f32]);
+}
+
+// Keeps every sample it is given, for checking the output in tests
+#[derive(Default)]
+pub struct CaptureSink {
+    pub samples: Vec<f32>,
+}
+
+impl AudioSink for CaptureSink {
+    fn play(&mut self, samples: &[f32]) {
+        self.samples.extend_from_slice(samples);
+    }
+}
+
+// Writes everything played to a 16 bit mono wav file, the header is completed
+// when the sink is dropped
+pub struct WavSink<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    // Number of samples written so far
+    samples: u32,
+}
+
+impl WavSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+        WavSink::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        // The sizes are filled in by `finish`
+        write_wav_header(&mut writer, sample_rate, 0)?;
+        Ok(WavSink {
+            writer,
+            sample_rate,
+            samples: 0,
+        })
+    }
+
+    // Fills in the header sizes for everything written so far
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_wav_header(&mut self.writer, self.sample_rate, self.samples)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Seek> Drop for WavSink<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn play(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            // Once the file is full the rest of the recording is lost
+            if self.samples >= MAX_WAV_SAMPLES {
+                break;
+            }
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            // There's nowhere to report a failed write from the event loop,
+            // drop the sample rather than stopping the emulator
+            if self.writer.write_all(&sample.to_le_bytes()).is_ok() {
+                self.samples += 1;
+            }
+        }
+    }
+}
+
+fn write_wav_header<W: Write>(writer: &mut W, sample_rate: u32, samples: u32) -> io::Result<()> {
+    let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "too large for a wav file");
+    let data_size = match samples <= MAX_WAV_SAMPLES {
+        true => samples * 2,
+        false => return Err(too_large()),
+    };
+    let byte_rate = sample_rate.checked_mul(2).ok_or_else(too_large)?;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?; // Format chunk size
+    writer.write_all(&1u16.to_le_bytes())?; // PCM
+    writer.write_all(&1u16.to_le_bytes())?; // Mono
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&byte_rate.to_le_bytes())?; // Bytes per second
+    writer.write_all(&2u16.to_le_bytes())?; // Bytes per sample
+    writer.write_all(&16u16.to_le_bytes())?; // Bits per sample
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())
+}
+
+// Rate in bits per second the XO-CHIP audio pattern is played back at
+pub fn playback_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+}
+
+// Turns the processor's sound state into samples, one frame at a time
+pub struct Audio {
+    sample_rate: u32,
+    // Frequency of the square wave buzzer
+    tone: f64,
+    volume: f32,
+    muted: bool,
+    // Position in the 128 bit pattern (or in the buzzer's square wave), kept
+    // between frames so the waveform doesn't click
+    position: f64,
+    // Samples owed to the next frame when `sample_rate` isn't a multiple of 60
+    remainder: u32,
+    buffer: Vec<f32>,
+}
+
+impl Audio {
+    pub fn new(sample_rate: u32) -> Self {
+        Audio {
+            sample_rate,
+            tone: DEFAULT_TONE,
+            volume: 1.0,
+            muted: false,
+            position: 0.0,
+            remainder: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn set_tone(&mut self, tone: f64) {
+        self.tone = tone;
+    }
+
+    // From 0.0 (silent) to 1.0 (full scale)
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    // Number of samples in the next frame
+    fn samples_per_frame(&mut self) -> usize {
+        self.remainder += self.sample_rate;
+        let samples = self.remainder / TIMER_HZ;
+        self.remainder %= TIMER_HZ;
+        samples as usize
+    }
+
+    // Renders one 60Hz frame of audio into `sink`. Sound plays for as long as
+    // the sound timer is running: the pattern on XO-CHIP, a square wave buzzer
+    // everywhere else.
+    pub fn render_frame(&mut self, cpu: &Processor, sink: &mut dyn AudioSink) {
+        let samples = self.samples_per_frame();
+        self.buffer.clear();
+
+        if cpu.sound_timer() == 0 || self.muted {
+            self.buffer.resize(samples, 0.0);
+        } else if cpu.platform() >= Platform::XoChip {
+            let pattern = cpu.audio_pattern();
+            let step = playback_rate(cpu.pitch()) / self.sample_rate as f64;
+            for _ in 0..samples {
+                let bit = self.position as usize % 128;
+                let sample = match pattern[bit / 8] >> (7 - bit % 8) & 0x01 {
+                    1 => self.volume,
+                    _ => -self.volume,
+                };
+                self.buffer.push(sample);
+                self.position = (self.position + step) % 128.0;
+            }
+        } else {
+            let step = self.tone / self.sample_rate as f64;
+            for _ in 0..samples {
+                let sample = match self.position < 0.5 {
+                    true => self.volume,
+                    false => -self.volume,
+                };
+                self.buffer.push(sample);
+                self.position = (self.position + step) % 1.0;
+            }
+        }
+
+        sink.play(&self.buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::audio::{
+        playback_rate, write_wav_header, Audio, AudioSink, CaptureSink, WavSink, MAX_WAV_SAMPLES,
+    };
+    use crate::{platform::Platform, processor::Processor};
+
+    #[test]
+    fn pitch() {
+        assert_eq!(playback_rate(64), 4000.0);
+        assert_eq!(playback_rate(112), 8000.0);
+        assert_eq!(playback_rate(16), 2000.0);
+    }
+
+    #[test]
+    fn render_pattern() {
+        let mut cpu = Processor::initialize();
+        cpu.set_platform(Platform::XoChip);
+        // 0xa000: I = 0x000, load the pattern from the start of the font
+        // 0x6104, 0xf118: run the sound timer for 4 frames
+        cpu.load_rom(&[0xa0, 0x00, 0xf0, 0x02, 0x61, 0x04, 0xf1, 0x18])
+            .unwrap();
+        for _ in 0..4 {
+            cpu.run_cycle([false; 16]).unwrap();
+        }
+
+        // One bit of the pattern per sample
+        let mut audio = Audio::new(4000);
+        let mut sink = CaptureSink::default();
+        audio.render_frame(&cpu, &mut sink);
+        assert_eq!(sink.samples.len(), 66);
+        // First font byte is 0xf0
+        assert_eq!(
+            sink.samples[..8],
+            [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]
+        );
+
+        // Silent once the timer stops
+        for _ in 0..4 {
+            cpu.tick_timers();
+        }
+        audio.render_frame(&cpu, &mut sink);
+        assert_eq!(sink.samples.len(), 133);
+        assert!(sink.samples[66..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn buzzer_timing() {
+        let mut cpu = Processor::initialize();
+        // 0x6103, 0xf118: run the sound timer for 3 frames
+        cpu.load_rom(&[0x61, 0x03, 0xf1, 0x18]).unwrap();
+        cpu.run_cycle([false; 16]).unwrap();
+        cpu.run_cycle([false; 16]).unwrap();
+
+        let mut audio = Audio::new(600);
+        audio.set_tone(100.0);
+        let mut sink = CaptureSink::default();
+        for _ in 0..5 {
+            audio.render_frame(&cpu, &mut sink);
+            cpu.tick_timers();
+        }
+
+        // 10 samples per frame, beeping for the first 3 frames
+        assert_eq!(sink.samples.len(), 50);
+        assert!(sink.samples[..30].iter().all(|&s| s != 0.0));
+        assert!(sink.samples[30..].iter().all(|&s| s == 0.0));
+        // 6 samples per square wave cycle
+        assert_eq!(sink.samples[..6], [1.0, 1.0, 1.0, -1.0, -1.0, -1.0]);
+    }
+
+    #[test]
+    fn mute_and_volume() {
+        let mut cpu = Processor::initialize();
+        cpu.load_rom(&[0x61, 0x03, 0xf1, 0x18]).unwrap();
+        cpu.run_cycle([false; 16]).unwrap();
+        cpu.run_cycle([false; 16]).unwrap();
+
+        let mut audio = Audio::new(600);
+        let mut sink = CaptureSink::default();
+        audio.set_volume(0.5);
+        audio.render_frame(&cpu, &mut sink);
+        assert!(sink.samples.iter().all(|&s| s.abs() == 0.5));
+
+        audio.toggle_mute();
+        audio.render_frame(&cpu, &mut sink);
+        assert!(sink.samples[10..].iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn wav_file() {
+        let mut wav = Cursor::new(Vec::new());
+        let mut sink = WavSink::new(&mut wav, 8000).unwrap();
+        sink.play(&[0.0, 1.0, -1.0]);
+        drop(sink);
+        let wav = wav.into_inner();
+
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(wav[..4], *b"RIFF");
+        assert_eq!(wav[4..8], 42u32.to_le_bytes());
+        assert_eq!(wav[24..28], 8000u32.to_le_bytes());
+        assert_eq!(wav[40..44], 6u32.to_le_bytes());
+        assert_eq!(wav[44..], [0x00, 0x00, 0xff, 0x7f, 0x01, 0x80]);
+    }
+
+    #[test]
+    fn full_wav_file() {
+        let mut wav = Cursor::new(Vec::new());
+        let mut sink = WavSink::new(&mut wav, 8000).unwrap();
+        sink.samples = MAX_WAV_SAMPLES - 1;
+        sink.play(&[1.0, 1.0, 1.0]);
+        assert_eq!(sink.samples, MAX_WAV_SAMPLES);
+        drop(sink);
+        let wav = wav.into_inner();
+
+        assert_eq!(wav.len(), 44 + 2);
+        assert_eq!(wav[4..8], (u32::MAX - 1).to_le_bytes());
+        assert!(write_wav_header(&mut Vec::new(), 8000, MAX_WAV_SAMPLES + 1).is_err());
+        assert!(write_wav_header(&mut Vec::new(), u32::MAX, 0).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use std::{env, fs, path::PathBuf, process};
+
+use chip_8::{
+    asm,
+    config::{parse_path, parse_with, ConfigError},
+    platform::Platform,
+    processor::Processor,
+};
+
+const USAGE: &str = "Usage: chip8-asm [OPTIONS] <SOURCE>
+
+Assembles a rom from the mnemonics chip8-disasm prints, or compiles one from
+Octo when SOURCE ends in .8o, and writes the value of every label and
+constant to a .sym file next to it.
+
+Options:
+    -m, --platform <NAME>
+                         Instruction set: chip8 (default), schip or xochip
+    -o, --out <PATH>     Where to write the rom (default SOURCE with a .ch8
+                         extension)
+    -h, --help           Print this message";
+
+struct Options {
+    source: PathBuf,
+    platform: Platform,
+    out: PathBuf,
+}
+
+impl Options {
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, ConfigError> {
+        let mut source = None;
+        let mut platform = Platform::default();
+        let mut out = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Err(ConfigError::Help),
+                "-m" | "--platform" => {
+                    platform = parse_with(&arg, args.next(), Platform::from_name)?
+                }
+                "-o" | "--out" => out = Some(parse_path(&arg, args.next())?),
+                _ if arg.starts_with('-') => return Err(ConfigError::UnknownOption(arg)),
+                _ => source = Some(PathBuf::from(arg)),
+            }
+        }
+
+        let source = source.ok_or(ConfigError::MissingSource)?;
+        Ok(Options {
+            out: out.unwrap_or_else(|| source.with_extension("ch8")),
+            source,
+            platform,
+        })
+    }
+}
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(ConfigError::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let program = match options.source.extension().and_then(|ext| ext.to_str()) {
+        Some("8o") => asm::octo::compile_file(&options.source, options.platform),
+        _ => asm::assemble_file(&options.source, options.platform),
+    };
+    let program = match program {
+        Ok(program) => program,
+        Err(err) => fail(&err.to_string()),
+    };
+    // Loading it checks it fits in the platform's ram
+    let mut cpu = Processor::initialize();
+    cpu.set_platform(options.platform);
+    if let Err(err) = cpu.load_rom(&program.bytes) {
+        fail(&format!("{}: {}", options.source.display(), err));
+    }
+
+    let symbols = options.out.with_extension("sym");
+    for (path, contents) in [
+        (&options.out, program.bytes.clone()),
+        (&symbols, program.symbol_file().into_bytes()),
+    ]
+    .iter()
+    {
+        if let Err(err) = fs::write(path, contents) {
+            fail(&format!("{}: {}", path.display(), err));
+        }
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use chip_8::{config::ConfigError, platform::Platform};
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn options() {
+        let options = Options::parse(args(&["games/pong.asm"])).unwrap();
+        assert_eq!(options.source.to_str(), Some("games/pong.asm"));
+        assert_eq!(options.out.to_str(), Some("games/pong.ch8"));
+        assert_eq!(options.platform, Platform::Chip8);
+
+        let options = Options::parse(args(&["-o", "out.rom", "-m", "schip", "pong.asm"])).unwrap();
+        assert_eq!(options.out.to_str(), Some("out.rom"));
+        assert_eq!(options.platform, Platform::SuperChip);
+
+        let options = Options::parse(args(&["pong.8o"])).unwrap();
+        assert_eq!(options.out.to_str(), Some("pong.ch8"));
+
+        assert!(matches!(
+            Options::parse(args(&["--fast", "pong.asm"])),
+            Err(ConfigError::UnknownOption(_))
+        ));
+        assert!(matches!(
+            Options::parse(args(&[])),
+            Err(ConfigError::MissingSource)
+        ));
+    }
+}
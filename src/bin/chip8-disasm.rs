@@ -0,0 +1,127 @@
+use std::{env, fs, path::PathBuf, process};
+
+use chip_8::{
+    config::{parse_path, parse_with, ConfigError},
+    disasm::Disassembly,
+    platform::Platform,
+    processor::Processor,
+};
+
+const USAGE: &str = "Usage: chip8-disasm [OPTIONS] <ROM>
+
+Prints an assembly listing of a rom. Code is found by following jumps, calls
+and skips from 0x200, everything else is listed as data.
+
+Options:
+    -m, --platform <NAME>
+                         Instruction set: chip8 (default), schip or xochip
+    -o, --out <PATH>     Write the listing to a file instead of stdout
+    -h, --help           Print this message";
+
+struct Options {
+    rom: PathBuf,
+    platform: Platform,
+    out: Option<PathBuf>,
+}
+
+impl Options {
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, ConfigError> {
+        let mut rom = None;
+        let mut platform = Platform::default();
+        let mut out = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Err(ConfigError::Help),
+                "-m" | "--platform" => {
+                    platform = parse_with(&arg, args.next(), Platform::from_name)?
+                }
+                "-o" | "--out" => out = Some(parse_path(&arg, args.next())?),
+                _ if arg.starts_with('-') => return Err(ConfigError::UnknownOption(arg)),
+                _ => rom = Some(PathBuf::from(arg)),
+            }
+        }
+
+        Ok(Options {
+            rom: rom.ok_or(ConfigError::MissingRom)?,
+            platform,
+            out,
+        })
+    }
+}
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(ConfigError::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let rom = match fs::read(&options.rom) {
+        Ok(rom) => rom,
+        Err(err) => fail(&format!(
+            "{}: could not read rom: {}",
+            options.rom.display(),
+            err
+        )),
+    };
+    // Loading it checks it fits in the platform's ram
+    let mut cpu = Processor::initialize();
+    cpu.set_platform(options.platform);
+    if let Err(err) = cpu.load_rom(&rom) {
+        fail(&format!("{}: {}", options.rom.display(), err));
+    }
+
+    let listing = Disassembly::new(&rom, options.platform).listing();
+    match &options.out {
+        Some(path) => {
+            if let Err(err) = fs::write(path, listing) {
+                fail(&format!("{}: {}", path.display(), err));
+            }
+        }
+        None => print!("{}", listing),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Options;
+    use chip_8::{config::ConfigError, platform::Platform};
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn options() {
+        let options = Options::parse(args(&["-m", "xochip", "-o", "out.asm", "rom.ch8"])).unwrap();
+        assert_eq!(options.rom.to_str(), Some("rom.ch8"));
+        assert_eq!(options.platform, Platform::XoChip);
+        assert_eq!(options.out.unwrap().to_str(), Some("out.asm"));
+
+        let options = Options::parse(args(&["rom.ch8"])).unwrap();
+        assert_eq!(options.platform, Platform::Chip8);
+        assert!(options.out.is_none());
+
+        assert!(matches!(
+            Options::parse(args(&["-m", "nes", "rom.ch8"])),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            Options::parse(args(&[])),
+            Err(ConfigError::MissingRom)
+        ));
+    }
+}
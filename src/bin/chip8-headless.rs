@@ -0,0 +1,347 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+    process,
+};
+
+use chip_8::{
+    config::{parse_path, parse_value, parse_with, ConfigError, DEFAULT_SPEED},
+    dump,
+    emulator::Emulator,
+    movie::{Movie, Playback},
+    palette::Palette,
+    platform::Platform,
+    processor::{Chip8Error, Processor},
+    quirks::Quirks,
+    random::DEFAULT_SEED,
+    timer::{TimerSource, TIMER_HZ},
+};
+
+const DEFAULT_FRAMES: u32 = 60;
+
+const USAGE: &str = "Usage: chip8-headless [OPTIONS] <ROM>
+
+Runs a rom without a window and dumps the screen and registers at the end.
+
+Options:
+    -f, --frames <N>     Frames to run, 60 per second (default 60, or the
+                         whole movie with --movie)
+    -c, --cycles <N>     Run exactly N instructions instead of whole frames
+    -s, --speed <HZ>     Instructions executed per second (default 700)
+    -m, --platform <NAME>
+                         Instruction set: chip8 (default), schip or xochip
+    -q, --quirks <NAME>  Interpreter behavior to emulate: modern, vip, chip48
+                         or schip (default depends on the platform)
+        --seed <N>       Seed for the random number generator (default fixed)
+    -k, --key <K>@<FROM>[-<TO>]
+                         Hold hex key K from frame FROM until frame TO, or
+                         for just one frame, can be repeated
+        --movie <PATH>   Take the keys, platform and seed from a movie
+    -o, --out <PATH>     Write the screen to a .pbm or .png file
+        --ascii          Print the screen as ascii art
+    -p, --palette <NAME> Colors for png output (default classic)
+    -x, --scale <N>      Size of a chip-8 pixel in png output (default 1)
+    -h, --help           Print this message";
+
+// A key held down for frames `from..to`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KeyPress {
+    key: usize,
+    from: u32,
+    to: u32,
+}
+
+impl KeyPress {
+    // Parses `K@FROM` or `K@FROM-TO`
+    fn parse(spec: &str) -> Option<KeyPress> {
+        let mut parts = spec.splitn(2, '@');
+        let key = usize::from_str_radix(parts.next()?, 16)
+            .ok()
+            .filter(|&key| key < 16)?;
+        let mut frames = parts.next()?.splitn(2, '-');
+        let from = frames.next()?.parse().ok()?;
+        let to = match frames.next() {
+            Some(to) => to.parse().ok().filter(|&to| to > from)?,
+            None => from + 1,
+        };
+        Some(KeyPress { key, from, to })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImageFormat {
+    Pbm,
+    Png,
+}
+
+struct Options {
+    rom: PathBuf,
+    frames: Option<u32>,
+    cycles: Option<u64>,
+    speed: u32,
+    platform: Platform,
+    quirks: Quirks,
+    seed: u64,
+    presses: Vec<KeyPress>,
+    movie: Option<PathBuf>,
+    out: Option<(PathBuf, ImageFormat)>,
+    ascii: bool,
+    palette: Palette,
+    scale: usize,
+}
+
+impl Options {
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, ConfigError> {
+        let mut rom = None;
+        let mut frames = None;
+        let mut cycles = None;
+        let mut speed = DEFAULT_SPEED;
+        let mut platform = Platform::default();
+        let mut quirks = None;
+        let mut seed = DEFAULT_SEED;
+        let mut presses = Vec::new();
+        let mut movie = None;
+        let mut out = None;
+        let mut ascii = false;
+        let mut palette = Palette::default();
+        let mut scale = 1;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Err(ConfigError::Help),
+                "-f" | "--frames" => frames = Some(parse_value(&arg, args.next())?),
+                "-c" | "--cycles" => cycles = Some(parse_value(&arg, args.next())?),
+                "-s" | "--speed" => speed = parse_value(&arg, args.next())?,
+                "-m" | "--platform" => {
+                    platform = parse_with(&arg, args.next(), Platform::from_name)?
+                }
+                "-q" | "--quirks" => quirks = Some(parse_with(&arg, args.next(), Quirks::preset)?),
+                "--seed" => seed = parse_with(&arg, args.next(), |val| val.parse().ok())?,
+                "-k" | "--key" => presses.push(parse_with(&arg, args.next(), KeyPress::parse)?),
+                "--movie" => movie = Some(parse_path(&arg, args.next())?),
+                "-o" | "--out" => {
+                    out = Some(parse_with(&arg, args.next(), |val| {
+                        let path = PathBuf::from(val);
+                        let format = match path.extension()?.to_str()? {
+                            "pbm" => ImageFormat::Pbm,
+                            "png" => ImageFormat::Png,
+                            _ => return None,
+                        };
+                        Some((path, format))
+                    })?)
+                }
+                "--ascii" => ascii = true,
+                "-p" | "--palette" => palette = parse_with(&arg, args.next(), Palette::preset)?,
+                "-x" | "--scale" => scale = parse_value(&arg, args.next())?,
+                _ if arg.starts_with('-') => return Err(ConfigError::UnknownOption(arg)),
+                _ => rom = Some(PathBuf::from(arg)),
+            }
+        }
+
+        Ok(Options {
+            rom: rom.ok_or(ConfigError::MissingRom)?,
+            frames,
+            cycles,
+            speed,
+            platform,
+            quirks: quirks.unwrap_or_else(|| platform.quirks()),
+            seed,
+            presses,
+            movie,
+            out,
+            ascii,
+            palette,
+            scale,
+        })
+    }
+
+    // Keypad state during `frame`
+    fn keys(&self, frame: u32) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for press in self.presses.iter() {
+            if (press.from..press.to).contains(&frame) {
+                keys[press.key] = true;
+            }
+        }
+        keys
+    }
+}
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(ConfigError::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let movie = options.movie.as_ref().map(|path| match Movie::load(path) {
+        Ok(movie) => movie,
+        Err(err) => fail(&format!("{}: {}", path.display(), err)),
+    });
+
+    let mut cpu = Processor::initialize();
+    cpu.set_platform(
+        movie
+            .as_ref()
+            .map_or(options.platform, |movie| movie.platform),
+    );
+    cpu.set_quirks(options.quirks);
+    cpu.set_seed(options.seed);
+    if let Err(err) = cpu.load_rom_file(&options.rom) {
+        fail(&format!("{}: {}", options.rom.display(), err));
+    }
+    if let Some(movie) = &movie {
+        if options.cycles.is_some() {
+            fail("--movie runs whole frames and can't be used with --cycles");
+        }
+        if let Err(err) = movie.start(&mut cpu) {
+            fail(&err.to_string());
+        }
+    }
+
+    let speed = movie.as_ref().map_or(options.speed, |movie| movie.speed);
+    let mut emulator = Emulator::new(cpu, speed, TimerSource::Cycles { cpu_hz: speed });
+    let frames = options
+        .frames
+        .or_else(|| movie.as_ref().map(Movie::frame_count))
+        .unwrap_or(DEFAULT_FRAMES);
+    let result = run(
+        &mut emulator,
+        &options,
+        speed,
+        frames,
+        movie.map(Playback::new),
+    );
+
+    write_screen(&emulator.cpu, &options);
+    print!("{}", dump::registers(&emulator.cpu));
+    if let Err(err) = result {
+        fail(&err);
+    }
+}
+
+// Runs the rom for as long as the options say or until it exits
+fn run(
+    emulator: &mut Emulator,
+    options: &Options,
+    speed: u32,
+    frames: u32,
+    mut playback: Option<Playback>,
+) -> Result<(), String> {
+    if let Some(cycles) = options.cycles {
+        for cycle in 0..cycles {
+            let frame = (cycle * TIMER_HZ as u64 / speed as u64) as u32;
+            emulator.step(options.keys(frame)).map_err(error)?;
+            if emulator.cpu.has_exited() {
+                break;
+            }
+        }
+        return Ok(());
+    }
+
+    for frame in 0..frames {
+        // The movie's keys take over from --key until it runs out
+        let movie_keys = playback.as_ref().and_then(Playback::keys);
+        let keys = movie_keys.unwrap_or_else(|| options.keys(frame));
+        emulator.run_frame(keys).map_err(error)?;
+        if let (Some(movie), Some(_)) = (&mut playback, movie_keys) {
+            movie
+                .advance(&emulator.cpu)
+                .map_err(|err| err.to_string())?;
+        }
+        if emulator.cpu.has_exited() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn error(err: Chip8Error) -> String {
+    err.to_string()
+}
+
+fn write_screen(cpu: &Processor, options: &Options) {
+    if options.ascii {
+        print!("{}", dump::ascii(cpu));
+    }
+    let (path, format) = match &options.out {
+        Some(out) => out,
+        None => return,
+    };
+    let result = match format {
+        ImageFormat::Pbm => fs::write(path, dump::pbm(cpu)),
+        ImageFormat::Png => File::create(path).and_then(|file| {
+            dump::write_png(BufWriter::new(file), cpu, &options.palette, options.scale)
+        }),
+    };
+    if let Err(err) = result {
+        fail(&format!("{}: {}", path.display(), err));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyPress, Options};
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn key_presses() {
+        assert_eq!(
+            KeyPress::parse("a@10"),
+            Some(KeyPress {
+                key: 0xa,
+                from: 10,
+                to: 11
+            })
+        );
+        assert_eq!(
+            KeyPress::parse("5@3-30"),
+            Some(KeyPress {
+                key: 5,
+                from: 3,
+                to: 30
+            })
+        );
+        assert_eq!(KeyPress::parse("10@3"), None);
+        assert_eq!(KeyPress::parse("5@30-3"), None);
+        assert_eq!(KeyPress::parse("5"), None);
+
+        let options = Options::parse(args(&["-k", "1@0-2", "--key", "f@1", "rom.ch8"])).unwrap();
+        assert!(options.keys(0)[1]);
+        assert!(options.keys(1)[0xf]);
+        assert_eq!(options.keys(2), [false; 16]);
+    }
+
+    #[test]
+    fn outputs() {
+        let options = Options::parse(args(&["-o", "screen.png", "rom.ch8"])).unwrap();
+        assert!(options.out.is_some());
+        assert!(Options::parse(args(&["-o", "screen.bmp", "rom.ch8"])).is_err());
+    }
+
+    #[test]
+    fn frames() {
+        // Left unset so a movie can run to its end
+        let options = Options::parse(args(&["--movie", "run.movie", "rom.ch8"])).unwrap();
+        assert_eq!(options.frames, None);
+        let options = Options::parse(args(&["-f", "120", "rom.ch8"])).unwrap();
+        assert_eq!(options.frames, Some(120));
+    }
+}
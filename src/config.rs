@@ -0,0 +1,340 @@
+use std::{env, fmt, path::PathBuf};
+
+use crate::{
+    audio::DEFAULT_TONE,
+    palette::{self, Palette},
+    platform::Platform,
+    quirks::Quirks,
+    rewind::DEFAULT_REWIND_SECONDS,
+    timer::TimerSource,
+    PIXEL_SCALE,
+};
+
+pub const DEFAULT_SPEED: u32 = 700;
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+pub const USAGE: &str = "Usage: chip-8 [OPTIONS] <ROM>
+
+Options:
+    -s, --speed <HZ>     Instructions executed per second (default 700)
+    -x, --scale <N>      Size of a chip-8 pixel on screen (default 10)
+    -t, --timers <MODE>  Tick the timers from executed `cycles` (default) or
+                         from `realtime`
+    -m, --platform <NAME>
+                         Instruction set: chip8 (default), schip or xochip
+    -q, --quirks <NAME>  Interpreter behavior to emulate: modern, vip, chip48
+                         or schip (default depends on the platform)
+    -p, --palette <NAME> Screen colors: classic (default), green, amber, lcd
+                         or high-contrast
+        --fg <RRGGBB>    Override the foreground color
+        --bg <RRGGBB>    Override the background color
+        --seed <N>       Seed for the random number generator (default random)
+        --tone <HZ>      Pitch of the buzzer (default 440)
+        --volume <0-100> Sound volume in percent (default 25)
+        --mute           Start with the sound muted, M toggles it
+        --wav <PATH>     Record the sound to a wav file instead of playing it
+        --force-state    Load save states made while running a different rom
+        --rewind <SECS>  How far back holding Backspace can rewind (default 10)
+        --load-state <PATH>
+                         Start from a save state
+        --record <PATH>  Record the keypad to a movie file, starting from
+                         power on, the loaded state or the end of --play
+        --play <PATH>    Play back a movie, then hand over to the keyboard
+    -d, --debug          Start paused with a debugger reading commands from
+                         stdin, type help for a list
+        --gdb <PORT>     Wait for gdb to connect to PORT on localhost and
+                         debug the rom through it
+    -h, --help           Print this message
+
+Shift+F1-F9 saves the state to a numbered slot next to the rom, F1-F9 loads it.";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Help,
+    MissingRom,
+    MissingSource,
+    MissingValue(String),
+    InvalidValue(String, String),
+    UnknownOption(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Help => write!(f, "help requested"),
+            ConfigError::MissingRom => write!(f, "no rom given"),
+            ConfigError::MissingSource => write!(f, "no source given"),
+            ConfigError::MissingValue(opt) => write!(f, "{} needs a value", opt),
+            ConfigError::InvalidValue(opt, val) => {
+                write!(f, "invalid value '{}' for {}", val, opt)
+            }
+            ConfigError::UnknownOption(opt) => write!(f, "unknown option {}", opt),
+        }
+    }
+}
+
+pub struct Config {
+    pub rom: PathBuf,
+    // Instructions per second
+    pub speed: u32,
+    pub scale: usize,
+    pub timers: TimerSource,
+    pub palette: Palette,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    // None picks a new seed every run
+    pub seed: Option<u64>,
+    pub tone: f64,
+    // From 0.0 to 1.0
+    pub volume: f32,
+    pub mute: bool,
+    pub wav: Option<PathBuf>,
+    // Load save states even if they were made for another rom
+    pub force_state: bool,
+    // Length of the rewind buffer in seconds
+    pub rewind: u32,
+    pub load_state: Option<PathBuf>,
+    // Movie to write on exit
+    pub record: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub debug: bool,
+    // Port to serve the gdb remote protocol on
+    pub gdb: Option<u16>,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Config, ConfigError> {
+        Config::parse(env::args().skip(1))
+    }
+
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Config, ConfigError> {
+        let mut rom = None;
+        let mut speed = DEFAULT_SPEED;
+        let mut scale = PIXEL_SCALE;
+        let mut realtime = false;
+        let mut palette = Palette::default();
+        let mut platform = Platform::default();
+        let mut quirks = None;
+        let mut seed = None;
+        let mut tone = DEFAULT_TONE;
+        let mut volume = DEFAULT_VOLUME;
+        let mut mute = false;
+        let mut wav = None;
+        let mut force_state = false;
+        let mut rewind = DEFAULT_REWIND_SECONDS;
+        let mut load_state = None;
+        let mut record = None;
+        let mut play = None;
+        let mut debug = false;
+        let mut gdb = None;
+        let mut foreground = None;
+        let mut background = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Err(ConfigError::Help),
+                "-s" | "--speed" => speed = parse_value(&arg, args.next())?,
+                "-x" | "--scale" => scale = parse_value(&arg, args.next())?,
+                "-t" | "--timers" => {
+                    realtime = parse_with(&arg, args.next(), |mode| match mode {
+                        "cycles" => Some(false),
+                        "realtime" => Some(true),
+                        _ => None,
+                    })?
+                }
+                "-p" | "--palette" => palette = parse_with(&arg, args.next(), Palette::preset)?,
+                "-m" | "--platform" => {
+                    platform = parse_with(&arg, args.next(), Platform::from_name)?
+                }
+                "-q" | "--quirks" => quirks = Some(parse_with(&arg, args.next(), Quirks::preset)?),
+                "--fg" => foreground = Some(parse_with(&arg, args.next(), palette::parse_color)?),
+                "--bg" => background = Some(parse_with(&arg, args.next(), palette::parse_color)?),
+                "--seed" => seed = Some(parse_with(&arg, args.next(), |val| val.parse().ok())?),
+                "--tone" => tone = parse_value(&arg, args.next())?,
+                "--volume" => {
+                    volume = parse_with(&arg, args.next(), |val| match val.parse::<u8>() {
+                        Ok(percent) if percent <= 100 => Some(percent as f32 / 100.0),
+                        _ => None,
+                    })?
+                }
+                "--mute" => mute = true,
+                "--force-state" => force_state = true,
+                "--rewind" => rewind = parse_value(&arg, args.next())?,
+                "--wav" => wav = Some(parse_path(&arg, args.next())?),
+                "--load-state" => load_state = Some(parse_path(&arg, args.next())?),
+                "--record" => record = Some(parse_path(&arg, args.next())?),
+                "--play" => play = Some(parse_path(&arg, args.next())?),
+                "-d" | "--debug" => debug = true,
+                "--gdb" => gdb = Some(parse_value(&arg, args.next())?),
+                _ if arg.starts_with('-') => return Err(ConfigError::UnknownOption(arg)),
+                _ => rom = Some(PathBuf::from(arg)),
+            }
+        }
+
+        let timers = match realtime {
+            true => TimerSource::RealTime,
+            false => TimerSource::Cycles { cpu_hz: speed },
+        };
+
+        palette.foreground = foreground.unwrap_or(palette.foreground);
+        palette.background = background.unwrap_or(palette.background);
+
+        Ok(Config {
+            rom: rom.ok_or(ConfigError::MissingRom)?,
+            speed,
+            scale,
+            timers,
+            palette,
+            platform,
+            quirks: quirks.unwrap_or_else(|| platform.quirks()),
+            seed,
+            tone,
+            volume,
+            mute,
+            wav,
+            force_state,
+            rewind,
+            load_state,
+            record,
+            play,
+            debug,
+            gdb,
+        })
+    }
+}
+
+// Parses a positive number following an option
+pub fn parse_value<T>(opt: &str, value: Option<String>) -> Result<T, ConfigError>
+where
+    T: std::str::FromStr + PartialOrd + Default,
+{
+    let value = value.ok_or_else(|| ConfigError::MissingValue(opt.to_string()))?;
+    match value.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(ConfigError::InvalidValue(opt.to_string(), value)),
+    }
+}
+
+// Parses the value following an option with `parse`, e.g. a preset lookup
+pub fn parse_with<T, F>(opt: &str, value: Option<String>, parse: F) -> Result<T, ConfigError>
+where
+    F: Fn(&str) -> Option<T>,
+{
+    let value = value.ok_or_else(|| ConfigError::MissingValue(opt.to_string()))?;
+    parse(&value).ok_or_else(|| ConfigError::InvalidValue(opt.to_string(), value))
+}
+
+pub fn parse_path(opt: &str, value: Option<String>) -> Result<PathBuf, ConfigError> {
+    parse_with(opt, value, |val| Some(PathBuf::from(val)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{Config, ConfigError, DEFAULT_SPEED};
+    use crate::palette::{self, Palette};
+    use crate::platform::Platform;
+    use crate::quirks::{self, Quirks};
+    use crate::rewind::DEFAULT_REWIND_SECONDS;
+    use crate::timer::TimerSource;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        let config = Config::parse(args(&["-s", "1000", "--scale", "5", "pong.ch8"])).unwrap();
+        assert_eq!(config.rom.to_str(), Some("pong.ch8"));
+        assert_eq!(config.speed, 1000);
+        assert_eq!(config.scale, 5);
+        assert_eq!(config.timers, TimerSource::Cycles { cpu_hz: 1000 });
+
+        let config = Config::parse(args(&["pong.ch8", "-t", "realtime"])).unwrap();
+        assert_eq!(config.speed, DEFAULT_SPEED);
+        assert_eq!(config.timers, TimerSource::RealTime);
+        assert_eq!(config.palette, Palette::default());
+        assert_eq!(config.quirks, Quirks::default());
+
+        let config = Config::parse(args(&["--quirks", "vip", "pong.ch8"])).unwrap();
+        assert_eq!(config.quirks, quirks::COSMAC_VIP);
+
+        let config = Config::parse(args(&["-m", "schip", "pong.ch8"])).unwrap();
+        assert_eq!(config.platform, Platform::SuperChip);
+        assert_eq!(config.quirks, quirks::SUPER_CHIP);
+
+        let config =
+            Config::parse(args(&["--volume", "0", "--wav", "out.wav", "pong.ch8"])).unwrap();
+        assert_eq!(config.volume, 0.0);
+        assert_eq!(config.wav.unwrap().to_str(), Some("out.wav"));
+        assert_eq!(config.seed, None);
+        assert!(!config.force_state);
+        assert_eq!(config.rewind, DEFAULT_REWIND_SECONDS);
+
+        let config = Config::parse(args(&["--seed", "1234", "pong.ch8", "--force-state"])).unwrap();
+        assert_eq!(config.seed, Some(1234));
+        assert!(config.force_state);
+
+        let config = Config::parse(args(&["--rewind", "30", "pong.ch8"])).unwrap();
+        assert_eq!(config.rewind, 30);
+        assert_eq!(config.record, None);
+
+        let config = Config::parse(args(&[
+            "--play",
+            "in.c8m",
+            "--record",
+            "out.c8m",
+            "--load-state",
+            "pong.state1",
+            "pong.ch8",
+        ]))
+        .unwrap();
+        assert_eq!(config.play.unwrap().to_str(), Some("in.c8m"));
+        assert_eq!(config.record.unwrap().to_str(), Some("out.c8m"));
+        assert_eq!(config.load_state.unwrap().to_str(), Some("pong.state1"));
+        assert!(!config.debug);
+
+        let config = Config::parse(args(&["-d", "pong.ch8"])).unwrap();
+        assert!(config.debug);
+        assert_eq!(config.gdb, None);
+
+        let config = Config::parse(args(&["--gdb", "1234", "pong.ch8"])).unwrap();
+        assert_eq!(config.gdb, Some(1234));
+
+        let config = Config::parse(args(&["-p", "amber", "--bg", "102030", "pong.ch8"])).unwrap();
+        assert_eq!(config.palette.foreground, palette::AMBER.foreground);
+        assert_eq!(config.palette.background, [0x10, 0x20, 0x30]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(
+            Config::parse(args(&[])),
+            Err(ConfigError::MissingRom)
+        ));
+        assert!(matches!(
+            Config::parse(args(&["pong.ch8", "--speed"])),
+            Err(ConfigError::MissingValue(_))
+        ));
+        assert!(matches!(
+            Config::parse(args(&["pong.ch8", "--scale", "0"])),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+        assert!(matches!(
+            Config::parse(args(&["pong.ch8", "--timers", "fast"])),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+        assert!(matches!(
+            Config::parse(args(&["pong.ch8", "--volume", "101"])),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+        assert!(matches!(
+            Config::parse(args(&["pong.ch8", "--gdb", "70000"])),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+        assert!(matches!(
+            Config::parse(args(&["--fast", "pong.ch8"])),
+            Err(ConfigError::UnknownOption(_))
+        ));
+    }
+}
@@ -0,0 +1,668 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    disasm, dump,
+    emulator::Emulator,
+    instruction::Instruction,
+    processor::{Processor, Watch},
+};
+
+mod condition;
+pub use condition::Condition;
+
+pub const HELP: &str = "Commands:
+    c, continue          Run until a breakpoint
+    p, pause             Stop running
+    s, step [N]          Run N instructions (default 1)
+    n, next              Step, running the whole subroutine at a CALL
+    u, until <ADDR>      Run until PC reaches ADDR
+    b, break [ADDR] [if COND]
+                         Set a breakpoint at ADDR that only stops when COND
+                         holds, or list the breakpoints and watchpoints
+    watch <ADDR> [LEN]   Stop when LEN bytes from ADDR are written (default 1)
+    rwatch <ADDR> [LEN]  Stop when they are read
+    awatch <ADDR> [LEN]  Stop when they are read or written
+    d, delete [ADDR]     Delete the breakpoint and watchpoints at ADDR, or all
+                         of them
+    r, regs              Show the registers, stack and timers
+    l, list [N]          Disassemble N instructions from PC (default 1)
+    x <ADDR> [LEN]       Dump LEN bytes of memory from ADDR (default 16)
+    w <ADDR> <BYTE>...   Write bytes to memory at ADDR
+    h, help              Print this message
+    q, quit              Exit the emulator
+
+Conditions compare v0-vf, i, pc, sp, dt, st and numbers with ==, !=, <, <=,
+> and >=, and join them with && and ||, e.g. `v3 == 10 && i > 300`.
+
+Addresses, bytes and numbers in conditions are hex, counts are decimal. An
+empty line repeats the last command.";
+
+// Bytes shown on each line of a memory dump
+const DUMP_WIDTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Continue,
+    Pause,
+    Step(u32),
+    Next,
+    Until(usize),
+    Break(usize, Option<Condition>),
+    Watch(Watch),
+    // Lists the breakpoints and watchpoints
+    Breakpoints,
+    Delete(Option<usize>),
+    Registers,
+    List(u32),
+    Examine { address: usize, len: usize },
+    Write { address: usize, bytes: Vec<u8> },
+    Help,
+    Quit,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    Unknown(String),
+    MissingArgument(&'static str),
+    InvalidArgument(String),
+    InvalidCondition(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Unknown(name) => write!(f, "unknown command '{}', try help", name),
+            CommandError::MissingArgument(arg) => write!(f, "missing {}", arg),
+            CommandError::InvalidArgument(arg) => write!(f, "invalid argument '{}'", arg),
+            CommandError::InvalidCondition(reason) => write!(f, "invalid condition: {}", reason),
+        }
+    }
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, CommandError> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let mut next = || words.next();
+        let command = match name {
+            "c" | "continue" => Command::Continue,
+            "p" | "pause" => Command::Pause,
+            "s" | "step" => Command::Step(next().map_or(Ok(1), count)?),
+            "n" | "next" => Command::Next,
+            "u" | "until" => {
+                Command::Until(hex(next().ok_or(CommandError::MissingArgument("address"))?)?)
+            }
+            "b" | "break" => match next() {
+                None => Command::Breakpoints,
+                Some(address) => {
+                    let address = hex(address)?;
+                    let condition = match next() {
+                        None => None,
+                        Some("if") => {
+                            Some(Condition::parse(&words.collect::<Vec<&str>>().join(" "))?)
+                        }
+                        Some(word) => return Err(CommandError::InvalidArgument(word.to_string())),
+                    };
+                    Command::Break(address, condition)
+                }
+            },
+            "watch" | "rwatch" | "awatch" => Command::Watch(Watch {
+                start: hex(next().ok_or(CommandError::MissingArgument("address"))?)?,
+                len: next().map_or(Ok(1), count)? as usize,
+                read: name != "watch",
+                write: name != "rwatch",
+            }),
+            "d" | "delete" => Command::Delete(next().map(hex).transpose()?),
+            "r" | "regs" => Command::Registers,
+            "l" | "list" => Command::List(next().map_or(Ok(1), count)?),
+            "x" => Command::Examine {
+                address: hex(next().ok_or(CommandError::MissingArgument("address"))?)?,
+                len: next().map_or(Ok(DUMP_WIDTH as u32), count)? as usize,
+            },
+            "w" => {
+                let address = hex(next().ok_or(CommandError::MissingArgument("address"))?)?;
+                let bytes = words
+                    .map(|word| match hex(word)? {
+                        byte if byte <= 0xff => Ok(byte as u8),
+                        _ => Err(CommandError::InvalidArgument(word.to_string())),
+                    })
+                    .collect::<Result<Vec<u8>, _>>()?;
+                if bytes.is_empty() {
+                    return Err(CommandError::MissingArgument("bytes"));
+                }
+                Command::Write { address, bytes }
+            }
+            "h" | "help" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            _ => return Err(CommandError::Unknown(name.to_string())),
+        };
+        Ok(command)
+    }
+}
+
+// Parses a hex number with or without a leading 0x
+fn hex(word: &str) -> Result<usize, CommandError> {
+    let digits = word.trim_start_matches("0x");
+    usize::from_str_radix(digits, 16).map_err(|_| CommandError::InvalidArgument(word.to_string()))
+}
+
+fn count(word: &str) -> Result<u32, CommandError> {
+    match word.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(CommandError::InvalidArgument(word.to_string())),
+    }
+}
+
+// Where running stops by itself, `depth` is the stack depth to stop at when
+// stepping over a call so recursive calls don't stop early
+#[derive(Debug, Clone, Copy)]
+struct Target {
+    pc: usize,
+    depth: Option<usize>,
+}
+
+// Pauses and steps through the program running in an `Emulator`. Commands
+// come in as text and the results go back out as text, so it can sit behind
+// any kind of console.
+pub struct Debugger {
+    breakpoints: BTreeMap<usize, Option<Condition>>,
+    paused: bool,
+    target: Option<Target>,
+    // Repeated by an empty line
+    last: Option<Command>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    // Starts paused so breakpoints can be set before the program runs
+    pub fn new() -> Self {
+        Debugger {
+            breakpoints: BTreeMap::new(),
+            paused: true,
+            target: None,
+            last: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Parses a line typed by the user, None for an empty line with nothing to
+    // repeat
+    pub fn parse(&mut self, line: &str) -> Result<Option<Command>, CommandError> {
+        if line.trim().is_empty() {
+            return Ok(self.last.clone());
+        }
+        let command = Command::parse(line)?;
+        self.last = Some(command.clone());
+        Ok(Some(command))
+    }
+
+    // Runs a command, returning what to show the user. Instructions run by
+    // stepping see `keys` held down.
+    pub fn execute(
+        &mut self,
+        emulator: &mut Emulator,
+        keys: [bool; 16],
+        command: Command,
+    ) -> String {
+        let cpu = &mut emulator.cpu;
+        match command {
+            Command::Continue => {
+                self.paused = false;
+                String::new()
+            }
+            Command::Pause if self.paused => "Already paused\n".to_string(),
+            Command::Pause => self.stop(cpu, "Paused"),
+            Command::Step(count) => self.step(emulator, keys, count),
+            Command::Next => match Instruction::decode(opcode_at(cpu, cpu.pc())) {
+                Ok(Instruction::Call(_)) => {
+                    self.target = Some(Target {
+                        pc: cpu.pc() + 2,
+                        depth: Some(cpu.stack().len()),
+                    });
+                    self.paused = false;
+                    String::new()
+                }
+                _ => self.step(emulator, keys, 1),
+            },
+            Command::Until(address) => {
+                self.target = Some(Target {
+                    pc: address,
+                    depth: None,
+                });
+                self.paused = false;
+                String::new()
+            }
+            Command::Break(address, condition) => {
+                let message = match &condition {
+                    Some(condition) => format!("Breakpoint at {:03x} if {}\n", address, condition),
+                    None => format!("Breakpoint at {:03x}\n", address),
+                };
+                self.breakpoints.insert(address, condition);
+                message
+            }
+            Command::Watch(watch) => match watch.start.checked_add(watch.len) {
+                Some(end) if end <= cpu.memory().len() => {
+                    cpu.watches_mut().push(watch);
+                    format!("Watchpoint on {}\n", describe(&watch))
+                }
+                _ => format!("{:03x} is outside memory\n", watch.start),
+            },
+            Command::Breakpoints => {
+                let mut lines = String::new();
+                for (address, condition) in self.breakpoints.iter() {
+                    lines += &location(cpu, *address);
+                    if let Some(condition) = condition {
+                        lines += &format!(" if {}", condition);
+                    }
+                    lines += "\n";
+                }
+                for watch in cpu.watches() {
+                    lines += &format!("Watchpoint on {}\n", describe(watch));
+                }
+                match lines.is_empty() {
+                    true => "No breakpoints\n".to_string(),
+                    false => lines,
+                }
+            }
+            Command::Delete(None) => {
+                self.breakpoints.clear();
+                cpu.watches_mut().clear();
+                "Deleted all breakpoints\n".to_string()
+            }
+            Command::Delete(Some(address)) => {
+                let watches = cpu.watches_mut();
+                let count = watches.len();
+                watches.retain(|watch| watch.start != address);
+                match self.breakpoints.remove(&address).is_some() || watches.len() < count {
+                    true => format!("Deleted breakpoints at {:03x}\n", address),
+                    false => format!("No breakpoint at {:03x}\n", address),
+                }
+            }
+            Command::Registers => dump::registers(cpu),
+            Command::List(count) => {
+                let mut address = cpu.pc();
+                let mut lines = String::new();
+                for _ in 0..count {
+                    if address + 1 >= cpu.memory().len() {
+                        break;
+                    }
+                    lines += &format!("{}\n", location(cpu, address));
+                    address += disasm::instruction_len(cpu.platform(), opcode_at(cpu, address));
+                }
+                lines
+            }
+            Command::Examine { address, len } => {
+                let memory = cpu.memory();
+                let end = match address.checked_add(len) {
+                    Some(end) if address < memory.len() => memory.len().min(end),
+                    _ => return format!("{:03x} is outside memory\n", address),
+                };
+                memory[address..end]
+                    .chunks(DUMP_WIDTH)
+                    .enumerate()
+                    .map(|(i, line)| {
+                        let bytes: Vec<String> =
+                            line.iter().map(|byte| format!("{:02x}", byte)).collect();
+                        format!("{:03x}: {}\n", address + i * DUMP_WIDTH, bytes.join(" "))
+                    })
+                    .collect()
+            }
+            Command::Write { address, bytes } => {
+                let memory = cpu.memory_mut();
+                let range = address
+                    .checked_add(bytes.len())
+                    .and_then(|end| memory.get_mut(address..end));
+                match range {
+                    Some(range) => {
+                        range.copy_from_slice(&bytes);
+                        format!("Wrote {} bytes at {:03x}\n", bytes.len(), address)
+                    }
+                    None => format!("{:03x} is outside memory\n", address),
+                }
+            }
+            Command::Help => format!("{}\n", HELP),
+            // Up to whoever reads the commands
+            Command::Quit => String::new(),
+        }
+    }
+
+    // Runs a frame unless paused, returning a message if the program stopped
+    // at a breakpoint, a watchpoint, the target of a command or a fault
+    pub fn run_frame(&mut self, emulator: &mut Emulator, keys: [bool; 16]) -> Option<String> {
+        if self.paused {
+            return None;
+        }
+        let (breakpoints, target) = (&self.breakpoints, self.target);
+        let stopped = emulator.run_frame_until(keys, |cpu| {
+            at_breakpoint(breakpoints, cpu) || cpu.watch_hit().is_some() || reached(target, cpu)
+        });
+        match stopped {
+            Ok(false) => None,
+            Ok(true) => Some(self.stop(&emulator.cpu, &self.reason(&emulator.cpu))),
+            Err(err) => Some(self.stop(&emulator.cpu, &err.to_string())),
+        }
+    }
+
+    fn step(&mut self, emulator: &mut Emulator, keys: [bool; 16], count: u32) -> String {
+        for _ in 0..count {
+            if let Err(err) = emulator.step(keys) {
+                return self.stop(&emulator.cpu, &err.to_string());
+            }
+            let cpu = &emulator.cpu;
+            if at_breakpoint(&self.breakpoints, cpu) || cpu.watch_hit().is_some() {
+                break;
+            }
+        }
+        self.stop(&emulator.cpu, &self.reason(&emulator.cpu))
+    }
+
+    fn reason(&self, cpu: &Processor) -> String {
+        if let Some(hit) = cpu.watch_hit() {
+            let access = match hit.write {
+                true => "written",
+                false => "read",
+            };
+            return format!(
+                "Watchpoint: {:03x} {} by {:03x}",
+                hit.address, access, hit.pc
+            );
+        }
+        match at_breakpoint(&self.breakpoints, cpu) {
+            true => "Breakpoint".to_string(),
+            false => "Stopped".to_string(),
+        }
+    }
+
+    fn stop(&mut self, cpu: &Processor, reason: &str) -> String {
+        self.paused = true;
+        self.target = None;
+        format!("{}\n{}\n", reason, location(cpu, cpu.pc()))
+    }
+}
+
+fn at_breakpoint(breakpoints: &BTreeMap<usize, Option<Condition>>, cpu: &Processor) -> bool {
+    match breakpoints.get(&cpu.pc()) {
+        Some(Some(condition)) => condition.holds(cpu),
+        Some(None) => true,
+        None => false,
+    }
+}
+
+fn reached(target: Option<Target>, cpu: &Processor) -> bool {
+    target.is_some_and(|target| {
+        cpu.pc() == target.pc
+            && target
+                .depth
+                .map_or(true, |depth| cpu.stack().len() <= depth)
+    })
+}
+
+// e.g. `300-303 (write)`
+fn describe(watch: &Watch) -> String {
+    let access = match (watch.read, watch.write) {
+        (true, true) => "access",
+        (true, false) => "read",
+        _ => "write",
+    };
+    format!(
+        "{:03x}-{:03x} ({})",
+        watch.start,
+        watch.start + watch.len - 1,
+        access
+    )
+}
+
+fn opcode_at(cpu: &Processor, address: usize) -> u16 {
+    let memory = cpu.memory();
+    let byte = |address: usize| *memory.get(address).unwrap_or(&0) as u16;
+    byte(address) << 8 | byte(address + 1)
+}
+
+// The instruction at `address`, e.g. `204: 6a02  LD VA, 0x02`
+fn location(cpu: &Processor, address: usize) -> String {
+    let opcode = opcode_at(cpu, address);
+    format!(
+        "{:03x}: {:04x}  {}",
+        address,
+        opcode,
+        disasm::mnemonic(cpu.platform(), opcode, opcode_at(cpu, address + 2))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::{Command, CommandError, Debugger};
+    use crate::emulator::Emulator;
+    use crate::processor::Processor;
+    use crate::timer::TimerSource;
+
+    const KEYS: [bool; 16] = [false; 16];
+
+    fn emulator(rom: &[u8]) -> Emulator {
+        let mut cpu = Processor::initialize();
+        cpu.load_rom(rom).unwrap();
+        Emulator::new(cpu, 600, TimerSource::Cycles { cpu_hz: 600 })
+    }
+
+    // Runs frames until the debugger stops the program
+    fn run(debugger: &mut Debugger, emulator: &mut Emulator) -> String {
+        for _ in 0..100 {
+            if let Some(message) = debugger.run_frame(emulator, KEYS) {
+                return message;
+            }
+        }
+        panic!("program didn't stop");
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Command::parse("s"), Ok(Command::Step(1)));
+        assert_eq!(Command::parse("step 10"), Ok(Command::Step(10)));
+        assert_eq!(Command::parse("b 0x2a0"), Ok(Command::Break(0x2a0, None)));
+        assert_eq!(Command::parse("  break  "), Ok(Command::Breakpoints));
+        assert_eq!(Command::parse("u 300"), Ok(Command::Until(0x300)));
+        assert_eq!(
+            Command::parse("x 300 4"),
+            Ok(Command::Examine {
+                address: 0x300,
+                len: 4
+            })
+        );
+        assert_eq!(
+            Command::parse("w 300 ff 0x1"),
+            Ok(Command::Write {
+                address: 0x300,
+                bytes: vec![0xff, 0x01]
+            })
+        );
+        assert_eq!(
+            Command::parse("jump"),
+            Err(CommandError::Unknown("jump".to_string()))
+        );
+        assert_eq!(
+            Command::parse("step 0"),
+            Err(CommandError::InvalidArgument("0".to_string()))
+        );
+        assert_eq!(
+            Command::parse("w 300 100"),
+            Err(CommandError::InvalidArgument("100".to_string()))
+        );
+        assert_eq!(
+            Command::parse("until"),
+            Err(CommandError::MissingArgument("address"))
+        );
+
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.parse(""), Ok(None));
+        assert_eq!(debugger.parse("n"), Ok(Some(Command::Next)));
+        assert_eq!(debugger.parse(""), Ok(Some(Command::Next)));
+    }
+
+    #[test]
+    fn breakpoints() {
+        // 0x200: V0 += 1, jump to 0x200
+        let mut emulator = emulator(&[0x70, 0x01, 0x12, 0x00]);
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.run_frame(&mut emulator, KEYS), None);
+        assert_eq!(emulator.cpu.pc(), 0x200);
+
+        debugger.execute(&mut emulator, KEYS, Command::Break(0x202, None));
+        debugger.execute(&mut emulator, KEYS, Command::Continue);
+        assert_eq!(
+            run(&mut debugger, &mut emulator),
+            "Breakpoint\n202: 1200  JP 0x200\n"
+        );
+        assert!(debugger.is_paused());
+
+        // Continuing from a breakpoint runs past it
+        debugger.execute(&mut emulator, KEYS, Command::Continue);
+        run(&mut debugger, &mut emulator);
+        assert_eq!(emulator.cpu.registers()[0], 2);
+
+        debugger.execute(&mut emulator, KEYS, Command::Delete(None));
+        debugger.execute(&mut emulator, KEYS, Command::Continue);
+        assert_eq!(debugger.run_frame(&mut emulator, KEYS), None);
+        let message = debugger.execute(&mut emulator, KEYS, Command::Pause);
+        assert!(message.starts_with("Paused\n"));
+    }
+
+    // 0x200: call 0x206, 0x202: jump to 0x202
+    // 0x206: V0 = 1, return
+    const CALL: [u8; 10] = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x60, 0x01, 0x00, 0xee];
+
+    #[test]
+    fn stepping() {
+        let mut emulator = emulator(&CALL);
+        let mut debugger = Debugger::new();
+        let message = debugger.execute(&mut emulator, KEYS, Command::Step(1));
+        assert_eq!(message, "Stopped\n206: 6001  LD V0, 0x01\n");
+        debugger.execute(&mut emulator, KEYS, Command::Step(2));
+        assert_eq!(emulator.cpu.pc(), 0x202);
+
+        // Steps over the whole call
+        let mut emulator = self::emulator(&CALL);
+        debugger.execute(&mut emulator, KEYS, Command::Next);
+        run(&mut debugger, &mut emulator);
+        assert_eq!(emulator.cpu.pc(), 0x202);
+        assert_eq!(emulator.cpu.registers()[0], 1);
+
+        let mut emulator = self::emulator(&CALL);
+        debugger.execute(&mut emulator, KEYS, Command::Until(0x208));
+        run(&mut debugger, &mut emulator);
+        assert_eq!(emulator.cpu.pc(), 0x208);
+        assert_eq!(emulator.cpu.stack(), &[0x202]);
+    }
+
+    #[test]
+    fn memory() {
+        let mut emulator = emulator(&[0x12, 0x00]);
+        let mut debugger = Debugger::new();
+        let message = debugger.execute(
+            &mut emulator,
+            KEYS,
+            Command::Write {
+                address: 0x300,
+                bytes: vec![0xab, 0xcd],
+            },
+        );
+        assert_eq!(message, "Wrote 2 bytes at 300\n");
+        let message = debugger.execute(
+            &mut emulator,
+            KEYS,
+            Command::Examine {
+                address: 0x2ff,
+                len: 20,
+            },
+        );
+        assert_eq!(
+            message,
+            "2ff: 00 ab cd 00 00 00 00 00 00 00 00 00 00 00 00 00\n30f: 00 00 00 00\n"
+        );
+        let message = debugger.execute(
+            &mut emulator,
+            KEYS,
+            Command::Write {
+                address: 0xfff,
+                bytes: vec![1, 2],
+            },
+        );
+        assert_eq!(message, "fff is outside memory\n");
+        let command = Command::parse("w ffffffffffffffff 00").unwrap();
+        let message = debugger.execute(&mut emulator, KEYS, command);
+        assert_eq!(message, "ffffffffffffffff is outside memory\n");
+        let command = Command::parse("x ffffffffffffffff").unwrap();
+        let message = debugger.execute(&mut emulator, KEYS, command);
+        assert_eq!(message, "ffffffffffffffff is outside memory\n");
+        let message = debugger.execute(&mut emulator, KEYS, Command::List(2));
+        assert_eq!(message, "200: 1200  JP 0x200\n202: 0000  DW 0x0000\n");
+    }
+
+    #[test]
+    fn conditional_breakpoints() {
+        // 0x200: V0 += 1, jump to 0x200
+        let mut emulator = emulator(&[0x70, 0x01, 0x12, 0x00]);
+        let mut debugger = Debugger::new();
+        let command = debugger.parse("b 202 if v0 == 5").unwrap().unwrap();
+        assert_eq!(
+            debugger.execute(&mut emulator, KEYS, command),
+            "Breakpoint at 202 if v0 == 5\n"
+        );
+        debugger.execute(&mut emulator, KEYS, Command::Continue);
+        assert!(run(&mut debugger, &mut emulator).starts_with("Breakpoint\n"));
+        assert_eq!(emulator.cpu.registers()[0], 5);
+        assert_eq!(
+            Command::parse("b 202 if v0 = 5"),
+            Err(CommandError::InvalidCondition("unexpected '='".to_string()))
+        );
+    }
+
+    #[test]
+    fn watchpoints() {
+        // 0x200: I = 0x300, V0 += 1, store V0 to V1, jump to 0x202
+        let mut emulator = emulator(&[0xa3, 0x00, 0x70, 0x01, 0xf1, 0x55, 0x12, 0x02]);
+        let mut debugger = Debugger::new();
+        let command = Command::parse("watch 301").unwrap();
+        assert_eq!(
+            debugger.execute(&mut emulator, KEYS, command),
+            "Watchpoint on 301-301 (write)\n"
+        );
+        let command = Command::parse("rwatch 300 2").unwrap();
+        debugger.execute(&mut emulator, KEYS, command);
+        debugger.execute(&mut emulator, KEYS, Command::Continue);
+        assert_eq!(
+            run(&mut debugger, &mut emulator),
+            "Watchpoint: 301 written by 204\n206: 1202  JP 0x202\n"
+        );
+        assert_eq!(
+            debugger.execute(&mut emulator, KEYS, Command::Breakpoints),
+            "Watchpoint on 301-301 (write)\nWatchpoint on 300-301 (read)\n"
+        );
+        let command = Command::parse("watch ffffffffffffffff 2").unwrap();
+        assert_eq!(
+            debugger.execute(&mut emulator, KEYS, command),
+            "ffffffffffffffff is outside memory\n"
+        );
+        debugger.execute(&mut emulator, KEYS, Command::Delete(Some(0x301)));
+        // Only reads of 300-301 stop it now
+        let message = debugger.execute(&mut emulator, KEYS, Command::Step(10));
+        assert_eq!(message, "Stopped\n202: 7001  ADD V0, 0x01\n");
+    }
+
+    #[test]
+    fn faults_pause() {
+        let mut emulator = emulator(&[0x00, 0x00]);
+        let mut debugger = Debugger::new();
+        debugger.execute(&mut emulator, KEYS, Command::Continue);
+        let message = run(&mut debugger, &mut emulator);
+        assert!(message.starts_with("unknown opcode"), "{}", message);
+        assert!(debugger.is_paused());
+    }
+}
@@ -0,0 +1,267 @@
+use std::fmt;
+
+use crate::{debugger::CommandError, processor::Processor};
+
+// A breakpoint condition such as `v3 == 0x10 && i > 0x300`. Comparisons can be
+// joined with && and ||, and grouped with parentheses. Values are the
+// registers v0-vf, i, pc, sp, dt and st, or hex numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    text: String,
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Compare(Value, Op, Value),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    V(usize),
+    Index,
+    Pc,
+    // Stack depth
+    Sp,
+    DelayTimer,
+    SoundTimer,
+    Number(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, CommandError> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(Condition {
+                text: text.trim().to_string(),
+                expr,
+            }),
+            Some(token) => Err(invalid(&format!("unexpected '{}'", token))),
+        }
+    }
+
+    pub fn holds(&self, cpu: &Processor) -> bool {
+        self.expr.eval(cpu)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Expr {
+    fn eval(&self, cpu: &Processor) -> bool {
+        match self {
+            Expr::Compare(left, op, right) => {
+                let (left, right) = (left.eval(cpu), right.eval(cpu));
+                match op {
+                    Op::Eq => left == right,
+                    Op::Ne => left != right,
+                    Op::Lt => left < right,
+                    Op::Le => left <= right,
+                    Op::Gt => left > right,
+                    Op::Ge => left >= right,
+                }
+            }
+            Expr::And(left, right) => left.eval(cpu) && right.eval(cpu),
+            Expr::Or(left, right) => left.eval(cpu) || right.eval(cpu),
+        }
+    }
+}
+
+impl Value {
+    fn parse(word: &str) -> Option<Value> {
+        let value = match word {
+            "i" => Value::Index,
+            "pc" => Value::Pc,
+            "sp" => Value::Sp,
+            "dt" => Value::DelayTimer,
+            "st" => Value::SoundTimer,
+            _ if word.len() == 2 && word.starts_with('v') => {
+                Value::V(usize::from_str_radix(&word[1..], 16).ok()?)
+            }
+            _ => Value::Number(usize::from_str_radix(word.trim_start_matches("0x"), 16).ok()?),
+        };
+        Some(value)
+    }
+
+    fn eval(self, cpu: &Processor) -> usize {
+        match self {
+            Value::V(x) => cpu.registers()[x] as usize,
+            Value::Index => cpu.index() as usize,
+            Value::Pc => cpu.pc(),
+            Value::Sp => cpu.stack().len(),
+            Value::DelayTimer => cpu.delay_timer() as usize,
+            Value::SoundTimer => cpu.sound_timer() as usize,
+            Value::Number(n) => n,
+        }
+    }
+}
+
+fn invalid(reason: &str) -> CommandError {
+    CommandError::InvalidCondition(reason.to_string())
+}
+
+// Splits a condition into words and operators
+fn tokenize(text: &str) -> Result<Vec<String>, CommandError> {
+    let chars: Vec<char> = text.to_lowercase().chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let len = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            _ if c.is_ascii_alphanumeric() => chars[i..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .count(),
+            '(' | ')' => 1,
+            _ => match chars.get(i..i + 2) {
+                Some(
+                    ['=', '='] | ['!', '='] | ['<', '='] | ['>', '='] | ['&', '&'] | ['|', '|'],
+                ) => 2,
+                _ if c == '<' || c == '>' => 1,
+                _ => return Err(invalid(&format!("unexpected '{}'", c))),
+            },
+        };
+        tokens.push(chars[i..i + len].iter().collect());
+        i += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.next).map(String::as_str)
+    }
+
+    fn take(&mut self) -> Result<String, CommandError> {
+        let token = self
+            .tokens
+            .get(self.next)
+            .cloned()
+            .ok_or_else(|| invalid("ends too early"))?;
+        self.next += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Expr, CommandError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some("||") {
+            self.next += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, CommandError> {
+        let mut expr = self.term()?;
+        while self.peek() == Some("&&") {
+            self.next += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.term()?));
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, CommandError> {
+        if self.peek() == Some("(") {
+            self.next += 1;
+            let expr = self.or()?;
+            return match self.take()?.as_str() {
+                ")" => Ok(expr),
+                token => Err(invalid(&format!("expected ')' but found '{}'", token))),
+            };
+        }
+        let left = self.value()?;
+        let op = match self.take()?.as_str() {
+            "==" => Op::Eq,
+            "!=" => Op::Ne,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            token => {
+                return Err(invalid(&format!(
+                    "expected a comparison but found '{}'",
+                    token
+                )))
+            }
+        };
+        Ok(Expr::Compare(left, op, self.value()?))
+    }
+
+    fn value(&mut self) -> Result<Value, CommandError> {
+        let token = self.take()?;
+        Value::parse(&token).ok_or_else(|| invalid(&format!("unknown value '{}'", token)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::condition::Condition;
+    use crate::processor::Processor;
+
+    #[test]
+    fn conditions() {
+        let mut cpu = Processor::initialize();
+        // 0x200: V3 = 0x10, I = 0x310
+        cpu.load_rom(&[0x63, 0x10, 0xa3, 0x10]).unwrap();
+        cpu.run_cycle([false; 16]).unwrap();
+        cpu.run_cycle([false; 16]).unwrap();
+
+        let holds = |text: &str| Condition::parse(text).unwrap().holds(&cpu);
+        assert!(holds("v3 == 0x10 && i > 0x300"));
+        assert!(holds("V3==10&&I>300"));
+        assert!(!holds("v3 == 0x10 && i > 0x310"));
+        assert!(holds("v3 != 10 || i >= 310"));
+        assert!(holds("(v0 == 1 || v3 == 10) && pc == 204 && sp < 1"));
+        assert!(!holds("v0 == 1 || v3 == 10 && pc == 0"));
+        assert!(holds("dt <= st"));
+    }
+
+    #[test]
+    fn bad_conditions() {
+        for text in [
+            "",
+            "v3",
+            "v3 == ",
+            "v3 = 1",
+            "vg == 1",
+            "(v3 == 1",
+            "v3 == 1 v2",
+            "v3 == $1",
+        ]
+        .iter()
+        {
+            assert!(Condition::parse(text).is_err(), "{}", text);
+        }
+        assert_eq!(
+            Condition::parse(" v3 == 1 ").unwrap().to_string(),
+            "v3 == 1"
+        );
+    }
+}
@@ -0,0 +1,418 @@
+// Turns opcodes back into assembly. Mnemonics follow Cowgod's chip-8
+// reference, with the usual names for the SUPER-CHIP and XO-CHIP additions.
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::instruction::Instruction;
+use crate::platform::Platform;
+use crate::processor::PROGRAM_START;
+
+// Formats `opcode` as an instruction, `next` is the word after it which
+// F000 NNNN uses as its address. Opcodes that aren't instructions on
+// `platform` come out as data words.
+pub fn mnemonic(platform: Platform, opcode: u16, next: u16) -> String {
+    instruction(platform, opcode, next, &|_| None).unwrap_or_else(|| data(opcode))
+}
+
+// Bytes taken up by `opcode`, F000 NNNN is the only instruction longer than 2
+pub fn instruction_len(platform: Platform, opcode: u16) -> usize {
+    match decode(platform, opcode) {
+        Some(instruction) => instruction.size(),
+        None => 2,
+    }
+}
+
+// The instruction `opcode` encodes on `platform`
+fn decode(platform: Platform, opcode: u16) -> Option<Instruction> {
+    Instruction::decode(opcode)
+        .ok()
+        .filter(|instruction| instruction.platform() <= platform)
+}
+
+// The instruction `opcode` encodes on `platform`, addresses are written with
+// `name` when it knows them
+fn instruction(
+    platform: Platform,
+    opcode: u16,
+    next: u16,
+    name: &dyn Fn(usize) -> Option<String>,
+) -> Option<String> {
+    use Instruction::*;
+
+    let address = |nnn: u16| name(nnn as usize).unwrap_or_else(|| format!("{:#05x}", nnn));
+    let text = match decode(platform, opcode)? {
+        Clear => "CLS".to_string(),
+        Return => "RET".to_string(),
+        ScrollDown(n) => format!("SCD {}", n),
+        ScrollUp(n) => format!("SCU {}", n),
+        ScrollRight => "SCR".to_string(),
+        ScrollLeft => "SCL".to_string(),
+        Exit => "EXIT".to_string(),
+        Lores => "LOW".to_string(),
+        Hires => "HIGH".to_string(),
+        Jump(nnn) => format!("JP {}", address(nnn)),
+        Call(nnn) => format!("CALL {}", address(nnn)),
+        SkipEqual(x, nn) => format!("SE V{:X}, {:#04x}", x, nn),
+        SkipNotEqual(x, nn) => format!("SNE V{:X}, {:#04x}", x, nn),
+        SkipEqualRegister(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        SaveRange(x, y) => format!("SAVE V{:X}, V{:X}", x, y),
+        LoadRange(x, y) => format!("LOAD V{:X}, V{:X}", x, y),
+        Set(x, nn) => format!("LD V{:X}, {:#04x}", x, nn),
+        Add(x, nn) => format!("ADD V{:X}, {:#04x}", x, nn),
+        Copy(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Or(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        And(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Xor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        AddRegister(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        Sub(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        ShiftRight(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        SubReverse(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        ShiftLeft(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        SkipNotEqualRegister(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        SetIndex(nnn) => format!("LD I, {}", address(nnn)),
+        JumpOffset(nnn) => format!("JP V0, {}", address(nnn)),
+        Random(x, nn) => format!("RND V{:X}, {:#04x}", x, nn),
+        Draw(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        SkipKey(x) => format!("SKP V{:X}", x),
+        SkipNotKey(x) => format!("SKNP V{:X}", x),
+        SetIndexLong => {
+            let long = name(next as usize).unwrap_or_else(|| format!("{:#06x}", next));
+            format!("LD I, LONG {}", long)
+        }
+        Planes(n) => format!("PLANE {}", n),
+        LoadAudio => "AUDIO".to_string(),
+        GetDelay(x) => format!("LD V{:X}, DT", x),
+        WaitKey(x) => format!("LD V{:X}, K", x),
+        SetDelay(x) => format!("LD DT, V{:X}", x),
+        SetSound(x) => format!("LD ST, V{:X}", x),
+        AddIndex(x) => format!("ADD I, V{:X}", x),
+        Font(x) => format!("LD F, V{:X}", x),
+        BigFont(x) => format!("LD HF, V{:X}", x),
+        Bcd(x) => format!("LD B, V{:X}", x),
+        Pitch(x) => format!("PITCH V{:X}", x),
+        Store(x) => format!("LD [I], V{:X}", x),
+        Load(x) => format!("LD V{:X}, [I]", x),
+        SaveFlags(x) => format!("LD R, V{:X}", x),
+        LoadFlags(x) => format!("LD V{:X}, R", x),
+    };
+    Some(text)
+}
+
+fn data(opcode: u16) -> String {
+    format!("DW {:#06x}", opcode)
+}
+
+// Why an address got a label, a later reason with a higher rank renames it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Label {
+    // Pointed at by ANNN or F000 NNNN, shown as sprite rows unless it's code
+    Sprite,
+    // Jumped to by 1NNN or BNNN
+    Jump,
+    // Called by 2NNN
+    Call,
+}
+
+// A rom split into code and data by following its control flow from 0x200.
+// Jumps, calls and skips lead to more code, anything never reached that way
+// is data.
+pub struct Disassembly {
+    platform: Platform,
+    rom: Vec<u8>,
+    // Addresses instructions start at
+    code: BTreeSet<usize>,
+    labels: BTreeMap<usize, Label>,
+}
+
+impl Disassembly {
+    pub fn new(rom: &[u8], platform: Platform) -> Disassembly {
+        let mut disasm = Disassembly {
+            platform,
+            rom: rom.to_vec(),
+            code: BTreeSet::new(),
+            labels: BTreeMap::new(),
+        };
+        let mut pending = vec![PROGRAM_START];
+        while let Some(address) = pending.pop() {
+            if disasm.code.contains(&address) {
+                continue;
+            }
+            let (opcode, next) = match (disasm.word(address), disasm.word(address + 2)) {
+                (Some(opcode), next) => (opcode, next.unwrap_or(0)),
+                (None, _) => continue,
+            };
+            // Running into something that isn't an instruction ends the path
+            let instruction = match decode(platform, opcode) {
+                Some(instruction) if disasm.contains(address + instruction.size() - 1) => {
+                    instruction
+                }
+                _ => continue,
+            };
+            disasm.code.insert(address);
+
+            let after = address + instruction.size();
+            match instruction {
+                Instruction::Return | Instruction::Exit => {}
+                Instruction::Jump(nnn) => {
+                    disasm.mark(nnn as usize, Label::Jump);
+                    pending.push(nnn as usize);
+                }
+                Instruction::Call(nnn) => {
+                    disasm.mark(nnn as usize, Label::Call);
+                    pending.push(nnn as usize);
+                    pending.push(after);
+                }
+                // Only the V0 = 0 entry of a jump table can be followed
+                Instruction::JumpOffset(nnn) => {
+                    disasm.mark(nnn as usize, Label::Jump);
+                    pending.push(nnn as usize);
+                }
+                Instruction::SetIndex(nnn) => {
+                    disasm.mark(nnn as usize, Label::Sprite);
+                    pending.push(after);
+                }
+                Instruction::SetIndexLong => {
+                    disasm.mark(next as usize, Label::Sprite);
+                    pending.push(after);
+                }
+                _ if instruction.is_skip() => disasm.skip(after, &mut pending),
+                _ => pending.push(after),
+            }
+        }
+        disasm
+    }
+
+    pub fn is_code(&self, address: usize) -> bool {
+        self.code.contains(&address)
+    }
+
+    pub fn label(&self, address: usize) -> Option<Label> {
+        self.labels.get(&address).copied()
+    }
+
+    // The name the listing gives `address`, e.g. `sub_2a4`
+    pub fn label_name(&self, address: usize) -> Option<String> {
+        let prefix = match self.label(address)? {
+            Label::Sprite => "sprite",
+            Label::Jump => "loc",
+            Label::Call => "sub",
+        };
+        Some(format!("{}_{:03x}", prefix, address))
+    }
+
+    // The whole rom as assembly, each line ends with a comment giving the
+    // address and the bytes it came from
+    pub fn listing(&self) -> String {
+        let mut lines = String::new();
+        let end = PROGRAM_START + self.rom.len();
+        let mut address = PROGRAM_START;
+        while address < end {
+            if let Some(name) = self.label_name(address) {
+                lines += &format!("{}:\n", name);
+            }
+            let boundary = self.boundary(address, end);
+            let opcode = self.word(address).unwrap_or(0);
+            let len = instruction_len(self.platform, opcode);
+            // An instruction with a label inside it is shown as bytes so the
+            // label still gets a line
+            if self.is_code(address) && address + len <= boundary {
+                let next = self.word(address + 2).unwrap_or(0);
+                let text = instruction(self.platform, opcode, next, &|address| {
+                    self.label_name(address)
+                })
+                .unwrap_or_else(|| data(opcode));
+                let bytes = match len {
+                    4 => format!("{:04x}{:04x}", opcode, next),
+                    _ => format!("{:04x}", opcode),
+                };
+                lines += &line(&text, &format!("{:03x}: {}", address, bytes));
+                address += len;
+                continue;
+            }
+
+            let bytes = &self.rom[address - PROGRAM_START..boundary - PROGRAM_START];
+            match self.label(address) {
+                Some(Label::Sprite) => {
+                    for (i, byte) in bytes.iter().enumerate() {
+                        let pixels: String = (0..8)
+                            .map(|bit| match byte << bit & 0x80 {
+                                0 => '.',
+                                _ => '#',
+                            })
+                            .collect();
+                        let text = format!("DB {:#04x}", byte);
+                        lines += &line(&text, &format!("{:03x}: {}", address + i, pixels));
+                    }
+                }
+                _ => {
+                    for (i, chunk) in bytes.chunks(8).enumerate() {
+                        let list: Vec<String> =
+                            chunk.iter().map(|byte| format!("{:#04x}", byte)).collect();
+                        let text = format!("DB {}", list.join(", "));
+                        lines += &line(&text, &format!("{:03x}", address + i * 8));
+                    }
+                }
+            }
+            address = boundary;
+        }
+        lines
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        (PROGRAM_START..PROGRAM_START + self.rom.len()).contains(&address)
+    }
+
+    fn word(&self, address: usize) -> Option<u16> {
+        match self.contains(address) && self.contains(address + 1) {
+            true => {
+                let i = address - PROGRAM_START;
+                Some((self.rom[i] as u16) << 8 | self.rom[i + 1] as u16)
+            }
+            false => None,
+        }
+    }
+
+    // Labels outside the rom have no line to go on, so they're left as numbers
+    fn mark(&mut self, address: usize, label: Label) {
+        if self.contains(address) {
+            let rank = self.labels.entry(address).or_insert(label);
+            *rank = (*rank).max(label);
+        }
+    }
+
+    // A skip carries on at the next instruction or the one after it
+    fn skip(&self, next: usize, pending: &mut Vec<usize>) {
+        let len = instruction_len(self.platform, self.word(next).unwrap_or(0));
+        pending.push(next);
+        pending.push(next + len);
+    }
+
+    // Where the run of lines starting at `address` has to stop, the next
+    // label or instruction
+    fn boundary(&self, address: usize, end: usize) -> usize {
+        let code = self.code.range(address + 1..).next();
+        let label = self.labels.range(address + 1..).next().map(|(at, _)| at);
+        code.into_iter()
+            .chain(label)
+            .copied()
+            .min()
+            .unwrap_or(end)
+            .min(end)
+    }
+}
+
+fn line(text: &str, comment: &str) -> String {
+    format!("    {:<24}; {}\n", text, comment)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm::{mnemonic, Disassembly, Label};
+    use crate::platform::Platform;
+
+    #[test]
+    fn mnemonics() {
+        let cases = [
+            (0x00e0, "CLS"),
+            (0x00c4, "SCD 4"),
+            (0x1234, "JP 0x234"),
+            (0x2abc, "CALL 0xabc"),
+            (0x3a05, "SE VA, 0x05"),
+            (0x5122, "SAVE V1, V2"),
+            (0x8ab4, "ADD VA, VB"),
+            (0x812e, "SHL V1, V2"),
+            (0xa2f0, "LD I, 0x2f0"),
+            (0xd125, "DRW V1, V2, 5"),
+            (0xe3a1, "SKNP V3"),
+            (0xf265, "LD V2, [I]"),
+            (0xf201, "PLANE 2"),
+            (0x0123, "DW 0x0123"),
+            (0x5121, "DW 0x5121"),
+            (0xf1ff, "DW 0xf1ff"),
+        ];
+        for (opcode, text) in cases.iter() {
+            assert_eq!(mnemonic(Platform::XoChip, *opcode, 0), *text);
+        }
+        assert_eq!(
+            mnemonic(Platform::XoChip, 0xf000, 0xabcd),
+            "LD I, LONG 0xabcd"
+        );
+    }
+
+    #[test]
+    fn platform_opcodes() {
+        let cases = [
+            (0x00ff, "DW 0x00ff", "HIGH", "HIGH"),
+            (0x00c4, "DW 0x00c4", "SCD 4", "SCD 4"),
+            (0xf130, "DW 0xf130", "LD HF, V1", "LD HF, V1"),
+            (0x00d4, "DW 0x00d4", "DW 0x00d4", "SCU 4"),
+            (0x5122, "DW 0x5122", "DW 0x5122", "SAVE V1, V2"),
+            (0xf000, "DW 0xf000", "DW 0xf000", "LD I, LONG 0x0000"),
+            (0xf23a, "DW 0xf23a", "DW 0xf23a", "PITCH V2"),
+            (0x00e0, "CLS", "CLS", "CLS"),
+        ];
+        for (opcode, chip8, schip, xo) in cases.iter() {
+            assert_eq!(mnemonic(Platform::Chip8, *opcode, 0), *chip8);
+            assert_eq!(mnemonic(Platform::SuperChip, *opcode, 0), *schip);
+            assert_eq!(mnemonic(Platform::XoChip, *opcode, 0), *xo);
+        }
+    }
+
+    #[test]
+    fn code_and_data() {
+        let rom = [
+            0x22, 0x0a, // 200: CALL 0x20a
+            0x30, 0x01, // 202: SE V0, 0x01
+            0x12, 0x08, // 204: JP 0x208
+            0xff, 0xff, // 206: never reached
+            0x12, 0x08, // 208: JP 0x208
+            0xa2, 0x10, // 20a: LD I, 0x210
+            0xd0, 0x12, // 20c: DRW V0, V1, 2
+            0x00, 0xee, // 20e: RET
+            0xf0, 0x90, // 210: sprite
+        ];
+        let disasm = Disassembly::new(&rom, Platform::Chip8);
+        let code: Vec<usize> = (0x200..0x212).filter(|&a| disasm.is_code(a)).collect();
+        assert_eq!(code, [0x200, 0x202, 0x204, 0x208, 0x20a, 0x20c, 0x20e]);
+        assert_eq!(disasm.label(0x20a), Some(Label::Call));
+        assert_eq!(disasm.label(0x208), Some(Label::Jump));
+        assert_eq!(disasm.label(0x210), Some(Label::Sprite));
+        assert_eq!(disasm.label(0x206), None);
+
+        let expected = "    CALL sub_20a            ; 200: 220a
+    SE V0, 0x01             ; 202: 3001
+    JP loc_208              ; 204: 1208
+    DB 0xff, 0xff           ; 206
+loc_208:
+    JP loc_208              ; 208: 1208
+sub_20a:
+    LD I, sprite_210        ; 20a: a210
+    DRW V0, V1, 2           ; 20c: d012
+    RET                     ; 20e: 00ee
+sprite_210:
+    DB 0xf0                 ; 210: ####....
+    DB 0x90                 ; 211: #..#....
+";
+        assert_eq!(disasm.listing(), expected);
+    }
+
+    #[test]
+    fn xo_chip_flow() {
+        let rom = [
+            0xf0, 0x00, 0x02, 0x0c, // 200: LD I, LONG 0x20c
+            0x30, 0x00, // 204: SE V0, 0x00
+            0xf0, 0x00, 0x02, 0x0c, // 206: LD I, LONG 0x20c, skipped as one
+            0x00, 0xfd, // 20a: EXIT
+            0x81, // 20c: sprite
+        ];
+        let disasm = Disassembly::new(&rom, Platform::XoChip);
+        assert!(disasm.is_code(0x206));
+        assert!(disasm.is_code(0x20a));
+        assert!(!disasm.is_code(0x208));
+        assert!(disasm.listing().contains("LD I, LONG sprite_20c"));
+
+        // On plain chip-8 the same rom stops at the first word
+        let disasm = Disassembly::new(&rom, Platform::Chip8);
+        assert!(!disasm.is_code(0x200));
+        assert!(disasm.listing().starts_with("    DB 0xf0, 0x00"));
+    }
+}
@@ -1,16 +1,18 @@
 use pixels::{wgpu::Surface, Pixels, SurfaceTexture};
 use winit::window::Window;
 
-use crate::{HEIGHT, PIXEL_SCALE, WIDTH};
+use crate::{palette::Palette, processor::VRAM, HEIGHT, WIDTH};
 
 pub struct Display {
     pixels: Pixels,
+    scale: usize,
+    palette: Palette,
 }
 
 impl Display {
-    pub fn new(window: &Window) -> Self {
-        let width = (WIDTH * PIXEL_SCALE) as u32;
-        let height = (HEIGHT * PIXEL_SCALE) as u32;
+    pub fn new(window: &Window, scale: usize, palette: Palette) -> Self {
+        let width = (WIDTH * scale) as u32;
+        let height = (HEIGHT * scale) as u32;
 
         let pixels = {
             let surface = Surface::create(window);
@@ -18,16 +20,30 @@ impl Display {
             Pixels::new(width, height, surface_texture).unwrap()
         };
 
-        Display { pixels }
+        Display {
+            pixels,
+            scale,
+            palette,
+        }
     }
 
-    pub fn draw(&mut self, vram: [u8; 2048]) {
+    // Draws the top left `width` x `height` cells of vram stretched over the
+    // whole window
+    pub fn draw(&mut self, vram: &[u8; VRAM], (width, height): (usize, usize)) {
+        let frame_width = WIDTH * self.scale;
+        let frame_height = HEIGHT * self.scale;
+        let palette = self.palette;
         let frame = self.pixels.get_frame();
-        println!("Drawing");
-        for pixel in frame.chunks_exact_mut(4) {
-            pixel[0] = 0x00; // R
-            pixel[1] = 0x00; // G
-            pixel[2] = 0x00; // B
+
+        // In low resolution each vram cell becomes a `scale` x `scale` block of
+        // frame pixels, high resolution cells are half that size
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let col = (i % frame_width) * width / frame_width;
+            let row = (i / frame_width) * height / frame_height;
+            let [r, g, b] = palette.color(vram[col + row * width]);
+            pixel[0] = r; // R
+            pixel[1] = g; // G
+            pixel[2] = b; // B
             pixel[3] = 0xff; // A
         }
 
@@ -1,71 +1,36 @@
 use winit::event::{ElementState, VirtualKeyCode};
 
-pub fn get_keys(code: Option<VirtualKeyCode>, state: ElementState, keys: &mut [u8; 16]) {
+// Lays the left hand side of the keyboard over the keypad in order, row by
+// row, with W and Q swapped
+//
+// 1 2 3 4        0 1 2 3
+// Q W E R   ->   5 4 6 7
+// A S D F        8 9 A B
+// Z X C V        C D E F
+fn keypad_index(code: VirtualKeyCode) -> Option<usize> {
     match code {
-        Some(VirtualKeyCode::Key1) => match state {
-            ElementState::Pressed => keys[0] = 1,
-            ElementState::Released => keys[0] = 0,
-        },
-        Some(VirtualKeyCode::Key2) => match state {
-            ElementState::Pressed => keys[1] = 1,
-            ElementState::Released => keys[1] = 0,
-        },
-        Some(VirtualKeyCode::Key3) => match state {
-            ElementState::Pressed => keys[2] = 1,
-            ElementState::Released => keys[2] = 0,
-        },
-        Some(VirtualKeyCode::Key4) => match state {
-            ElementState::Pressed => keys[3] = 1,
-            ElementState::Released => keys[3] = 0,
-        },
-        Some(VirtualKeyCode::W) => match state {
-            ElementState::Pressed => keys[4] = 1,
-            ElementState::Released => keys[4] = 0,
-        },
-        Some(VirtualKeyCode::Q) => match state {
-            ElementState::Pressed => keys[5] = 1,
-            ElementState::Released => keys[5] = 0,
-        },
-        Some(VirtualKeyCode::E) => match state {
-            ElementState::Pressed => keys[6] = 1,
-            ElementState::Released => keys[6] = 0,
-        },
-        Some(VirtualKeyCode::R) => match state {
-            ElementState::Pressed => keys[7] = 1,
-            ElementState::Released => keys[7] = 0,
-        },
-        Some(VirtualKeyCode::A) => match state {
-            ElementState::Pressed => keys[8] = 1,
-            ElementState::Released => keys[8] = 0,
-        },
-        Some(VirtualKeyCode::S) => match state {
-            ElementState::Pressed => keys[9] = 1,
-            ElementState::Released => keys[9] = 0,
-        },
-        Some(VirtualKeyCode::D) => match state {
-            ElementState::Pressed => keys[10] = 1,
-            ElementState::Released => keys[10] = 0,
-        },
-        Some(VirtualKeyCode::F) => match state {
-            ElementState::Pressed => keys[11] = 1,
-            ElementState::Released => keys[11] = 0,
-        },
-        Some(VirtualKeyCode::Z) => match state {
-            ElementState::Pressed => keys[12] = 1,
-            ElementState::Released => keys[12] = 0,
-        },
-        Some(VirtualKeyCode::X) => match state {
-            ElementState::Pressed => keys[13] = 1,
-            ElementState::Released => keys[13] = 0,
-        },
-        Some(VirtualKeyCode::C) => match state {
-            ElementState::Pressed => keys[14] = 1,
-            ElementState::Released => keys[14] = 0,
-        },
-        Some(VirtualKeyCode::V) => match state {
-            ElementState::Pressed => keys[15] = 1,
-            ElementState::Released => keys[15] = 0,
-        },
-        _ => (),
-    };
+        VirtualKeyCode::Key1 => Some(0x0),
+        VirtualKeyCode::Key2 => Some(0x1),
+        VirtualKeyCode::Key3 => Some(0x2),
+        VirtualKeyCode::Key4 => Some(0x3),
+        VirtualKeyCode::W => Some(0x4),
+        VirtualKeyCode::Q => Some(0x5),
+        VirtualKeyCode::E => Some(0x6),
+        VirtualKeyCode::R => Some(0x7),
+        VirtualKeyCode::A => Some(0x8),
+        VirtualKeyCode::S => Some(0x9),
+        VirtualKeyCode::D => Some(0xa),
+        VirtualKeyCode::F => Some(0xb),
+        VirtualKeyCode::Z => Some(0xc),
+        VirtualKeyCode::X => Some(0xd),
+        VirtualKeyCode::C => Some(0xe),
+        VirtualKeyCode::V => Some(0xf),
+        _ => None,
+    }
+}
+
+pub fn get_keys(code: Option<VirtualKeyCode>, state: ElementState, keys: &mut [bool; 16]) {
+    if let Some(i) = code.and_then(keypad_index) {
+        keys[i] = state == ElementState::Pressed;
+    }
 }
@@ -1,2 +1,4 @@
 pub mod display;
 pub mod input;
+#[cfg(feature = "speaker")]
+pub mod speaker;
@@ -0,0 +1,90 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    Sample, Stream, StreamConfig,
+};
+
+use crate::audio::AudioSink;
+
+// Samples queued beyond this are dropped so a stalled frame doesn't leave
+// the sound lagging behind the game
+const MAX_QUEUED_SECONDS: u32 = 1;
+
+// Plays samples on the default output device
+pub struct Speaker {
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    sample_rate: u32,
+    // Sound stops when the stream is dropped
+    _stream: Stream,
+}
+
+impl Speaker {
+    pub fn new() -> Result<Self, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device")?;
+        let supported = device
+            .default_output_config()
+            .map_err(|err| err.to_string())?;
+        let config = supported.config();
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+
+        let stream = match supported.sample_format() {
+            cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, queue.clone()),
+            cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, queue.clone()),
+            cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, queue.clone()),
+        }?;
+        stream.play().map_err(|err| err.to_string())?;
+
+        Ok(Speaker {
+            queue,
+            sample_rate: config.sample_rate.0,
+            _stream: stream,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
+
+impl AudioSink for Speaker {
+    fn play(&mut self, samples: &[f32]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples);
+        let max = (self.sample_rate * MAX_QUEUED_SECONDS) as usize;
+        if queue.len() > max {
+            let excess = queue.len() - max;
+            queue.drain(..excess);
+        }
+    }
+}
+
+fn build_stream<T: Sample>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    queue: Arc<Mutex<VecDeque<f32>>>,
+) -> Result<Stream, String> {
+    let channels = config.channels as usize;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                let mut queue = queue.lock().unwrap();
+                // Every channel gets the same mono sample, silence if the
+                // emulator hasn't kept up
+                for frame in data.chunks_mut(channels) {
+                    let sample = Sample::from(&queue.pop_front().unwrap_or(0.0));
+                    for out in frame.iter_mut() {
+                        *out = sample;
+                    }
+                }
+            },
+            |err| eprintln!("audio stream error: {}", err),
+        )
+        .map_err(|err| err.to_string())
+}
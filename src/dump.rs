@@ -0,0 +1,154 @@
+use std::io::{self, Write};
+
+use crate::{palette::Palette, processor::Processor};
+
+// Text and image dumps of the screen and registers, for running roms without
+// a window
+
+// Characters used for the four pixel values in ascii dumps
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+// Plain (P1) PBM of the screen at the current resolution, lit on any plane
+// counts as black
+pub fn pbm(cpu: &Processor) -> String {
+    let (width, height) = cpu.resolution();
+    let mut pbm = format!("P1\n{} {}\n", width, height);
+    for row in cpu.vram()[..width * height].chunks(width) {
+        let line: Vec<&str> = row
+            .iter()
+            .map(|&pixel| match pixel {
+                0 => "0",
+                _ => "1",
+            })
+            .collect();
+        pbm.push_str(&line.join(" "));
+        pbm.push('\n');
+    }
+    pbm
+}
+
+// One character per pixel, one line per row
+pub fn ascii(cpu: &Processor) -> String {
+    let (width, height) = cpu.resolution();
+    let mut ascii = String::with_capacity((width + 1) * height);
+    for row in cpu.vram()[..width * height].chunks(width) {
+        ascii.extend(
+            row.iter()
+                .map(|&pixel| ASCII_PIXELS[(pixel & 0b11) as usize]),
+        );
+        ascii.push('\n');
+    }
+    ascii
+}
+
+// RGB PNG of the screen with each pixel drawn as a `scale` x `scale` block
+pub fn write_png<W: Write>(
+    writer: W,
+    cpu: &Processor,
+    palette: &Palette,
+    scale: usize,
+) -> io::Result<()> {
+    let (width, height) = cpu.resolution();
+    let mut data = Vec::with_capacity(width * height * scale * scale * 3);
+    for row in cpu.vram()[..width * height].chunks(width) {
+        let mut line = Vec::with_capacity(width * scale * 3);
+        for &pixel in row {
+            for _ in 0..scale {
+                line.extend_from_slice(&palette.color(pixel));
+            }
+        }
+        for _ in 0..scale {
+            data.extend_from_slice(&line);
+        }
+    }
+
+    let mut encoder = png::Encoder::new(writer, (width * scale) as u32, (height * scale) as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(&data).map_err(png_error)
+}
+
+fn png_error(err: png::EncodingError) -> io::Error {
+    match err {
+        png::EncodingError::IoError(err) => err,
+        err => io::Error::other(err),
+    }
+}
+
+// Human readable dump of the registers, stack and timers
+pub fn registers(cpu: &Processor) -> String {
+    let v: Vec<String> = cpu
+        .registers()
+        .iter()
+        .enumerate()
+        .map(|(i, value)| format!("V{:X}={:02x}", i, value))
+        .collect();
+    let stack: Vec<String> = cpu
+        .stack()
+        .iter()
+        .map(|address| format!("{:03x}", address))
+        .collect();
+    format!(
+        "PC={:03x} I={:03x} DT={:02x} ST={:02x}\n{}\n{}\nstack=[{}]\n",
+        cpu.pc(),
+        cpu.index(),
+        cpu.delay_timer(),
+        cpu.sound_timer(),
+        v[..8].join(" "),
+        v[8..].join(" "),
+        stack.join(" "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump::{ascii, pbm, registers, write_png};
+    use crate::palette::Palette;
+    use crate::processor::Processor;
+
+    const KEYS: [bool; 16] = [false; 16];
+
+    // Draws the font's 0 in the top left corner
+    fn zero() -> Processor {
+        let mut cpu = Processor::initialize();
+        cpu.load_rom(&[0xa0, 0x00, 0xd0, 0x05]).unwrap();
+        cpu.run_cycle(KEYS).unwrap();
+        cpu.run_cycle(KEYS).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn text_dumps() {
+        let cpu = zero();
+        let pbm = pbm(&cpu);
+        let mut lines = pbm.lines();
+        assert_eq!(lines.next(), Some("P1"));
+        assert_eq!(lines.next(), Some("64 32"));
+        assert!(lines.next().unwrap().starts_with("1 1 1 1 0 0"));
+        assert!(lines.next().unwrap().starts_with("1 0 0 1 0 0"));
+        assert_eq!(pbm.lines().count(), 34);
+
+        let ascii = ascii(&cpu);
+        assert!(ascii.starts_with("####....."));
+        assert_eq!(ascii.lines().nth(1).map(|line| &line[..6]), Some("#..#.."));
+        assert_eq!(ascii.lines().count(), 32);
+    }
+
+    #[test]
+    fn png() {
+        let mut png = Vec::new();
+        write_png(&mut png, &zero(), &Palette::default(), 2).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+        // Width and height in the IHDR chunk
+        assert_eq!(&png[16..24], &[0, 0, 0, 128, 0, 0, 0, 64]);
+    }
+
+    #[test]
+    fn register_dump() {
+        let dump = registers(&zero());
+        assert!(dump.starts_with("PC=204 I=000"));
+        assert!(dump.contains("VF=00"));
+        assert!(dump.ends_with("stack=[]\n"));
+    }
+}
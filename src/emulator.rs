@@ -0,0 +1,79 @@
+use crate::{
+    processor::{Chip8Error, Processor},
+    timer::{TimerClock, TimerSource, TIMER_HZ},
+};
+
+// Runs a processor one 60Hz frame at a time at a fixed instruction rate
+pub struct Emulator {
+    pub cpu: Processor,
+    clock: TimerClock,
+    // Instructions per second
+    speed: u32,
+    // Instructions owed to the next frame when `speed` isn't a multiple of 60
+    remainder: u32,
+}
+
+impl Emulator {
+    pub fn new(cpu: Processor, speed: u32, timers: TimerSource) -> Self {
+        Emulator {
+            cpu,
+            clock: TimerClock::new(timers),
+            speed,
+            remainder: 0,
+        }
+    }
+
+    // Number of instructions to run in the next frame
+    fn cycles_per_frame(&mut self) -> u32 {
+        self.remainder += self.speed;
+        let cycles = self.remainder / TIMER_HZ;
+        self.remainder %= TIMER_HZ;
+        cycles
+    }
+
+    // Runs one frame worth of instructions with `keys` held down, ticking the
+    // timers whenever they are due
+    pub fn run_frame(&mut self, keys: [bool; 16]) -> Result<(), Chip8Error> {
+        self.run_frame_until(keys, |_| false).map(|_| ())
+    }
+
+    // Like `run_frame` but calls `stop` after every instruction and ends the
+    // frame early if it returns true, returns whether it did
+    pub fn run_frame_until<F>(&mut self, keys: [bool; 16], mut stop: F) -> Result<bool, Chip8Error>
+    where
+        F: FnMut(&Processor) -> bool,
+    {
+        for _ in 0..self.cycles_per_frame() {
+            self.step(keys)?;
+            if stop(&self.cpu) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Runs a single instruction, ticking the timers if they are due
+    pub fn step(&mut self, keys: [bool; 16]) -> Result<(), Chip8Error> {
+        self.cpu.run_cycle(keys)?;
+        self.clock.run(&mut self.cpu, 1);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{emulator::Emulator, processor::Processor, timer::TimerSource};
+
+    #[test]
+    fn run_frame() {
+        let mut cpu = Processor::initialize();
+        // 0x200: jump to 0x200
+        cpu.load_rom(&[0x12, 0x00]).unwrap();
+        let mut emulator = Emulator::new(cpu, 90, TimerSource::Cycles { cpu_hz: 90 });
+
+        // 1.5 instructions per frame
+        assert_eq!(emulator.cycles_per_frame(), 1);
+        assert_eq!(emulator.cycles_per_frame(), 2);
+        emulator.run_frame([false; 16]).unwrap();
+    }
+}
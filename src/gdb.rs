@@ -0,0 +1,703 @@
+// A GDB remote serial protocol stub, so gdb and other front-ends that speak
+// the protocol can debug a running rom over TCP. Only what's needed for
+// registers, memory, breakpoints, watchpoints, stepping and continuing is
+// implemented, anything else gets the empty "unsupported" reply.
+use std::{
+    collections::BTreeSet,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    emulator::Emulator,
+    processor::{Chip8Error, Processor, Watch},
+};
+
+// Largest packet we accept, advertised to gdb in qSupported
+const MAX_PACKET: usize = 0x1000;
+// Registers in the order gdb numbers them: V0-VF, I, PC, SP, DT, ST
+const REGISTER_COUNT: usize = 21;
+const INDEX: usize = 16;
+const PC: usize = 17;
+const SP: usize = 18;
+const DELAY_TIMER: usize = 19;
+const SOUND_TIMER: usize = 20;
+
+// Signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// gdb has no chip-8 architecture, z80 is the closest it knows: byte
+// addressed, 16 bit little endian pointers like the ones we send
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <architecture>z80</architecture>
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// What the emulator should do with the connection after a poll
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Session {
+    Attached,
+    // gdb detached or went away, the program carries on running
+    Detached,
+    // gdb asked to kill the program
+    Killed,
+}
+
+enum Input {
+    Packet(String),
+    // gdb sends a bare 0x03 byte to stop a running program
+    Interrupt,
+}
+
+// Serves one gdb connection. Reads from `stream` must not block, returning
+// `WouldBlock` when there's nothing to read, so the stub can be polled once a
+// frame alongside the window.
+pub struct GdbStub<S> {
+    stream: S,
+    // Bytes received but not handled yet
+    input: Vec<u8>,
+    // Off once gdb asks for QStartNoAckMode
+    ack: bool,
+    paused: bool,
+    breakpoints: BTreeSet<usize>,
+    session: Session,
+}
+
+// A TCP connection that reads without blocking but writes blocking, so
+// replies are never cut short
+pub struct Connection(TcpStream);
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.set_nonblocking(true)?;
+        let read = self.0.read(buf);
+        self.0.set_nonblocking(false)?;
+        read
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// Waits for gdb to connect to `port` on localhost
+pub fn listen(port: u16) -> io::Result<GdbStub<Connection>> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    Ok(GdbStub::new(Connection(stream)))
+}
+
+impl<S: Read + Write> GdbStub<S> {
+    // Starts paused, gdb expects the program to be stopped when it attaches
+    pub fn new(stream: S) -> Self {
+        GdbStub {
+            stream,
+            input: Vec::new(),
+            ack: true,
+            paused: true,
+            breakpoints: BTreeSet::new(),
+            session: Session::Attached,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Handles whatever gdb has sent since the last poll. Instructions run by
+    // stepping see `keys` held down.
+    pub fn poll(&mut self, emulator: &mut Emulator, keys: [bool; 16]) -> io::Result<Session> {
+        let mut buf = [0; 1024];
+        let mut closed = false;
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    closed = true;
+                    break;
+                }
+                Ok(len) => self.input.extend_from_slice(&buf[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+        while self.session == Session::Attached {
+            match self.next_input()? {
+                Some(Input::Packet(packet)) => self.handle(&packet, emulator, keys)?,
+                Some(Input::Interrupt) if !self.paused => {
+                    self.paused = true;
+                    self.send(&format!("S{:02x}", SIGINT))?;
+                }
+                Some(Input::Interrupt) => (),
+                None => break,
+            }
+        }
+        // Packets sent just before closing still count, e.g. a kill
+        if closed && self.session == Session::Attached {
+            self.session = Session::Detached;
+        }
+        Ok(self.session)
+    }
+
+    // Runs a frame unless paused, telling gdb if the program stopped
+    pub fn run_frame(&mut self, emulator: &mut Emulator, keys: [bool; 16]) -> io::Result<()> {
+        if self.paused {
+            return Ok(());
+        }
+        let breakpoints = &self.breakpoints;
+        let stopped = emulator.run_frame_until(keys, |cpu| {
+            breakpoints.contains(&cpu.pc()) || cpu.watch_hit().is_some()
+        });
+        match stopped {
+            Ok(false) => Ok(()),
+            Ok(true) => self.stopped(stop_reply(&emulator.cpu)),
+            Err(err) => self.stopped(fault_reply(err)),
+        }
+    }
+
+    fn stopped(&mut self, reply: String) -> io::Result<()> {
+        self.paused = true;
+        self.send(&reply)
+    }
+
+    fn next_input(&mut self) -> io::Result<Option<Input>> {
+        loop {
+            match self.input.first() {
+                None => return Ok(None),
+                Some(0x03) => {
+                    self.input.remove(0);
+                    return Ok(Some(Input::Interrupt));
+                }
+                Some(b'$') => {
+                    let end = match self.input.iter().position(|&byte| byte == b'#') {
+                        Some(end) if self.input.len() >= end + 3 => end,
+                        // Wait for the rest of the packet
+                        _ => return Ok(None),
+                    };
+                    let data = String::from_utf8_lossy(&self.input[1..end]).into_owned();
+                    let checksum = std::str::from_utf8(&self.input[end + 1..end + 3])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    self.input.drain(..end + 3);
+                    match checksum == Some(checksum_of(&data)) {
+                        true => {
+                            if self.ack {
+                                self.stream.write_all(b"+")?;
+                            }
+                            return Ok(Some(Input::Packet(data)));
+                        }
+                        // Asks gdb to send it again
+                        false => self.stream.write_all(b"-")?,
+                    }
+                }
+                // Acks and anything else between packets
+                Some(_) => {
+                    self.input.remove(0);
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        write!(self.stream, "${}#{:02x}", data, checksum_of(data))?;
+        self.stream.flush()
+    }
+
+    fn handle(
+        &mut self,
+        packet: &str,
+        emulator: &mut Emulator,
+        keys: [bool; 16],
+    ) -> io::Result<()> {
+        let cpu = &mut emulator.cpu;
+        let reply = match packet {
+            "?" => Some(format!("S{:02x}", SIGTRAP)),
+            "g" => Some((0..REGISTER_COUNT).map(|n| read_register(cpu, n)).collect()),
+            "qAttached" => Some("1".to_string()),
+            "qC" => Some("QC1".to_string()),
+            "qfThreadInfo" => Some("m1".to_string()),
+            "qsThreadInfo" => Some("l".to_string()),
+            "QStartNoAckMode" => {
+                self.send("OK")?;
+                self.ack = false;
+                return Ok(());
+            }
+            "k" => {
+                self.session = Session::Killed;
+                return Ok(());
+            }
+            _ if packet.starts_with("qSupported") => Some(format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+",
+                MAX_PACKET
+            )),
+            _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                features(&packet["qXfer:features:read:target.xml:".len()..])
+            }
+            _ if packet.starts_with('D') => {
+                self.session = Session::Detached;
+                self.paused = false;
+                Some("OK".to_string())
+            }
+            _ if packet.starts_with('H') => Some("OK".to_string()),
+            _ if packet.starts_with('G') => write_registers(cpu, &packet[1..]),
+            _ if packet.starts_with('p') => usize::from_str_radix(&packet[1..], 16)
+                .ok()
+                .filter(|&n| n < REGISTER_COUNT)
+                .map(|n| read_register(cpu, n)),
+            _ if packet.starts_with('P') => {
+                let mut parts = packet[1..].splitn(2, '=');
+                parts
+                    .next()
+                    .and_then(|n| usize::from_str_radix(n, 16).ok())
+                    .zip(parts.next().and_then(from_hex))
+                    .and_then(|(n, bytes)| write_register(cpu, n, &bytes))
+                    .map(|_| "OK".to_string())
+            }
+            _ if packet.starts_with('m') => read_memory(cpu, &packet[1..]),
+            _ if packet.starts_with('M') => write_memory(cpu, &packet[1..]),
+            _ if packet.starts_with('Z') || packet.starts_with('z') => self.breakpoint(cpu, packet),
+            _ if packet.starts_with('c') || packet.starts_with('s') => {
+                if packet.len() > 1 {
+                    match usize::from_str_radix(&packet[1..], 16) {
+                        Ok(address) => cpu.set_pc(address),
+                        Err(_) => return self.send("E01"),
+                    }
+                }
+                if packet.starts_with('c') {
+                    // The reply comes when the program stops
+                    self.paused = false;
+                    return Ok(());
+                }
+                Some(match emulator.step(keys) {
+                    Ok(()) => stop_reply(&emulator.cpu),
+                    Err(err) => fault_reply(err),
+                })
+            }
+            // Empty means unsupported
+            _ => Some(String::new()),
+        };
+        self.send(reply.as_deref().unwrap_or("E01"))
+    }
+
+    // Handles Z (insert) and z (remove) for breakpoints and watchpoints
+    fn breakpoint(&mut self, cpu: &mut Processor, packet: &str) -> Option<String> {
+        let insert = packet.starts_with('Z');
+        let mut args = packet[1..].split(',');
+        let kind = args.next()?;
+        let address = usize::from_str_radix(args.next()?, 16).ok()?;
+        let len = match args.next() {
+            Some(len) => usize::from_str_radix(len, 16).ok()?,
+            None => 1,
+        };
+        match kind {
+            // Software and hardware breakpoints are the same thing here
+            "0" | "1" => match insert {
+                true => self.breakpoints.insert(address),
+                false => self.breakpoints.remove(&address),
+            },
+            "2" | "3" | "4" => {
+                let watch = Watch {
+                    start: address,
+                    len: len.max(1),
+                    read: kind != "2",
+                    write: kind != "3",
+                };
+                match insert {
+                    true => cpu.watches_mut().push(watch),
+                    false => cpu.watches_mut().retain(|other| *other != watch),
+                }
+                true
+            }
+            _ => return Some(String::new()),
+        };
+        Some("OK".to_string())
+    }
+}
+
+fn checksum_of(data: &str) -> u8 {
+    data.bytes().fold(0, |sum, byte| sum.wrapping_add(byte))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// Parses `ADDR,LEN`
+fn address_range(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let address = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((address, len))
+}
+
+fn register_size(n: usize) -> usize {
+    match n {
+        INDEX | PC => 2,
+        _ => 1,
+    }
+}
+
+// Registers are sent little endian
+fn read_register(cpu: &Processor, n: usize) -> String {
+    let value = match n {
+        0..=15 => cpu.registers()[n] as usize,
+        INDEX => cpu.index() as usize,
+        PC => cpu.pc(),
+        SP => cpu.stack().len(),
+        DELAY_TIMER => cpu.delay_timer() as usize,
+        _ => cpu.sound_timer() as usize,
+    };
+    to_hex(&value.to_le_bytes()[..register_size(n)])
+}
+
+fn write_register(cpu: &mut Processor, n: usize, bytes: &[u8]) -> Option<()> {
+    if bytes.len() != register_size(n) {
+        return None;
+    }
+    let value = bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| value << 8 | byte as usize);
+    match n {
+        0..=15 => cpu.set_register(n, value as u8),
+        INDEX => cpu.set_index(value as u16),
+        PC => cpu.set_pc(value),
+        // The stack can't be resized from outside, only "changed" to what it is
+        SP if value == cpu.stack().len() => (),
+        SP => return None,
+        DELAY_TIMER => cpu.set_timers(value as u8, cpu.sound_timer()),
+        SOUND_TIMER => cpu.set_timers(cpu.delay_timer(), value as u8),
+        _ => return None,
+    }
+    Some(())
+}
+
+fn write_registers(cpu: &mut Processor, hex: &str) -> Option<String> {
+    let bytes = from_hex(hex)?;
+    let mut start = 0;
+    for n in 0..REGISTER_COUNT {
+        let end = start + register_size(n);
+        write_register(cpu, n, bytes.get(start..end)?)?;
+        start = end;
+    }
+    Some("OK".to_string())
+}
+
+fn read_memory(cpu: &Processor, args: &str) -> Option<String> {
+    let (address, len) = address_range(args)?;
+    let len = len.min(MAX_PACKET / 2);
+    cpu.memory()
+        .get(address..address.checked_add(len)?)
+        .map(to_hex)
+}
+
+fn write_memory(cpu: &mut Processor, args: &str) -> Option<String> {
+    let mut parts = args.splitn(2, ':');
+    let (address, len) = address_range(parts.next()?)?;
+    let bytes = from_hex(parts.next()?).filter(|bytes| bytes.len() == len)?;
+    cpu.memory_mut()
+        .get_mut(address..address.checked_add(len)?)?
+        .copy_from_slice(&bytes);
+    Some("OK".to_string())
+}
+
+// Answers a qXfer read of `OFFSET,LEN` from the target description, `l`
+// marks the last chunk
+fn features(args: &str) -> Option<String> {
+    let (offset, len) = address_range(args)?;
+    let xml = TARGET_XML.get(offset.min(TARGET_XML.len())..)?;
+    Some(match xml.len() <= len {
+        true => format!("l{}", xml),
+        false => format!("m{}", &xml[..len]),
+    })
+}
+
+fn stop_reply(cpu: &Processor) -> String {
+    match cpu.watch_hit() {
+        Some(hit) => {
+            let kind = match hit.write {
+                true => "watch",
+                false => "rwatch",
+            };
+            format!("T{:02x}{}:{:x};", SIGTRAP, kind, hit.address)
+        }
+        None => format!("S{:02x}", SIGTRAP),
+    }
+}
+
+fn fault_reply(err: Chip8Error) -> String {
+    let signal = match err {
+        Chip8Error::UnknownOpcode { .. } => SIGILL,
+        _ => SIGSEGV,
+    };
+    format!("S{:02x}", signal)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, ErrorKind, Read, Write};
+
+    use crate::emulator::Emulator;
+    use crate::gdb::{checksum_of, GdbStub, Session, TARGET_XML};
+    use crate::processor::Processor;
+    use crate::timer::TimerSource;
+
+    const KEYS: [bool; 16] = [false; 16];
+
+    // Stands in for the socket, reads what the test queued up and collects
+    // what the stub wrote
+    #[derive(Default)]
+    struct Pipe {
+        incoming: Vec<u8>,
+        outgoing: Vec<u8>,
+        // Reads end once `incoming` runs out
+        closed: bool,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.incoming.is_empty() {
+                return match self.closed {
+                    true => Ok(0),
+                    false => Err(io::Error::from(ErrorKind::WouldBlock)),
+                };
+            }
+            let len = buf.len().min(self.incoming.len());
+            buf[..len].copy_from_slice(&self.incoming[..len]);
+            self.incoming.drain(..len);
+            Ok(len)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.outgoing.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn stub(rom: &[u8]) -> (GdbStub<Pipe>, Emulator) {
+        let mut cpu = Processor::initialize();
+        cpu.load_rom(rom).unwrap();
+        let emulator = Emulator::new(cpu, 600, TimerSource::Cycles { cpu_hz: 600 });
+        (GdbStub::new(Pipe::default()), emulator)
+    }
+
+    fn packet(data: &str) -> String {
+        format!("${}#{:02x}", data, checksum_of(data))
+    }
+
+    // Sends a packet and returns everything the stub wrote back
+    fn request(stub: &mut GdbStub<Pipe>, emulator: &mut Emulator, data: &str) -> String {
+        stub.stream
+            .incoming
+            .extend_from_slice(packet(data).as_bytes());
+        assert_eq!(stub.poll(emulator, KEYS).unwrap(), Session::Attached);
+        let reply = String::from_utf8(stub.stream.outgoing.clone()).unwrap();
+        stub.stream.outgoing.clear();
+        reply
+    }
+
+    #[test]
+    fn packets() {
+        let (mut stub, mut emulator) = stub(&[0x12, 0x00]);
+        assert_eq!(request(&mut stub, &mut emulator, "?"), "+$S05#b8");
+        assert!(request(&mut stub, &mut emulator, "qSupported:swbreak+")
+            .contains("qXfer:features:read+"));
+        assert_eq!(
+            request(&mut stub, &mut emulator, "vMustReplyEmpty"),
+            "+$#00"
+        );
+
+        // A bad checksum is refused and the next packet still gets through
+        stub.stream.incoming.extend_from_slice(b"$?#00");
+        assert_eq!(request(&mut stub, &mut emulator, "?"), "-+$S05#b8");
+
+        // Packets can arrive in pieces
+        stub.stream.incoming.extend_from_slice(b"$qAtt");
+        stub.poll(&mut emulator, KEYS).unwrap();
+        assert!(stub.stream.outgoing.is_empty());
+        stub.stream.incoming.extend_from_slice(b"ached#8f");
+        stub.poll(&mut emulator, KEYS).unwrap();
+        assert_eq!(
+            stub.stream.outgoing,
+            packet("1").replace('$', "+$").as_bytes()
+        );
+        stub.stream.outgoing.clear();
+
+        assert_eq!(
+            request(&mut stub, &mut emulator, "QStartNoAckMode"),
+            format!("+{}", packet("OK"))
+        );
+        assert_eq!(request(&mut stub, &mut emulator, "qC"), packet("QC1"));
+    }
+
+    #[test]
+    fn target_description() {
+        let (mut stub, mut emulator) = stub(&[0x12, 0x00]);
+        let mut xml = String::new();
+        loop {
+            let query = format!("qXfer:features:read:target.xml:{:x},80", xml.len());
+            let reply = request(&mut stub, &mut emulator, &query);
+            let data = &reply[2..reply.len() - 3];
+            xml += &data[1..];
+            if data.starts_with('l') {
+                break;
+            }
+        }
+        assert_eq!(xml, TARGET_XML);
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        // 0x200: V3 = 0x42, I = 0x345
+        let (mut stub, mut emulator) = stub(&[0x63, 0x42, 0xa3, 0x45]);
+        emulator.cpu.run_cycle(KEYS).unwrap();
+        emulator.cpu.run_cycle(KEYS).unwrap();
+
+        let reply = request(&mut stub, &mut emulator, "g");
+        let registers = format!("{}{}", "00".repeat(3), "42");
+        assert!(reply.starts_with(&format!("+${}", registers)));
+        assert!(reply.contains("45030402000000#"));
+        assert_eq!(
+            request(&mut stub, &mut emulator, "p11"),
+            format!("+{}", packet("0402"))
+        );
+        assert_eq!(
+            request(&mut stub, &mut emulator, "P10=0003"),
+            format!("+{}", packet("OK"))
+        );
+        assert_eq!(emulator.cpu.index(), 0x300);
+        assert_eq!(
+            request(&mut stub, &mut emulator, "P12=01"),
+            format!("+{}", packet("E01"))
+        );
+
+        assert_eq!(
+            request(&mut stub, &mut emulator, "M300,3:abcdef"),
+            format!("+{}", packet("OK"))
+        );
+        assert_eq!(
+            request(&mut stub, &mut emulator, "m2ff,5"),
+            format!("+{}", packet("00abcdef00"))
+        );
+        assert_eq!(
+            request(&mut stub, &mut emulator, "mfff,2"),
+            format!("+{}", packet("E01"))
+        );
+    }
+
+    #[test]
+    fn breakpoints_and_stepping() {
+        // 0x200: V0 += 1, store V0 at I, jump to 0x200
+        let (mut stub, mut emulator) = stub(&[0x70, 0x01, 0xf0, 0x55, 0x12, 0x00]);
+        assert_eq!(
+            request(&mut stub, &mut emulator, "s"),
+            format!("+{}", packet("S05"))
+        );
+        assert_eq!(emulator.cpu.pc(), 0x202);
+
+        request(&mut stub, &mut emulator, "Z0,204,2");
+        assert_eq!(request(&mut stub, &mut emulator, "c"), "+");
+        assert!(!stub.is_paused());
+        stub.run_frame(&mut emulator, KEYS).unwrap();
+        assert_eq!(stub.stream.outgoing, packet("S05").as_bytes());
+        stub.stream.outgoing.clear();
+        assert_eq!(emulator.cpu.pc(), 0x204);
+
+        // Write watchpoint on the byte FX55 stores
+        request(&mut stub, &mut emulator, "z0,204,2");
+        request(&mut stub, &mut emulator, "Z2,0,1");
+        request(&mut stub, &mut emulator, "c");
+        stub.run_frame(&mut emulator, KEYS).unwrap();
+        assert_eq!(stub.stream.outgoing, packet("T05watch:0;").as_bytes());
+        stub.stream.outgoing.clear();
+
+        // Interrupted while running freely
+        request(&mut stub, &mut emulator, "z2,0,1");
+        request(&mut stub, &mut emulator, "c");
+        stub.run_frame(&mut emulator, KEYS).unwrap();
+        stub.stream.incoming.push(0x03);
+        stub.poll(&mut emulator, KEYS).unwrap();
+        assert_eq!(stub.stream.outgoing, packet("S02").as_bytes());
+        assert!(stub.is_paused());
+
+        // Killed even if gdb hangs up straight after asking
+        stub.stream
+            .incoming
+            .extend_from_slice(packet("k").as_bytes());
+        stub.stream.closed = true;
+        assert_eq!(stub.poll(&mut emulator, KEYS).unwrap(), Session::Killed);
+    }
+
+    #[test]
+    fn detach() {
+        let (mut stub, mut emulator) = stub(&[0x12, 0x00]);
+        stub.stream
+            .incoming
+            .extend_from_slice(packet("D").as_bytes());
+        assert_eq!(stub.poll(&mut emulator, KEYS).unwrap(), Session::Detached);
+        assert!(!stub.is_paused());
+
+        let (mut stub, mut emulator) = self::stub(&[0x12, 0x00]);
+        stub.stream.closed = true;
+        assert_eq!(stub.poll(&mut emulator, KEYS).unwrap(), Session::Detached);
+    }
+
+    #[test]
+    fn faults() {
+        let (mut stub, mut emulator) = stub(&[0x00, 0x00]);
+        request(&mut stub, &mut emulator, "c");
+        stub.run_frame(&mut emulator, KEYS).unwrap();
+        assert_eq!(stub.stream.outgoing, packet("S04").as_bytes());
+    }
+}
@@ -0,0 +1,461 @@
+use std::fmt;
+
+use crate::platform::Platform;
+
+// A decoded opcode. X and Y are register numbers, NN and N are the
+// immediate byte and nibble, NNN is an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // 00E0
+    Clear,
+    // 00EE
+    Return,
+    // 00CN
+    ScrollDown(u8),
+    // 00DN
+    ScrollUp(u8),
+    // 00FB
+    ScrollRight,
+    // 00FC
+    ScrollLeft,
+    // 00FD
+    Exit,
+    // 00FE
+    Lores,
+    // 00FF
+    Hires,
+    // 1NNN
+    Jump(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SkipEqual(usize, u8),
+    // 4XNN
+    SkipNotEqual(usize, u8),
+    // 5XY0
+    SkipEqualRegister(usize, usize),
+    // 5XY2
+    SaveRange(usize, usize),
+    // 5XY3
+    LoadRange(usize, usize),
+    // 6XNN
+    Set(usize, u8),
+    // 7XNN
+    Add(usize, u8),
+    // 8XY0
+    Copy(usize, usize),
+    // 8XY1
+    Or(usize, usize),
+    // 8XY2
+    And(usize, usize),
+    // 8XY3
+    Xor(usize, usize),
+    // 8XY4
+    AddRegister(usize, usize),
+    // 8XY5
+    Sub(usize, usize),
+    // 8XY6
+    ShiftRight(usize, usize),
+    // 8XY7
+    SubReverse(usize, usize),
+    // 8XYE
+    ShiftLeft(usize, usize),
+    // 9XY0
+    SkipNotEqualRegister(usize, usize),
+    // ANNN
+    SetIndex(u16),
+    // BNNN, the `jump_vx` quirk reads X from the top nibble of NNN
+    JumpOffset(u16),
+    // CXNN
+    Random(usize, u8),
+    // DXYN
+    Draw(usize, usize, u8),
+    // EX9E
+    SkipKey(usize),
+    // EXA1
+    SkipNotKey(usize),
+    // F000, the address is the word after the opcode
+    SetIndexLong,
+    // FN01
+    Planes(u8),
+    // F002
+    LoadAudio,
+    // FX07
+    GetDelay(usize),
+    // FX0A
+    WaitKey(usize),
+    // FX15
+    SetDelay(usize),
+    // FX18
+    SetSound(usize),
+    // FX1E
+    AddIndex(usize),
+    // FX29
+    Font(usize),
+    // FX30
+    BigFont(usize),
+    // FX33
+    Bcd(usize),
+    // FX3A
+    Pitch(usize),
+    // FX55
+    Store(usize),
+    // FX65
+    Load(usize),
+    // FX75
+    SaveFlags(usize),
+    // FX85
+    LoadFlags(usize),
+}
+
+// An opcode that isn't an instruction on any platform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x} is not an instruction", self.opcode)
+    }
+}
+
+impl Instruction {
+    // Decodes instructions from every platform, check `platform` before
+    // running one
+    pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+        use Instruction::*;
+
+        let (op_major, x, y, n) = nibbles(opcode);
+        let nn = (opcode & 0x00ff) as u8;
+        let nnn = opcode & 0x0fff;
+        let instruction = match op_major {
+            0x0 => match opcode {
+                0x00e0 => Clear,
+                0x00ee => Return,
+                0x00c0..=0x00cf => ScrollDown(n),
+                0x00d0..=0x00df => ScrollUp(n),
+                0x00fb => ScrollRight,
+                0x00fc => ScrollLeft,
+                0x00fd => Exit,
+                0x00fe => Lores,
+                0x00ff => Hires,
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x1 => Jump(nnn),
+            0x2 => Call(nnn),
+            0x3 => SkipEqual(x, nn),
+            0x4 => SkipNotEqual(x, nn),
+            0x5 => match n {
+                0x0 => SkipEqualRegister(x, y),
+                0x2 => SaveRange(x, y),
+                0x3 => LoadRange(x, y),
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x6 => Set(x, nn),
+            0x7 => Add(x, nn),
+            0x8 => match n {
+                0x0 => Copy(x, y),
+                0x1 => Or(x, y),
+                0x2 => And(x, y),
+                0x3 => Xor(x, y),
+                0x4 => AddRegister(x, y),
+                0x5 => Sub(x, y),
+                0x6 => ShiftRight(x, y),
+                0x7 => SubReverse(x, y),
+                0xe => ShiftLeft(x, y),
+                _ => return Err(DecodeError { opcode }),
+            },
+            0x9 => match n {
+                0x0 => SkipNotEqualRegister(x, y),
+                _ => return Err(DecodeError { opcode }),
+            },
+            0xa => SetIndex(nnn),
+            0xb => JumpOffset(nnn),
+            0xc => Random(x, nn),
+            0xd => Draw(x, y, n),
+            0xe => match nn {
+                0x9e => SkipKey(x),
+                0xa1 => SkipNotKey(x),
+                _ => return Err(DecodeError { opcode }),
+            },
+            _ => match nn {
+                0x00 if x == 0 => SetIndexLong,
+                0x01 => Planes(x as u8),
+                0x02 if x == 0 => LoadAudio,
+                0x07 => GetDelay(x),
+                0x0a => WaitKey(x),
+                0x15 => SetDelay(x),
+                0x18 => SetSound(x),
+                0x1e => AddIndex(x),
+                0x29 => Font(x),
+                0x30 => BigFont(x),
+                0x33 => Bcd(x),
+                0x3a => Pitch(x),
+                0x55 => Store(x),
+                0x65 => Load(x),
+                0x75 => SaveFlags(x),
+                0x85 => LoadFlags(x),
+                _ => return Err(DecodeError { opcode }),
+            },
+        };
+        Ok(instruction)
+    }
+
+    // Packs the instruction back into an opcode. Registers and 4 bit operands
+    // must be below 0x10 and addresses below 0x1000, like `decode` makes
+    // them. Debug builds panic on anything bigger, release builds drop the
+    // extra bits so they can't spill into the neighbouring fields.
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        let nibble = |value: usize| {
+            debug_assert!(value <= 0xf, "operand {:#x} doesn't fit in 4 bits", value);
+            value as u16 & 0xf
+        };
+        let address = |nnn: u16| {
+            debug_assert!(nnn <= 0xfff, "address {:#x} doesn't fit in 12 bits", nnn);
+            nnn & 0xfff
+        };
+        let xnn = |op: u16, x: usize, nn: u8| op << 12 | nibble(x) << 8 | nn as u16;
+        let xyn = |op: u16, x: usize, y: usize, n: u8| {
+            xnn(op, x, (nibble(y) << 4 | nibble(n as usize)) as u8)
+        };
+        match *self {
+            Clear => 0x00e0,
+            Return => 0x00ee,
+            ScrollDown(n) => 0x00c0 | nibble(n as usize),
+            ScrollUp(n) => 0x00d0 | nibble(n as usize),
+            ScrollRight => 0x00fb,
+            ScrollLeft => 0x00fc,
+            Exit => 0x00fd,
+            Lores => 0x00fe,
+            Hires => 0x00ff,
+            Jump(nnn) => 0x1000 | address(nnn),
+            Call(nnn) => 0x2000 | address(nnn),
+            SkipEqual(x, nn) => xnn(0x3, x, nn),
+            SkipNotEqual(x, nn) => xnn(0x4, x, nn),
+            SkipEqualRegister(x, y) => xyn(0x5, x, y, 0x0),
+            SaveRange(x, y) => xyn(0x5, x, y, 0x2),
+            LoadRange(x, y) => xyn(0x5, x, y, 0x3),
+            Set(x, nn) => xnn(0x6, x, nn),
+            Add(x, nn) => xnn(0x7, x, nn),
+            Copy(x, y) => xyn(0x8, x, y, 0x0),
+            Or(x, y) => xyn(0x8, x, y, 0x1),
+            And(x, y) => xyn(0x8, x, y, 0x2),
+            Xor(x, y) => xyn(0x8, x, y, 0x3),
+            AddRegister(x, y) => xyn(0x8, x, y, 0x4),
+            Sub(x, y) => xyn(0x8, x, y, 0x5),
+            ShiftRight(x, y) => xyn(0x8, x, y, 0x6),
+            SubReverse(x, y) => xyn(0x8, x, y, 0x7),
+            ShiftLeft(x, y) => xyn(0x8, x, y, 0xe),
+            SkipNotEqualRegister(x, y) => xyn(0x9, x, y, 0x0),
+            SetIndex(nnn) => 0xa000 | address(nnn),
+            JumpOffset(nnn) => 0xb000 | address(nnn),
+            Random(x, nn) => xnn(0xc, x, nn),
+            Draw(x, y, n) => xyn(0xd, x, y, n),
+            SkipKey(x) => xnn(0xe, x, 0x9e),
+            SkipNotKey(x) => xnn(0xe, x, 0xa1),
+            SetIndexLong => 0xf000,
+            Planes(n) => xnn(0xf, n as usize, 0x01),
+            LoadAudio => 0xf002,
+            GetDelay(x) => xnn(0xf, x, 0x07),
+            WaitKey(x) => xnn(0xf, x, 0x0a),
+            SetDelay(x) => xnn(0xf, x, 0x15),
+            SetSound(x) => xnn(0xf, x, 0x18),
+            AddIndex(x) => xnn(0xf, x, 0x1e),
+            Font(x) => xnn(0xf, x, 0x29),
+            BigFont(x) => xnn(0xf, x, 0x30),
+            Bcd(x) => xnn(0xf, x, 0x33),
+            Pitch(x) => xnn(0xf, x, 0x3a),
+            Store(x) => xnn(0xf, x, 0x55),
+            Load(x) => xnn(0xf, x, 0x65),
+            SaveFlags(x) => xnn(0xf, x, 0x75),
+            LoadFlags(x) => xnn(0xf, x, 0x85),
+        }
+    }
+
+    // The first platform with this instruction
+    pub fn platform(&self) -> Platform {
+        use Instruction::*;
+
+        match self {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | Lores | Hires | BigFont(_)
+            | SaveFlags(_) | LoadFlags(_) => Platform::SuperChip,
+            ScrollUp(_) | SaveRange(..) | LoadRange(..) | SetIndexLong | Planes(_) | LoadAudio
+            | Pitch(_) => Platform::XoChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    // Bytes taken up in memory, F000 NNNN is the only instruction longer than 2
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::SetIndexLong => 4,
+            _ => 2,
+        }
+    }
+
+    // Whether the instruction skips the one after it when its test passes
+    pub fn is_skip(&self) -> bool {
+        use Instruction::*;
+
+        matches!(
+            self,
+            SkipEqual(..)
+                | SkipNotEqual(..)
+                | SkipEqualRegister(..)
+                | SkipNotEqualRegister(..)
+                | SkipKey(_)
+                | SkipNotKey(_)
+        )
+    }
+}
+
+// An opcode is two bytes long (four nibbles).
+//
+// /------- byte 1 -------\  /------- byte 2 -------\
+// /----n1----||----n2----\  /----n3----||----n4----\
+// / op_major ||     x    \  /    y     || op_minor \
+fn nibbles(opcode: u16) -> (u8, usize, usize, u8) {
+    let op_major = ((opcode & 0xf000) >> 12) as u8;
+    let x = ((opcode & 0x0f00) >> 8) as usize;
+    let y = ((opcode & 0x00f0) >> 4) as usize;
+    let op_minor = (opcode & 0x000f) as u8;
+
+    (op_major, x, y, op_minor)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::instruction::{DecodeError, Instruction};
+    use crate::platform::Platform;
+
+    #[test]
+    fn decode_encode_round_trip() {
+        let mut valid = 0;
+        for opcode in 0..=0xffff_u16 {
+            if let Ok(instruction) = Instruction::decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{:?}", instruction);
+                valid += 1;
+            }
+        }
+        // 10 families with 12 bits of operands, 13 with 8 bits, 18 with 4 bits
+        // and 9 fixed opcodes
+        assert_eq!(valid, 10 * 4096 + 13 * 256 + 18 * 16 + 9);
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        use Instruction::*;
+
+        // Instructions built from random operands come back unchanged
+        let mut rng = StdRng::seed_from_u64(0x0c8);
+        for _ in 0..10_000 {
+            let x = rng.gen_range(0, 16);
+            let y = rng.gen_range(0, 16);
+            let n = rng.gen_range(0, 16);
+            let nn = rng.gen();
+            let nnn = rng.gen_range(0, 0x1000);
+            let instructions = [
+                ScrollDown(n),
+                ScrollUp(n),
+                Jump(nnn),
+                Call(nnn),
+                SkipEqual(x, nn),
+                SkipNotEqual(x, nn),
+                SkipEqualRegister(x, y),
+                SaveRange(x, y),
+                LoadRange(x, y),
+                Set(x, nn),
+                Add(x, nn),
+                Copy(x, y),
+                Or(x, y),
+                And(x, y),
+                Xor(x, y),
+                AddRegister(x, y),
+                Sub(x, y),
+                ShiftRight(x, y),
+                SubReverse(x, y),
+                ShiftLeft(x, y),
+                SkipNotEqualRegister(x, y),
+                SetIndex(nnn),
+                JumpOffset(nnn),
+                Random(x, nn),
+                Draw(x, y, n),
+                SkipKey(x),
+                SkipNotKey(x),
+                Planes(n),
+                GetDelay(x),
+                WaitKey(x),
+                SetDelay(x),
+                SetSound(x),
+                AddIndex(x),
+                Font(x),
+                BigFont(x),
+                Bcd(x),
+                Pitch(x),
+                Store(x),
+                Load(x),
+                SaveFlags(x),
+                LoadFlags(x),
+            ];
+            for instruction in instructions.iter() {
+                assert_eq!(Instruction::decode(instruction.encode()), Ok(*instruction));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "doesn't fit in 12 bits")]
+    fn encode_out_of_range_address() {
+        Instruction::Jump(0x1234).encode();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "doesn't fit in 4 bits")]
+    fn encode_out_of_range_register() {
+        Instruction::Xor(0x10, 0).encode();
+    }
+
+    #[test]
+    fn decoding() {
+        assert_eq!(Instruction::decode(0xd125), Ok(Instruction::Draw(1, 2, 5)));
+        assert_eq!(
+            Instruction::decode(0xb3a0),
+            Ok(Instruction::JumpOffset(0x3a0))
+        );
+        assert_eq!(Instruction::decode(0xf000), Ok(Instruction::SetIndexLong));
+        assert_eq!(Instruction::decode(0xf201), Ok(Instruction::Planes(2)));
+        for opcode in [
+            0x0000, 0x00e1, 0x5121, 0x800f, 0x9001, 0xe000, 0xf100, 0xf1ff,
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Instruction::decode(*opcode),
+                Err(DecodeError { opcode: *opcode })
+            );
+        }
+        assert_eq!(
+            DecodeError { opcode: 0x5121 }.to_string(),
+            "5121 is not an instruction"
+        );
+    }
+
+    #[test]
+    fn platforms() {
+        assert_eq!(Instruction::Clear.platform(), Platform::Chip8);
+        assert_eq!(Instruction::Hires.platform(), Platform::SuperChip);
+        assert_eq!(Instruction::BigFont(0).platform(), Platform::SuperChip);
+        assert_eq!(Instruction::ScrollUp(1).platform(), Platform::XoChip);
+        assert_eq!(Instruction::SetIndexLong.size(), 4);
+        assert_eq!(Instruction::Clear.size(), 2);
+        assert!(Instruction::SkipKey(0).is_skip());
+        assert!(!Instruction::Jump(0).is_skip());
+    }
+}
@@ -0,0 +1,29 @@
+pub mod asm;
+pub mod audio;
+mod bytes;
+pub mod config;
+pub mod debugger;
+pub mod disasm;
+pub mod drivers;
+pub mod dump;
+pub mod emulator;
+pub mod font;
+pub mod gdb;
+pub mod hash;
+pub mod instruction;
+pub mod movie;
+pub mod palette;
+pub mod platform;
+pub mod processor;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod savestate;
+pub mod timer;
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+// SUPER-CHIP high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+pub const PIXEL_SCALE: usize = 10;
@@ -1,41 +1,170 @@
+use std::{
+    io::{self, BufRead, Write},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
 use winit::{
     dpi::LogicalSize,
-    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event::{
+        DeviceEvent, ElementState, Event, KeyboardInput, ModifiersState, VirtualKeyCode,
+        WindowEvent,
+    },
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 
-mod processor;
-use processor::Processor;
-mod drivers;
-mod font;
-use drivers::{display::Display, input::get_keys};
+use chip_8::{
+    audio::{Audio, AudioSink, WavSink, SAMPLE_RATE},
+    config::{Config, ConfigError, USAGE},
+    debugger::{Command, Debugger},
+    drivers::{display::Display, input::get_keys},
+    emulator::Emulator,
+    gdb::{self, Session},
+    movie::{Movie, Playback},
+    processor::Processor,
+    rewind::Rewind,
+    savestate,
+    timer::{TimerSource, TIMER_HZ},
+    HEIGHT, WIDTH,
+};
 
-pub const WIDTH: usize = 64;
-pub const HEIGHT: usize = 32;
-pub const PIXEL_SCALE: usize = 10;
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / TIMER_HZ as u64);
 
 fn main() {
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(ConfigError::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+    // Stepping and pausing break the frame timing movies rely on
+    let debugging = config.debug || config.gdb.is_some();
+    if debugging && (config.record.is_some() || config.play.is_some()) {
+        eprintln!(
+            "--debug and --gdb can't be used with --record or --play\n\n{}",
+            USAGE
+        );
+        std::process::exit(2);
+    }
+    if config.debug && config.gdb.is_some() {
+        eprintln!("--debug can't be used with --gdb\n\n{}", USAGE);
+        std::process::exit(2);
+    }
+
+    let movie = config.play.as_ref().map(|path| match Movie::load(path) {
+        Ok(movie) => movie,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    });
+    // A movie from power on would replay over the loaded state and desync
+    if movie.as_ref().is_some_and(|movie| movie.start.is_none()) && config.load_state.is_some() {
+        eprintln!(
+            "--load-state can't be used with a movie recorded from power on\n\n{}",
+            USAGE
+        );
+        std::process::exit(2);
+    }
+
+    let mut chip8 = Processor::initialize();
+    chip8.set_platform(
+        movie
+            .as_ref()
+            .map_or(config.platform, |movie| movie.platform),
+    );
+    chip8.set_quirks(config.quirks);
+    chip8.set_seed(config.seed.unwrap_or_else(rand::random));
+    if let Err(err) = chip8.load_rom_file(&config.rom) {
+        eprintln!("{}: {}", config.rom.display(), err);
+        std::process::exit(1);
+    }
+    if let Some(path) = &config.load_state {
+        if let Err(err) = chip8.load_state_file(path, config.force_state) {
+            eprintln!("{}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+    if let (Some(movie), Some(path)) = (&movie, &config.play) {
+        if let Err(err) = movie.start(&mut chip8) {
+            eprintln!("{}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+
+    // Movies only replay the same if every frame runs the same number of
+    // instructions and timer ticks
+    let speed = movie.as_ref().map_or(config.speed, |movie| movie.speed);
+    let timers = match movie.is_some() || config.record.is_some() {
+        true => TimerSource::Cycles { cpu_hz: speed },
+        false => config.timers,
+    };
+    // Recording on from a movie records its frames again as they play
+    let mut recording = config.record.as_ref().map(|_| match &movie {
+        Some(movie) => {
+            let mut movie = movie.clone();
+            movie.truncate(0);
+            movie
+        }
+        None => Movie::new(&chip8, speed, config.load_state.is_none()),
+    });
+    let mut playback = movie.map(Playback::new);
+
+    let mut emulator = Emulator::new(chip8, speed, timers);
+    let mut rewind = Rewind::new((config.rewind * TIMER_HZ) as usize);
+    rewind.record(&emulator.cpu);
+
+    let mut debugger = match config.debug {
+        true => {
+            println!("Paused, type help for a list of commands");
+            prompt();
+            Some((Debugger::new(), read_commands()))
+        }
+        false => None,
+    };
+    let mut gdb = config.gdb.map(|port| {
+        println!("Waiting for gdb to connect to port {}", port);
+        match gdb::listen(port) {
+            Ok(stub) => stub,
+            Err(err) => {
+                eprintln!("gdb: {}", err);
+                std::process::exit(1);
+            }
+        }
+    });
+
+    let mut sink = open_audio(&config);
+    let sample_rate = sink.as_ref().map_or(SAMPLE_RATE, |(_, rate)| *rate);
+    let mut audio = Audio::new(sample_rate);
+    audio.set_tone(config.tone);
+    audio.set_volume(config.volume);
+    audio.set_muted(config.mute);
+
     let event_loop = EventLoop::new();
 
     let window = WindowBuilder::new()
         .with_title("Chip-8 Emulator")
         .with_inner_size(LogicalSize::new(
-            (WIDTH * PIXEL_SCALE) as f64,
-            (HEIGHT * PIXEL_SCALE) as f64,
+            (WIDTH * config.scale) as f64,
+            (HEIGHT * config.scale) as f64,
         ))
         .build(&event_loop)
         .expect("Could not create window.");
 
-    let mut display = Display::new(&window);
+    let mut display = Display::new(&window, config.scale, config.palette);
 
-    let mut chip8 = Processor::initialize();
-
-    let mut keys = [0; 16];
+    let mut keys = [false; 16];
+    let mut modifiers = ModifiersState::default();
+    let mut rewinding = false;
+    let mut next_frame = Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;
-
         match event {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
@@ -48,6 +177,67 @@ fn main() {
                         },
                     ..
                 } => *control_flow = ControlFlow::Exit,
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::M),
+                            state: ElementState::Released,
+                            ..
+                        },
+                    ..
+                } => audio.toggle_mute(),
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(VirtualKeyCode::Back),
+                            state,
+                            ..
+                        },
+                    ..
+                } => {
+                    rewinding = state == ElementState::Pressed;
+                    if rewinding && (recording.is_some() || playback.is_some()) {
+                        eprintln!("Can't rewind during a movie");
+                        rewinding = false;
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(code),
+                            state: ElementState::Released,
+                            ..
+                        },
+                    ..
+                } if slot_key(code).is_some() => {
+                    let slot = slot_key(code).unwrap();
+                    match modifiers.shift() {
+                        true => match savestate::save_slot(&emulator.cpu, &config.rom, slot) {
+                            Ok(path) => println!("Saved {}", path.display()),
+                            Err(err) => eprintln!("Slot {}: {}", slot, err),
+                        },
+                        false if recording.is_some() || playback.is_some() => {
+                            eprintln!("Can't load a state during a movie")
+                        }
+                        false => {
+                            match savestate::load_slot(
+                                &mut emulator.cpu,
+                                &config.rom,
+                                slot,
+                                config.force_state,
+                            ) {
+                                Ok(path) => println!("Loaded {}", path.display()),
+                                Err(err) => eprintln!("Slot {}: {}", slot, err),
+                            }
+                            // Don't rewind across a loaded state
+                            rewind.clear();
+                            rewind.record(&emulator.cpu);
+                            if emulator.cpu.take_draw_flag() {
+                                window.request_redraw();
+                            }
+                        }
+                    }
+                }
                 WindowEvent::KeyboardInput {
                     input:
                         KeyboardInput {
@@ -59,12 +249,206 @@ fn main() {
                 } => get_keys(virtual_keycode, state, &mut keys),
                 _ => (),
             },
+            Event::DeviceEvent {
+                event: DeviceEvent::ModifiersChanged(state),
+                ..
+            } => modifiers = state,
+            Event::MainEventsCleared => {
+                let now = Instant::now();
+                if now >= next_frame {
+                    if let Some((debugger, commands)) = &mut debugger {
+                        while let Ok(line) = commands.try_recv() {
+                            match debugger.parse(&line) {
+                                Ok(Some(Command::Quit)) => {
+                                    *control_flow = ControlFlow::Exit;
+                                    return;
+                                }
+                                Ok(Some(command)) => {
+                                    print!("{}", debugger.execute(&mut emulator, keys, command))
+                                }
+                                Ok(None) => (),
+                                Err(err) => println!("{}", err),
+                            }
+                            prompt();
+                        }
+                    }
+                    if let Some(stub) = &mut gdb {
+                        match stub.poll(&mut emulator, keys) {
+                            Ok(Session::Attached) => (),
+                            Ok(Session::Detached) => {
+                                println!("gdb detached");
+                                gdb = None;
+                            }
+                            Ok(Session::Killed) => {
+                                *control_flow = ControlFlow::Exit;
+                                return;
+                            }
+                            Err(err) => {
+                                eprintln!("gdb: {}", err);
+                                gdb = None;
+                            }
+                        }
+                    }
+                    let paused = debugger
+                        .as_ref()
+                        .is_some_and(|(debugger, _)| debugger.is_paused())
+                        || gdb.as_ref().is_some_and(|stub| stub.is_paused());
+                    match rewinding {
+                        // Stays on the oldest frame once the buffer runs out
+                        true => {
+                            rewind.step_back(&mut emulator.cpu);
+                        }
+                        false if paused => (),
+                        false => {
+                            let keys = match playback.as_ref().and_then(Playback::keys) {
+                                Some(movie_keys) => movie_keys,
+                                None => {
+                                    if let Some(playback) = playback.take() {
+                                        println!("Movie over after {} frames", playback.frame());
+                                    }
+                                    keys
+                                }
+                            };
+                            match (&mut debugger, &mut gdb) {
+                                // Faults pause the debugger instead of exiting
+                                (Some((debugger, _)), _) => {
+                                    if let Some(message) = debugger.run_frame(&mut emulator, keys) {
+                                        print!("{}", message);
+                                        prompt();
+                                    }
+                                }
+                                (None, Some(stub)) => {
+                                    if let Err(err) = stub.run_frame(&mut emulator, keys) {
+                                        eprintln!("gdb: {}", err);
+                                        gdb = None;
+                                    }
+                                }
+                                (None, None) => {
+                                    if let Err(err) = emulator.run_frame(keys) {
+                                        eprintln!("{}", err);
+                                        *control_flow = ControlFlow::Exit;
+                                        return;
+                                    }
+                                }
+                            }
+                            if let Some(movie) = &mut recording {
+                                movie.record_frame(keys, &emulator.cpu);
+                            }
+                            if let Some(movie) = &mut playback {
+                                if let Err(err) = movie.advance(&emulator.cpu) {
+                                    eprintln!("{}", err);
+                                    playback = None;
+                                }
+                            }
+                            rewind.record(&emulator.cpu);
+                            if let Some((sink, _)) = &mut sink {
+                                audio.render_frame(&emulator.cpu, sink.as_mut());
+                            }
+                        }
+                    }
+                    if emulator.cpu.take_draw_flag() {
+                        window.request_redraw();
+                    }
+                    if emulator.cpu.has_exited() {
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+
+                    next_frame += FRAME;
+                    // Don't try to catch up after falling behind, e.g. while
+                    // the window was being dragged
+                    if next_frame < now {
+                        next_frame = now + FRAME;
+                    }
+                }
+                *control_flow = ControlFlow::WaitUntil(next_frame);
+            }
             Event::RedrawRequested(_) => {
-                display.draw([0; 2048]);
+                display.draw(emulator.cpu.vram(), emulator.cpu.resolution());
+            }
+            Event::LoopDestroyed => {
+                // Make sure a wav file gets finished before the process exits
+                drop(sink.take());
+                if let (Some(movie), Some(path)) = (&recording, &config.record) {
+                    match movie.save(path) {
+                        Ok(()) => println!(
+                            "Recorded {} frames to {}",
+                            movie.frame_count(),
+                            path.display()
+                        ),
+                        Err(err) => eprintln!("{}: {}", path.display(), err),
+                    }
+                }
             }
             _ => (),
         }
+    });
+}
 
-        // window.request_redraw();
+// Reads debugger commands on another thread so the window keeps running
+// while waiting for them
+fn read_commands() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
     });
+    receiver
+}
+
+fn prompt() {
+    print!("(chip-8) ");
+    let _ = io::stdout().flush();
+}
+
+// Maps F1-F9 to save state slots 1-9
+fn slot_key(code: VirtualKeyCode) -> Option<u8> {
+    match code {
+        VirtualKeyCode::F1 => Some(1),
+        VirtualKeyCode::F2 => Some(2),
+        VirtualKeyCode::F3 => Some(3),
+        VirtualKeyCode::F4 => Some(4),
+        VirtualKeyCode::F5 => Some(5),
+        VirtualKeyCode::F6 => Some(6),
+        VirtualKeyCode::F7 => Some(7),
+        VirtualKeyCode::F8 => Some(8),
+        VirtualKeyCode::F9 => Some(9),
+        _ => None,
+    }
+}
+
+// Picks where the sound goes, returns the sink and its sample rate
+fn open_audio(config: &Config) -> Option<(Box<dyn AudioSink>, u32)> {
+    match &config.wav {
+        Some(path) => match WavSink::create(path, SAMPLE_RATE) {
+            Ok(sink) => Some((Box::new(sink), SAMPLE_RATE)),
+            Err(err) => {
+                eprintln!("{}: {}", path.display(), err);
+                None
+            }
+        },
+        None => open_speaker(),
+    }
+}
+
+#[cfg(feature = "speaker")]
+fn open_speaker() -> Option<(Box<dyn AudioSink>, u32)> {
+    match chip_8::drivers::speaker::Speaker::new() {
+        Ok(speaker) => {
+            let sample_rate = speaker.sample_rate();
+            Some((Box::new(speaker), sample_rate))
+        }
+        Err(err) => {
+            eprintln!("No sound: {}", err);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "speaker"))]
+fn open_speaker() -> Option<(Box<dyn AudioSink>, u32)> {
+    None
 }
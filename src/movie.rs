@@ -0,0 +1,435 @@
+use std::{error::Error, fmt, fs, io, path::Path};
+
+use crate::{
+    bytes::{Malformed, Reader},
+    hash::fnv1a,
+    platform::Platform,
+    processor::{keys_from_bits, keys_to_bits, Processor, StateError},
+    quirks::Quirks,
+};
+
+// Movie layout, all numbers little endian:
+//
+// magic "C8MV", version u16, rom hash u64, seed u64, speed u32, platform id
+// u8, quirks bits u8, start state length u32 and the save state (empty when
+// the movie starts from power on), frame count u32 and a u16 keypad bitmask
+// per frame, checkpoint count u32 and a (frame u32, state hash u64) pair per
+// checkpoint
+const MAGIC: &[u8; 4] = b"C8MV";
+const MOVIE_VERSION: u16 = 1;
+
+// Frames between the state hashes used to spot desyncs
+pub const CHECKPOINT_FRAMES: u32 = 60;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io(io::Error),
+    NotAMovie,
+    UnsupportedVersion(u16),
+    // Recorded while running a different rom
+    WrongRom {
+        expected: u64,
+        found: u64,
+    },
+    Corrupt,
+    // The start state couldn't be loaded
+    State(StateError),
+    // Playback no longer matches the recording
+    Desync {
+        frame: u32,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io(err) => write!(f, "{}", err),
+            MovieError::NotAMovie => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "unsupported movie version {}", version)
+            }
+            MovieError::WrongRom { expected, found } => write!(
+                f,
+                "movie is for rom {:016x}, not the running rom {:016x}",
+                expected, found
+            ),
+            MovieError::Corrupt => write!(f, "movie is corrupt"),
+            MovieError::State(err) => write!(f, "start state: {}", err),
+            MovieError::Desync {
+                frame,
+                expected,
+                found,
+            } => write!(
+                f,
+                "desync at frame {}: state hash {:016x}, recorded {:016x}",
+                frame, found, expected
+            ),
+        }
+    }
+}
+
+impl Error for MovieError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MovieError::Io(err) => Some(err),
+            MovieError::State(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MovieError {
+    fn from(err: io::Error) -> Self {
+        MovieError::Io(err)
+    }
+}
+
+impl From<Malformed> for MovieError {
+    fn from(_: Malformed) -> Self {
+        MovieError::Corrupt
+    }
+}
+
+impl From<StateError> for MovieError {
+    fn from(err: StateError) -> Self {
+        MovieError::State(err)
+    }
+}
+
+// A recorded play session. Replaying the same keys from the same start
+// through an `Emulator` at the same speed gives the same frames, as long as
+// the timers are driven by cycles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub seed: u64,
+    // Instructions per second
+    pub speed: u32,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    // Save state the movie starts from, None for power on
+    pub start: Option<Vec<u8>>,
+    // Keypad bitmask for every frame
+    pub frames: Vec<u16>,
+    // State hash after the frame with that number
+    checkpoints: Vec<(u32, u64)>,
+}
+
+impl Movie {
+    // Starts recording from `cpu`'s current state. Leave out the state with
+    // `from_power_on` when the rom has only just been loaded.
+    pub fn new(cpu: &Processor, speed: u32, from_power_on: bool) -> Self {
+        Movie {
+            rom_hash: cpu.rom_hash(),
+            seed: cpu.seed(),
+            speed,
+            platform: cpu.platform(),
+            quirks: cpu.quirks(),
+            start: match from_power_on {
+                true => None,
+                false => Some(cpu.save_state()),
+            },
+            frames: Vec::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    // Number of recorded frames
+    pub fn frame_count(&self) -> u32 {
+        self.frames.len() as u32
+    }
+
+    // Adds a frame that was run with `keys` held, leaving `cpu` as it is
+    pub fn record_frame(&mut self, keys: [bool; 16], cpu: &Processor) {
+        self.frames.push(keys_to_bits(keys));
+        let frame = self.frame_count();
+        if frame % CHECKPOINT_FRAMES == 0 {
+            self.checkpoints.push((frame, state_hash(cpu)));
+        }
+    }
+
+    // Keys held during the frame after `frame` frames have been played
+    pub fn keys(&self, frame: u32) -> Option<[bool; 16]> {
+        self.frames
+            .get(frame as usize)
+            .map(|&bits| keys_from_bits(bits))
+    }
+
+    // Drops everything after the first `frames` frames
+    pub fn truncate(&mut self, frames: u32) {
+        self.frames.truncate(frames as usize);
+        self.checkpoints.retain(|&(frame, _)| frame <= frames);
+    }
+
+    // Sets up `cpu`, which has the rom loaded, to play the movie from the
+    // start
+    pub fn start(&self, cpu: &mut Processor) -> Result<(), MovieError> {
+        if cpu.rom_hash() != self.rom_hash {
+            return Err(MovieError::WrongRom {
+                expected: self.rom_hash,
+                found: cpu.rom_hash(),
+            });
+        }
+        cpu.set_platform(self.platform);
+        cpu.set_quirks(self.quirks);
+        cpu.set_seed(self.seed);
+        if let Some(state) = &self.start {
+            cpu.load_state(state, false)?;
+        }
+        Ok(())
+    }
+
+    // Compares `cpu` to the recording after `frame` frames have been played
+    pub fn check(&self, frame: u32, cpu: &Processor) -> Result<(), MovieError> {
+        let expected = match self.checkpoints.iter().find(|(f, _)| *f == frame) {
+            Some(&(_, hash)) => hash,
+            None => return Ok(()),
+        };
+        match state_hash(cpu) {
+            found if found == expected => Ok(()),
+            found => Err(MovieError::Desync {
+                frame,
+                expected,
+                found,
+            }),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&MOVIE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.rom_hash.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.speed.to_le_bytes());
+        bytes.push(self.platform.id());
+        bytes.push(self.quirks.to_bits());
+        let start = self.start.as_deref().unwrap_or(&[]);
+        bytes.extend_from_slice(&(start.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(start);
+        bytes.extend_from_slice(&self.frame_count().to_le_bytes());
+        for keys in self.frames.iter() {
+            bytes.extend_from_slice(&keys.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.checkpoints.len() as u32).to_le_bytes());
+        for (frame, hash) in self.checkpoints.iter() {
+            bytes.extend_from_slice(&frame.to_le_bytes());
+            bytes.extend_from_slice(&hash.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, MovieError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(MovieError::NotAMovie);
+        }
+        let version = reader.u16()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let rom_hash = reader.u64()?;
+        let seed = reader.u64()?;
+        let speed = reader.u32()?;
+        let platform = Platform::from_id(reader.u8()?).ok_or(MovieError::Corrupt)?;
+        let quirks = Quirks::from_bits(reader.u8()?);
+        let start = match reader.u32()? as usize {
+            0 => None,
+            len => Some(reader.take(len)?.to_vec()),
+        };
+        let mut frames = Vec::new();
+        for _ in 0..reader.u32()? {
+            frames.push(reader.u16()?);
+        }
+        let mut checkpoints = Vec::new();
+        for _ in 0..reader.u32()? {
+            checkpoints.push((reader.u32()?, reader.u64()?));
+        }
+        if !reader.is_empty() || speed == 0 {
+            return Err(MovieError::Corrupt);
+        }
+
+        Ok(Movie {
+            rom_hash,
+            seed,
+            speed,
+            platform,
+            quirks,
+            start,
+            frames,
+            checkpoints,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MovieError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Movie, MovieError> {
+        Movie::from_bytes(&fs::read(path)?)
+    }
+}
+
+// Feeds a movie's keys to the emulator one frame at a time
+pub struct Playback {
+    movie: Movie,
+    // Frames played so far
+    frame: u32,
+}
+
+impl Playback {
+    pub fn new(movie: Movie) -> Self {
+        Playback { movie, frame: 0 }
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    // Keys for the next frame, None once the movie is over
+    pub fn keys(&self) -> Option<[bool; 16]> {
+        self.movie.keys(self.frame)
+    }
+
+    // Moves on after a frame has been run with `keys`, checking `cpu` still
+    // matches the recording
+    pub fn advance(&mut self, cpu: &Processor) -> Result<(), MovieError> {
+        self.frame += 1;
+        self.movie.check(self.frame, cpu)
+    }
+}
+
+fn state_hash(cpu: &Processor) -> u64 {
+    fnv1a(&cpu.save_state())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::Emulator;
+    use crate::movie::{Movie, MovieError, Playback, CHECKPOINT_FRAMES};
+    use crate::platform::Platform;
+    use crate::processor::Processor;
+    use crate::quirks;
+    use crate::timer::TimerSource;
+
+    const SPEED: u32 = 600;
+
+    // Draws a random sprite at the position of the held key, forever
+    const ROM: [u8; 12] = [
+        0xf0, 0x0a, // v0 = wait for key
+        0xc1, 0x0f, // v1 = random & 0x0f
+        0xf1, 0x29, // I = font sprite v1
+        0xd0, 0x05, // draw
+        0x00, 0xe0, // clear
+        0x12, 0x00, // jump 0x200
+    ];
+
+    fn emulator(cpu: Processor, speed: u32) -> Emulator {
+        Emulator::new(cpu, speed, TimerSource::Cycles { cpu_hz: speed })
+    }
+
+    fn fresh_cpu() -> Processor {
+        let mut cpu = Processor::initialize();
+        cpu.set_platform(Platform::SuperChip);
+        cpu.set_quirks(quirks::SUPER_CHIP);
+        cpu.set_seed(7);
+        cpu.load_rom(&ROM).unwrap();
+        cpu
+    }
+
+    // Records `frames` frames where the held key changes every few frames
+    fn record(cpu: Processor, from_power_on: bool, frames: u32) -> (Movie, Processor) {
+        let mut movie = Movie::new(&cpu, SPEED, from_power_on);
+        let mut emulator = emulator(cpu, SPEED);
+        for frame in 0..frames {
+            let mut keys = [false; 16];
+            keys[(frame / 3 % 16) as usize] = frame % 3 != 0;
+            emulator.run_frame(keys).unwrap();
+            movie.record_frame(keys, &emulator.cpu);
+        }
+        (movie, emulator.cpu)
+    }
+
+    fn play(movie: &Movie) -> Result<Processor, MovieError> {
+        let mut cpu = Processor::initialize();
+        cpu.load_rom(&ROM).unwrap();
+        movie.start(&mut cpu)?;
+        let mut emulator = emulator(cpu, movie.speed);
+        let mut playback = Playback::new(movie.clone());
+        while let Some(keys) = playback.keys() {
+            emulator.run_frame(keys).unwrap();
+            playback.advance(&emulator.cpu)?;
+        }
+        assert_eq!(playback.frame(), movie.frame_count());
+        Ok(emulator.cpu)
+    }
+
+    #[test]
+    fn playback_matches_recording() {
+        let (movie, recorded) = record(fresh_cpu(), true, CHECKPOINT_FRAMES * 3);
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.frame_count(), CHECKPOINT_FRAMES * 3);
+        assert_eq!(movie.platform, Platform::SuperChip);
+
+        let played = play(&movie).unwrap();
+        assert_eq!(played.save_state(), recorded.save_state());
+        assert_eq!(played.vram()[..], recorded.vram()[..]);
+    }
+
+    #[test]
+    fn starts_from_save_state() {
+        let (_, cpu) = record(fresh_cpu(), true, 10);
+        let (movie, recorded) = record(cpu, false, CHECKPOINT_FRAMES);
+        assert!(movie.start.is_some());
+
+        let played = play(&movie).unwrap();
+        assert_eq!(played.save_state(), recorded.save_state());
+    }
+
+    #[test]
+    fn truncate() {
+        let (mut movie, _) = record(fresh_cpu(), true, CHECKPOINT_FRAMES * 2);
+        let (short, recorded) = record(fresh_cpu(), true, CHECKPOINT_FRAMES + 5);
+        movie.truncate(CHECKPOINT_FRAMES + 5);
+        assert_eq!(movie, short);
+        assert_eq!(play(&movie).unwrap().save_state(), recorded.save_state());
+    }
+
+    #[test]
+    fn detects_desync() {
+        let (mut movie, _) = record(fresh_cpu(), true, CHECKPOINT_FRAMES * 2);
+        // Playing it back with another seed gives different sprites
+        movie.seed += 1;
+        assert!(matches!(
+            play(&movie),
+            Err(MovieError::Desync { frame, .. }) if frame == CHECKPOINT_FRAMES
+        ));
+    }
+
+    #[test]
+    fn bad_movies() {
+        let (movie, _) = record(fresh_cpu(), true, 5);
+        let mut cpu = Processor::initialize();
+        cpu.load_rom(&[0x12, 0x00]).unwrap();
+        match movie.start(&mut cpu) {
+            Err(MovieError::WrongRom { expected, found }) => {
+                assert_eq!(expected, fresh_cpu().rom_hash());
+                assert_eq!(found, cpu.rom_hash());
+            }
+            _ => panic!("started a movie on the wrong rom"),
+        }
+
+        let bytes = movie.to_bytes();
+        assert!(matches!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(MovieError::Corrupt)
+        ));
+        assert!(matches!(
+            Movie::from_bytes(b"C8ST"),
+            Err(MovieError::NotAMovie)
+        ));
+    }
+}
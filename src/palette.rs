@@ -0,0 +1,115 @@
+// Colors used to draw the screen, each is [R, G, B]. XO-CHIP pixels can be
+// set in plane 1 (`foreground`), plane 2 (`fill2`) or both (`blend`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub background: [u8; 3],
+    pub foreground: [u8; 3],
+    pub fill2: [u8; 3],
+    pub blend: [u8; 3],
+}
+
+pub const CLASSIC: Palette = Palette {
+    background: [0x00, 0x00, 0x00],
+    foreground: [0xff, 0xff, 0xff],
+    fill2: [0xaa, 0xaa, 0xaa],
+    blend: [0x55, 0x55, 0x55],
+};
+
+pub const GREEN_PHOSPHOR: Palette = Palette {
+    background: [0x0a, 0x1a, 0x0a],
+    foreground: [0x33, 0xff, 0x66],
+    fill2: [0x1a, 0x99, 0x40],
+    blend: [0x99, 0xff, 0xb3],
+};
+
+pub const AMBER: Palette = Palette {
+    background: [0x1a, 0x10, 0x00],
+    foreground: [0xff, 0xb0, 0x00],
+    fill2: [0x99, 0x60, 0x00],
+    blend: [0xff, 0xd8, 0x80],
+};
+
+pub const LCD: Palette = Palette {
+    background: [0x9b, 0xbc, 0x0f],
+    foreground: [0x0f, 0x38, 0x0f],
+    fill2: [0x8b, 0xac, 0x0f],
+    blend: [0x30, 0x62, 0x30],
+};
+
+pub const HIGH_CONTRAST: Palette = Palette {
+    background: [0x00, 0x00, 0x00],
+    foreground: [0xff, 0xff, 0x00],
+    fill2: [0x00, 0xff, 0xff],
+    blend: [0xff, 0x00, 0xff],
+};
+
+pub const PRESETS: [(&str, Palette); 5] = [
+    ("classic", CLASSIC),
+    ("green", GREEN_PHOSPHOR),
+    ("amber", AMBER),
+    ("lcd", LCD),
+    ("high-contrast", HIGH_CONTRAST),
+];
+
+impl Default for Palette {
+    fn default() -> Self {
+        CLASSIC
+    }
+}
+
+impl Palette {
+    pub fn preset(name: &str) -> Option<Palette> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, palette)| *palette)
+    }
+
+    // Color of a vram cell
+    pub fn color(&self, pixel: u8) -> [u8; 3] {
+        match pixel & 0b11 {
+            0b00 => self.background,
+            0b01 => self.foreground,
+            0b10 => self.fill2,
+            _ => self.blend,
+        }
+    }
+}
+
+// Parses a `RRGGBB` hex color, with or without a leading `#`
+pub fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::{parse_color, Palette, AMBER};
+
+    #[test]
+    fn presets() {
+        assert_eq!(Palette::preset("amber"), Some(AMBER));
+        assert_eq!(Palette::preset("purple"), None);
+    }
+
+    #[test]
+    fn plane_colors() {
+        let palette = Palette::default();
+        assert_eq!(palette.color(0), palette.background);
+        assert_eq!(palette.color(1), palette.foreground);
+        assert_eq!(palette.color(2), palette.fill2);
+        assert_eq!(palette.color(3), palette.blend);
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#ff8000"), Some([0xff, 0x80, 0x00]));
+        assert_eq!(parse_color("0A0b0C"), Some([0x0a, 0x0b, 0x0c]));
+        assert_eq!(parse_color("fff"), None);
+        assert_eq!(parse_color("gg0000"), None);
+    }
+}
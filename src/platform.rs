@@ -0,0 +1,59 @@
+use crate::quirks::{self, Quirks};
+
+// The instruction set a rom is written for, each one extends the last
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+pub const PLATFORMS: [(&str, Platform); 3] = [
+    ("chip8", Platform::Chip8),
+    ("schip", Platform::SuperChip),
+    ("xochip", Platform::XoChip),
+];
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Platform> {
+        PLATFORMS
+            .iter()
+            .find(|(platform, _)| *platform == name)
+            .map(|(_, platform)| *platform)
+    }
+
+    // Number identifying the platform in files
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Platform> {
+        PLATFORMS
+            .iter()
+            .map(|(_, platform)| *platform)
+            .find(|platform| platform.id() == id)
+    }
+
+    // The quirks roms for this platform usually expect
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => quirks::MODERN,
+            Platform::SuperChip => quirks::SUPER_CHIP,
+            Platform::XoChip => quirks::MODERN,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::platform::Platform;
+
+    #[test]
+    fn from_name() {
+        assert_eq!(Platform::from_name("chip8"), Some(Platform::Chip8));
+        assert_eq!(Platform::from_name("schip"), Some(Platform::SuperChip));
+        assert_eq!(Platform::from_name("xochip"), Some(Platform::XoChip));
+        assert_eq!(Platform::from_name("xo-chip"), None);
+    }
+}
@@ -1,10 +1,129 @@
 #![allow(dead_code)]
-use rand::Rng;
+use std::{error::Error, fmt, fs, io, path::Path};
 
-use crate::{font::FONT_STANDARD, HEIGHT, WIDTH};
+#[cfg(test)]
+mod conformance;
+mod state;
+mod watch;
+pub use state::StateError;
+pub use watch::{Watch, WatchHit};
+
+use crate::{
+    font::{FONT_BIG, FONT_STANDARD},
+    hash::fnv1a,
+    instruction::Instruction,
+    platform::Platform,
+    quirks::Quirks,
+    random::{RandomSource, SplitMix64, DEFAULT_SEED},
+    HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH,
+};
+
+pub const RAM: usize = 4096;
+// XO-CHIP can address all of this, other platforms only use the first `RAM` bytes
+pub const XO_RAM: usize = 0x10000;
+// Big enough for the SUPER-CHIP high resolution mode, low resolution only
+// uses the start of it
+pub const VRAM: usize = HIRES_WIDTH * HIRES_HEIGHT;
+// Plays the audio pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+// The big font is stored straight after the standard font
+const FONT_BIG_START: usize = FONT_STANDARD.len();
+// Programs are loaded (and start executing) here, the space below is reserved
+// for the interpreter and the font
+pub const PROGRAM_START: usize = 0x200;
+pub const MAX_ROM_SIZE: usize = RAM - PROGRAM_START;
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error),
+    Empty,
+    TooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(err) => write!(f, "could not read rom: {}", err),
+            RomError::Empty => write!(f, "rom is empty"),
+            RomError::TooLarge { size, max } => write!(
+                f,
+                "rom is {} bytes but only {} bytes fit in memory",
+                size, max
+            ),
+        }
+    }
+}
+
+impl Error for RomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RomError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(err: io::Error) -> Self {
+        RomError::Io(err)
+    }
+}
+
+// Faults raised by a running program, `pc` is the address of the instruction
+// that caused the fault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    UnknownOpcode {
+        pc: usize,
+        opcode: u16,
+    },
+    StackOverflow {
+        pc: usize,
+        opcode: u16,
+    },
+    StackUnderflow {
+        pc: usize,
+        opcode: u16,
+    },
+    MemoryOutOfBounds {
+        pc: usize,
+        opcode: u16,
+        address: usize,
+    },
+    PcOutOfBounds {
+        pc: usize,
+    },
+}
 
-const RAM: usize = 4096;
-const VRAM: usize = 2048;
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04x} at {:03x}", opcode, pc)
+            }
+            Chip8Error::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow by {:04x} at {:03x}", opcode, pc)
+            }
+            Chip8Error::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow by {:04x} at {:03x}", opcode, pc)
+            }
+            Chip8Error::MemoryOutOfBounds {
+                pc,
+                opcode,
+                address,
+            } => write!(
+                f,
+                "memory access out of bounds at {:04x} by {:04x} at {:03x}",
+                address, opcode, pc
+            ),
+            Chip8Error::PcOutOfBounds { pc } => {
+                write!(f, "program counter out of bounds at {:04x}", pc)
+            }
+        }
+    }
+}
+
+impl Error for Chip8Error {}
 
 pub struct Processor {
     // Registers and indexes
@@ -12,9 +131,14 @@ pub struct Processor {
     idxr: u16,
     pc: usize,
     // Memory
-    ram: [u8; RAM],
+    ram: [u8; XO_RAM],
+    // Each byte holds one pixel with a bit for each XO-CHIP bitplane, plane 1
+    // in bit 0 and plane 2 in bit 1
     vram: [u8; VRAM],
     draw_flag: bool,
+    hires: bool,
+    // Bitmask of the planes drawn to, only XO-CHIP can select plane 2
+    planes: u8,
     // Stack
     stack: [usize; 16],
     sp: usize,
@@ -25,23 +149,46 @@ pub struct Processor {
     // Timers
     delay_timer: u8,
     sound_timer: u8,
+    // XO-CHIP audio, a 1-bit sample loop played at a rate set by `pitch`
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    // SUPER-CHIP persistent flag registers
+    rpl: [u8; 16],
+    // Set once the program has run 00FD
+    exited: bool,
+    // Interpreter specific behavior
+    platform: Platform,
+    quirks: Quirks,
+    // Random numbers for CXNN and the seed they started from
+    rng: Box<dyn RandomSource>,
+    seed: u64,
+    // Identifies the loaded rom in save states
+    rom_hash: u64,
+    // Memory watchpoints and the last one hit, for debuggers
+    watches: Vec<Watch>,
+    watch_hit: Option<WatchHit>,
 }
 
 impl Processor {
     pub fn initialize() -> Processor {
-        let mut ram = [0; RAM];
+        let mut ram = [0; XO_RAM];
         // Load internal font to ram
         for (i, byte) in FONT_STANDARD.iter().enumerate() {
             ram[i] = *byte;
         }
+        for (i, byte) in FONT_BIG.iter().enumerate() {
+            ram[FONT_BIG_START + i] = *byte;
+        }
 
         Processor {
             v: [0; 16],
             idxr: 0,
-            pc: 0x200,
+            pc: PROGRAM_START,
             ram,
             vram: [0; VRAM],
             draw_flag: false,
+            hires: false,
+            planes: 0b01,
             stack: [0; 16],
             sp: 0,
             keys: [false; 16],
@@ -49,11 +196,94 @@ impl Processor {
             key_register: 0,
             delay_timer: 0,
             sound_timer: 0,
+            audio_pattern: [0; 16],
+            pitch: DEFAULT_PITCH,
+            rpl: [0; 16],
+            exited: false,
+            platform: Platform::default(),
+            quirks: Quirks::default(),
+            rng: Box::new(SplitMix64::new(DEFAULT_SEED)),
+            seed: DEFAULT_SEED,
+            rom_hash: fnv1a(&[]),
+            watches: Vec::new(),
+            watch_hit: None,
         }
     }
 
-    pub fn run_cycle(&mut self, keys: [bool; 16]) {
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Restarts the random number sequence from `seed`
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Box::new(SplitMix64::new(seed));
+        self.seed = seed;
+    }
+
+    // Replaces the random number generator, `seed` is only recorded
+    pub fn set_rng(&mut self, rng: Box<dyn RandomSource>, seed: u64) {
+        self.rng = rng;
+        self.seed = seed;
+    }
+
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    pub fn rng_state(&self) -> Option<u64> {
+        self.rng.state()
+    }
+
+    pub fn set_rng_state(&mut self, state: u64) {
+        self.rng.set_state(state);
+    }
+
+    // Copies a program image into ram at `PROGRAM_START`
+    // Set the platform first, XO-CHIP roms can be larger
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), RomError> {
+        let max = self.ram_size() - PROGRAM_START;
+        if rom.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if rom.len() > max {
+            return Err(RomError::TooLarge {
+                size: rom.len(),
+                max,
+            });
+        }
+        self.ram[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        self.rom_hash = fnv1a(rom);
+        Ok(())
+    }
+
+    pub fn load_rom_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), RomError> {
+        let rom = fs::read(path)?;
+        self.load_rom(&rom)
+    }
+
+    pub fn run_cycle(&mut self, keys: [bool; 16]) -> Result<(), Chip8Error> {
         self.keys = keys;
+        self.watch_hit = None;
+
+        if self.exited {
+            return Ok(());
+        }
 
         if self.waiting_for_key {
             // Wait for a key press
@@ -65,141 +295,359 @@ impl Processor {
                 }
             }
         } else {
-            let opcode = self.fetch_opcode();
-            let nibbles = decode_opcode(opcode);
-            self.execute_opcode(opcode, nibbles);
+            let opcode = self.fetch_opcode()?;
+            self.execute_opcode(opcode)?;
+        }
+        Ok(())
+    }
+
+    // The ram the current platform can address
+    pub fn memory(&self) -> &[u8] {
+        &self.ram[..self.ram_size()]
+    }
+
+    // Lets a debugger edit ram while the program is paused
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        let size = self.ram_size();
+        &mut self.ram[..size]
+    }
+
+    pub fn vram(&self) -> &[u8; VRAM] {
+        &self.vram
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn index(&self) -> u16 {
+        self.idxr
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    // Setters for debuggers to change the program's state while it's paused
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.v[x] = value;
+    }
+
+    pub fn set_index(&mut self, value: u16) {
+        self.idxr = value;
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn set_timers(&mut self, delay: u8, sound: u8) {
+        self.delay_timer = delay;
+        self.sound_timer = sound;
+    }
+
+    // Return addresses of the subroutines being run, innermost last
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn waiting_for_key(&self) -> bool {
+        self.waiting_for_key
+    }
+
+    // Width and height of the screen in the current display mode
+    pub fn resolution(&self) -> (usize, usize) {
+        match self.hires {
+            true => (HIRES_WIDTH, HIRES_HEIGHT),
+            false => (WIDTH, HEIGHT),
         }
     }
 
-    fn fetch_opcode(&mut self) -> u16 {
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    // Returns whether vram changed since the last call
+    pub fn take_draw_flag(&mut self) -> bool {
+        std::mem::replace(&mut self.draw_flag, false)
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // Counts both timers down by one, should be called at 60Hz
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    // Amount of addressable ram on the current platform
+    fn ram_size(&self) -> usize {
+        match self.platform >= Platform::XoChip {
+            true => XO_RAM,
+            false => RAM,
+        }
+    }
+
+    fn fetch_opcode(&mut self) -> Result<u16, Chip8Error> {
+        if self.pc + 1 >= self.ram_size() {
+            return Err(Chip8Error::PcOutOfBounds { pc: self.pc });
+        }
         let byte1 = self.ram[self.pc] as u16;
         let byte2 = self.ram[self.pc + 1] as u16;
 
-        byte1 << 8 | byte2
-    }
-
-    fn execute_opcode(&mut self, opcode: u16, nibbles: (u8, usize, usize, u8)) {
-        let (op_major, x, y, op_minor) = nibbles;
-
-        match op_major {
-            0x00 => match op_minor {
-                0x00 => self.op_00e0(),
-                0x0e => self.op_00ee(),
-                _ => unreachable!(),
-            },
-            0x01 => self.op_1nnn(opcode),
-            0x02 => self.op_2nnn(opcode),
-            0x03 => self.op_3xnn(x, opcode),
-            0x04 => self.op_4xnn(x, opcode),
-            0x05 => self.op_5xy0(x, y),
-            0x06 => self.op_6xnn(x, opcode),
-            0x07 => self.op_7xnn(x, opcode),
-            0x08 => match op_minor {
-                0x00 => self.op_8xy0(x, y),
-                0x01 => self.op_8xy1(x, y),
-                0x02 => self.op_8xy2(x, y),
-                0x03 => self.op_8xy3(x, y),
-                0x04 => self.op_8xy4(x, y),
-                0x05 => self.op_8xy5(x, y),
-                0x06 => self.op_8xy6(x),
-                0x07 => self.op_8xy7(x, y),
-                0x0e => self.op_8xye(x),
-                _ => unreachable!(),
-            },
-            0x09 => self.op_9xy0(x, y),
-            0x0a => self.op_annn(opcode),
-            0x0b => self.op_bnnn(opcode),
-            0x0c => self.op_cxnn(x, opcode),
-            0x0d => self.op_dxyn(x, y, op_minor),
-            0x0e => match op_minor {
-                0x0e => self.op_ex9e(x),
-                0x01 => self.op_exa1(x),
-                _ => unreachable!(),
-            },
-            0x0f => match op_minor {
-                0x07 => self.op_fx07(x),
-                0x0a => self.op_fx0a(x),
-                0x05 => match y {
-                    // Here there are three op_minor codes to deal with, annoying...
-                    0x01 => self.op_fx15(x),
-                    0x05 => self.op_fx55(x),
-                    0x06 => self.op_fx65(x),
-                    _ => unreachable!(),
-                },
-                0x08 => self.op_fx18(x),
-                0x0e => self.op_fx1e(x),
-                0x09 => self.op_fx29(x),
-                0x03 => self.op_fx33(x),
-                _ => unreachable!(),
-            },
-            _ => unimplemented!(),
-        }
-    }
-
-    // Clear screen
+        Ok(byte1 << 8 | byte2)
+    }
+
+    // Checks that `len` bytes starting at `address` are inside ram
+    fn check_ram(&self, opcode: u16, address: usize, len: usize) -> Result<(), Chip8Error> {
+        match address + len <= self.ram_size() {
+            true => Ok(()),
+            false => Err(Chip8Error::MemoryOutOfBounds {
+                pc: self.pc,
+                opcode,
+                address,
+            }),
+        }
+    }
+
+    fn execute_opcode(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        use Instruction::*;
+
+        let instruction = match Instruction::decode(opcode) {
+            Ok(instruction) if instruction.platform() <= self.platform => instruction,
+            _ => {
+                return Err(Chip8Error::UnknownOpcode {
+                    pc: self.pc,
+                    opcode,
+                })
+            }
+        };
+
+        match instruction {
+            Clear => self.op_00e0(),
+            Return => self.op_00ee(opcode)?,
+            ScrollDown(n) => self.op_00cn(n),
+            ScrollUp(n) => self.op_00dn(n),
+            ScrollRight => self.op_00fb(),
+            ScrollLeft => self.op_00fc(),
+            Exit => self.op_00fd(),
+            Lores => self.op_00fe(),
+            Hires => self.op_00ff(),
+            Jump(nnn) => self.op_1nnn(nnn),
+            Call(nnn) => self.op_2nnn(nnn, opcode)?,
+            SkipEqual(x, nn) => self.op_3xnn(x, nn),
+            SkipNotEqual(x, nn) => self.op_4xnn(x, nn),
+            SkipEqualRegister(x, y) => self.op_5xy0(x, y),
+            SaveRange(x, y) => self.op_5xy2(x, y, opcode)?,
+            LoadRange(x, y) => self.op_5xy3(x, y, opcode)?,
+            Set(x, nn) => self.op_6xnn(x, nn),
+            Add(x, nn) => self.op_7xnn(x, nn),
+            Copy(x, y) => self.op_8xy0(x, y),
+            Or(x, y) => self.op_8xy1(x, y),
+            And(x, y) => self.op_8xy2(x, y),
+            Xor(x, y) => self.op_8xy3(x, y),
+            AddRegister(x, y) => self.op_8xy4(x, y),
+            Sub(x, y) => self.op_8xy5(x, y),
+            ShiftRight(x, y) => self.op_8xy6(x, y),
+            SubReverse(x, y) => self.op_8xy7(x, y),
+            ShiftLeft(x, y) => self.op_8xye(x, y),
+            SkipNotEqualRegister(x, y) => self.op_9xy0(x, y),
+            SetIndex(nnn) => self.op_annn(nnn),
+            JumpOffset(nnn) => self.op_bnnn(nnn),
+            Random(x, nn) => self.op_cxnn(x, nn),
+            Draw(x, y, n) => self.op_dxyn(x, y, n, opcode)?,
+            SkipKey(x) => self.op_ex9e(x),
+            SkipNotKey(x) => self.op_exa1(x),
+            SetIndexLong => self.op_f000(opcode)?,
+            Planes(n) => self.op_fn01(n),
+            LoadAudio => self.op_f002(opcode)?,
+            GetDelay(x) => self.op_fx07(x),
+            WaitKey(x) => self.op_fx0a(x),
+            SetDelay(x) => self.op_fx15(x),
+            SetSound(x) => self.op_fx18(x),
+            AddIndex(x) => self.op_fx1e(x),
+            Font(x) => self.op_fx29(x),
+            BigFont(x) => self.op_fx30(x),
+            Bcd(x) => self.op_fx33(x, opcode)?,
+            Pitch(x) => self.op_fx3a(x),
+            Store(x) => self.op_fx55(x, opcode)?,
+            Load(x) => self.op_fx65(x, opcode)?,
+            SaveFlags(x) => self.op_fx75(x),
+            LoadFlags(x) => self.op_fx85(x),
+        }
+        Ok(())
+    }
+
+    // Scroll the screen down N pixels
+    fn op_00cn(&mut self, n: u8) {
+        self.scroll(0, n as isize);
+        self.pc += 2;
+    }
+
+    // Scroll the screen up N pixels
+    fn op_00dn(&mut self, n: u8) {
+        self.scroll(0, -(n as isize));
+        self.pc += 2;
+    }
+
+    // Scroll the screen right 4 pixels
+    fn op_00fb(&mut self) {
+        self.scroll(4, 0);
+        self.pc += 2;
+    }
+
+    // Scroll the screen left 4 pixels
+    fn op_00fc(&mut self) {
+        self.scroll(-4, 0);
+        self.pc += 2;
+    }
+
+    // Moves the selected planes `dx` pixels right and `dy` pixels down, pixels
+    // scrolled in from off screen are blank
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = self.resolution();
+        let old = self.vram;
+        for row in 0..height {
+            for col in 0..width {
+                let src_col = col as isize - dx;
+                let src_row = row as isize - dy;
+                let src = match (0..width as isize).contains(&src_col)
+                    && (0..height as isize).contains(&src_row)
+                {
+                    true => old[src_col as usize + src_row as usize * width] & self.planes,
+                    false => 0,
+                };
+                let i = col + row * width;
+                self.vram[i] = (self.vram[i] & !self.planes) | src;
+            }
+        }
+        self.draw_flag = true;
+    }
+
+    // Exit the interpreter
+    fn op_00fd(&mut self) {
+        self.exited = true;
+    }
+
+    // Switch to low resolution and clear the screen
+    fn op_00fe(&mut self) {
+        self.hires = false;
+        self.op_00e0();
+    }
+
+    // Switch to high resolution and clear the screen
+    fn op_00ff(&mut self) {
+        self.hires = true;
+        self.op_00e0();
+    }
+
+    // Clear screen (only the selected planes on XO-CHIP)
     fn op_00e0(&mut self) {
         for i in 0..VRAM {
-            self.vram[i] = 0;
+            self.vram[i] &= !self.planes;
         }
         self.draw_flag = true;
         self.pc += 2;
     }
 
     // Return
-    fn op_00ee(&mut self) {
+    fn op_00ee(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        if self.sp == 0 {
+            return Err(Chip8Error::StackUnderflow {
+                pc: self.pc,
+                opcode,
+            });
+        }
         self.sp -= 1;
         self.pc = self.stack[self.sp];
+        Ok(())
     }
 
     // Jump to address at `nnn`
-    fn op_1nnn(&mut self, opcode: u16) {
-        self.pc = (opcode & 0x0fff) as usize;
+    fn op_1nnn(&mut self, nnn: u16) {
+        self.pc = nnn as usize;
     }
 
     // Call subroutine at `nnn`
-    fn op_2nnn(&mut self, opcode: u16) {
+    fn op_2nnn(&mut self, nnn: u16, opcode: u16) -> Result<(), Chip8Error> {
+        if self.sp == self.stack.len() {
+            return Err(Chip8Error::StackOverflow {
+                pc: self.pc,
+                opcode,
+            });
+        }
         self.stack[self.sp] = self.pc + 2;
         self.sp += 1;
-        self.pc = (opcode & 0x0fff) as usize;
+        self.pc = nnn as usize;
+        Ok(())
     }
 
     // Skips the next instruction if VX equals NN
-    fn op_3xnn(&mut self, x: usize, opcode: u16) {
-        let nn = (opcode & 0x00ff) as u8;
+    fn op_3xnn(&mut self, x: usize, nn: u8) {
         match self.v[x] == nn {
-            true => self.pc += 4,
+            true => self.skip(),
             false => self.pc += 2,
         }
     }
 
     // Skips the next instruction if VX doesn't equal NN
-    fn op_4xnn(&mut self, x: usize, opcode: u16) {
-        let nn = (opcode & 0x00ff) as u8;
+    fn op_4xnn(&mut self, x: usize, nn: u8) {
         match self.v[x] == nn {
             true => self.pc += 2,
-            false => self.pc += 4,
+            false => self.skip(),
         }
     }
 
     // Skips the next instruction if VX equals VY
     fn op_5xy0(&mut self, x: usize, y: usize) {
         match self.v[x] == self.v[y] {
-            true => self.pc += 4,
+            true => self.skip(),
             false => self.pc += 2,
         }
     }
 
+    // Stores VX to VY (in either order) in memory starting at address I
+    fn op_5xy2(&mut self, x: usize, y: usize, opcode: u16) -> Result<(), Chip8Error> {
+        let bytes: Vec<u8> = register_range(x, y).map(|reg| self.v[reg]).collect();
+        self.write_ram(opcode, self.idxr as usize, &bytes)?;
+        self.pc += 2;
+        Ok(())
+    }
+
+    // Fills VX to VY (in either order) with values from memory starting at address I
+    fn op_5xy3(&mut self, x: usize, y: usize, opcode: u16) -> Result<(), Chip8Error> {
+        let len = x.max(y) - x.min(y) + 1;
+        let bytes = self.read_ram(opcode, self.idxr as usize, len)?.to_vec();
+        for (reg, byte) in register_range(x, y).zip(bytes) {
+            self.v[reg] = byte;
+        }
+        self.pc += 2;
+        Ok(())
+    }
+
     // Sets VX to NN
-    fn op_6xnn(&mut self, x: usize, opcode: u16) {
-        let nn = (opcode & 0x00ff) as u8;
+    fn op_6xnn(&mut self, x: usize, nn: u8) {
         self.v[x] = nn;
         self.pc += 2;
     }
 
-    // Adds NN to VX
-    fn op_7xnn(&mut self, x: usize, opcode: u16) {
-        let nn = (opcode & 0x00ff) as u8;
-        self.v[x] += nn;
+    // Adds NN to VX, wrapping around without touching VF
+    fn op_7xnn(&mut self, x: usize, nn: u8) {
+        self.v[x] = self.v[x].wrapping_add(nn);
         self.pc += 2;
     }
 
@@ -212,18 +660,21 @@ impl Processor {
     // Sets VX to VX or VY
     fn op_8xy1(&mut self, x: usize, y: usize) {
         self.v[x] |= self.v[y];
+        self.reset_vf();
         self.pc += 2;
     }
 
     // Sets VX to VX and VY
     fn op_8xy2(&mut self, x: usize, y: usize) {
         self.v[x] &= self.v[y];
+        self.reset_vf();
         self.pc += 2;
     }
 
     // Sets VX to VX xor VY
     fn op_8xy3(&mut self, x: usize, y: usize) {
         self.v[x] ^= self.v[y];
+        self.reset_vf();
         self.pc += 2;
     }
 
@@ -243,105 +694,196 @@ impl Processor {
 
     // VY is subtracted from VX. VF is set to 0 when there's a borrow, and 1 when there isn't
     fn op_8xy5(&mut self, x: usize, y: usize) {
-        self.v[0x0f] = match self.v[x] > self.v[y] {
-            true => 0,
-            false => 1,
+        let (vx, vy) = (self.v[x], self.v[y]);
+        self.v[x] = vx.wrapping_sub(vy);
+        // VF is set when there is no borrow, after the result so that it wins
+        // when it is also VX
+        self.v[0x0f] = match vx >= vy {
+            true => 1,
+            false => 0,
         };
-        self.v[x] = self.v[x].wrapping_sub(self.v[y]);
         self.pc += 2;
     }
 
     // Stores the least significant bit of VX in VF and then shifts VX to the right by 1
-    fn op_8xy6(&mut self, x: usize) {
-        self.v[0x0f] = self.v[x] & 0x01;
-        self.v[x] >>= 1;
+    // (VY is shifted into VX instead with the `shift_vy` quirk)
+    fn op_8xy6(&mut self, x: usize, y: usize) {
+        let val = self.shift_source(x, y);
+        self.v[x] = val >> 1;
+        self.v[0x0f] = val & 0x01;
         self.pc += 2;
     }
 
     // Sets VX to VY minus VX
     fn op_8xy7(&mut self, x: usize, y: usize) {
-        self.v[0x0f] = match self.v[x] <= self.v[y] {
-            true => 0,
-            false => 1,
+        let (vx, vy) = (self.v[x], self.v[y]);
+        self.v[x] = vy.wrapping_sub(vx);
+        self.v[0x0f] = match vy >= vx {
+            true => 1,
+            false => 0,
         };
-        self.v[x] = self.v[y].wrapping_sub(self.v[x]);
         self.pc += 2;
     }
 
     // Stores the most significant bit of VX in VF and then shifts VX to the left by 1
-    fn op_8xye(&mut self, x: usize) {
-        self.v[0x0f] = self.v[x] & 0b10000000;
-        self.v[0x0f] >>= 7;
-        self.v[x] <<= 1;
+    // (VY is shifted into VX instead with the `shift_vy` quirk)
+    fn op_8xye(&mut self, x: usize, y: usize) {
+        let val = self.shift_source(x, y);
+        self.v[x] = val << 1;
+        self.v[0x0f] = (val & 0b10000000) >> 7;
         self.pc += 2;
     }
 
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        match self.quirks.shift_vy {
+            true => self.v[y],
+            false => self.v[x],
+        }
+    }
+
+    fn reset_vf(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.v[0x0f] = 0;
+        }
+    }
+
     // Skips the next instruction if VX doesn't equal VY
     fn op_9xy0(&mut self, x: usize, y: usize) {
         match self.v[x] != self.v[y] {
-            true => self.pc += 4,
+            true => self.skip(),
             false => self.pc += 2,
         }
     }
 
     // Set idxr to address `nnn`
-    fn op_annn(&mut self, opcode: u16) {
-        self.idxr = opcode & 0x0fff;
+    fn op_annn(&mut self, nnn: u16) {
+        self.idxr = nnn;
         self.pc += 2;
     }
 
-    // Jumps to the address NNN plus V0
-    fn op_bnnn(&mut self, opcode: u16) {
-        let mut nnn = opcode & 0x0fff;
-        nnn += self.v[0x00] as u16;
-        self.pc = nnn as usize;
+    // Jumps to the address NNN plus V0 (or plus VX with the `jump_vx` quirk)
+    fn op_bnnn(&mut self, nnn: u16) {
+        let x = (nnn >> 8) as usize;
+        let offset = match self.quirks.jump_vx {
+            true => self.v[x] as u16,
+            false => self.v[0x00] as u16,
+        };
+        self.pc = (nnn + offset) as usize;
     }
 
     // Sets VX to the result of a bitwise and operation on a random number and NN
-    fn op_cxnn(&mut self, x: usize, opcode: u16) {
-        let random_num = rand::thread_rng().gen::<u8>();
-        self.v[x] = random_num & (opcode & 0x00ff) as u8;
-        self.pc += 2;
-    }
-
-    // Draw sprite - TODO test this actually works...
-    fn op_dxyn(&mut self, x: usize, y: usize, n: u8) {
-        self.v[0x0f] = 0;
-        for row in 0..n as usize {
-            let data = self.ram[self.idxr as usize + row] as usize;
-            for col in 0..8 as usize {
-                let vram_pixel = self.vram[
-                    ((x + col) % WIDTH) + // wrap x direction 
-                    (((y + row) % HEIGHT) * WIDTH) // wrap y direction
-                ];
-                let new_pixel = data >> (7 - col) & 0x01;
-                // Check for collision
-                if new_pixel > 0 && vram_pixel > 0 {
-                    self.v[0x0f] = 1;
+    fn op_cxnn(&mut self, x: usize, nn: u8) {
+        let random_num = self.rng.next_u8();
+        self.v[x] = random_num & nn;
+        self.pc += 2;
+    }
+
+    // Draws an N row sprite from I at (VX, VY), XORing it onto the screen and
+    // setting VF when a pixel is turned off. Sprites that run off the edge
+    // either wrap around or get clipped, depending on the quirks.
+    // On SUPER-CHIP DXY0 draws a 16x16 sprite stored as two bytes per row. On
+    // XO-CHIP the sprite is drawn to each selected plane in turn, with the
+    // data for plane 2 following the data for plane 1
+    fn op_dxyn(&mut self, x: usize, y: usize, n: u8, opcode: u16) -> Result<(), Chip8Error> {
+        let (width, height) = self.resolution();
+        let (rows, cols) = match n {
+            0 if self.platform >= Platform::SuperChip => (16, 16),
+            _ => (n as usize, 8),
+        };
+        let row_bytes = cols / 8;
+        let sprite_bytes = rows * row_bytes;
+        let planes = [0b01, 0b10]
+            .iter()
+            .filter(|&&plane| self.planes & plane != 0)
+            .copied()
+            .collect::<Vec<u8>>();
+        let sprite = self
+            .read_ram(opcode, self.idxr as usize, sprite_bytes * planes.len())?
+            .to_vec();
+        // The start position always wraps, only the parts of the sprite that
+        // run off the edge get clipped
+        let x = self.v[x] as usize % width;
+        let y = self.v[y] as usize % height;
+        let mut collided_rows = 0;
+        let mut clipped_rows = 0;
+        for row in 0..rows {
+            if self.quirks.clip_sprites && y + row >= height {
+                clipped_rows += 1;
+                continue;
+            }
+            let line = (y + row) % height * width;
+            let mut collided = false;
+            for (i, &plane) in planes.iter().enumerate() {
+                let start = i * sprite_bytes + row * row_bytes;
+                let data = sprite[start..start + row_bytes]
+                    .iter()
+                    .fold(0, |data, &byte| data << 8 | byte as usize);
+                for col in 0..cols {
+                    if data >> (cols - 1 - col) & 0x01 == 0
+                        || (self.quirks.clip_sprites && x + col >= width)
+                    {
+                        continue;
+                    }
+                    let pixel = &mut self.vram[line + (x + col) % width];
+                    collided |= *pixel & plane != 0;
+                    *pixel ^= plane;
                 }
-                self.vram[x + col + ((y + row) * WIDTH)] = new_pixel as u8;
             }
+            collided_rows += collided as u8;
         }
+        self.v[0x0f] = match self.quirks.collision_rows && self.hires {
+            true => collided_rows + clipped_rows,
+            false => (collided_rows > 0) as u8,
+        };
         self.draw_flag = true;
         self.pc += 2;
+        Ok(())
     }
 
     // Skips the next instruction if the key stored in VX is pressed
     fn op_ex9e(&mut self, x: usize) {
-        match self.keys[self.v[x] as usize] {
-            true => self.pc += 4,
+        match self.keys[(self.v[x] & 0x0f) as usize] {
+            true => self.skip(),
             false => self.pc += 2,
         }
     }
 
     // Skips the next instruction if the key stored in VX isn't pressed
     fn op_exa1(&mut self, x: usize) {
-        match self.keys[self.v[x] as usize] {
+        match self.keys[(self.v[x] & 0x0f) as usize] {
             true => self.pc += 2,
-            false => self.pc += 4,
+            false => self.skip(),
         }
     }
 
+    // Sets I to the 16 bit address NNNN stored after the opcode
+    fn op_f000(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        self.check_ram(opcode, self.pc + 2, 2)?;
+        self.idxr = (self.ram[self.pc + 2] as u16) << 8 | self.ram[self.pc + 3] as u16;
+        self.pc += 4;
+        Ok(())
+    }
+
+    // Selects the planes to draw to from the bitmask N
+    fn op_fn01(&mut self, n: u8) {
+        self.planes = n & 0b11;
+        self.pc += 2;
+    }
+
+    // Loads the 16 byte audio pattern from memory starting at address I
+    fn op_f002(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let pattern = self.read_ram(opcode, self.idxr as usize, 16)?.to_vec();
+        self.audio_pattern.copy_from_slice(&pattern);
+        self.pc += 2;
+        Ok(())
+    }
+
+    // Sets the audio pattern playback rate to VX
+    fn op_fx3a(&mut self, x: usize) {
+        self.pitch = self.v[x];
+        self.pc += 2;
+    }
+
     // Sets VX to the value of the delay timer
     fn op_fx07(&mut self, x: usize) {
         self.v[x] = self.delay_timer;
@@ -367,68 +909,120 @@ impl Processor {
         self.pc += 2;
     }
 
-    // Adds VX to I
+    // Adds VX to I, setting VF if I runs past the end of memory and wraps
+    // around to the start
     fn op_fx1e(&mut self, x: usize) {
-        self.idxr += self.v[x] as u16;
-        match self.idxr {
-            0x000..=0xfff => self.v[0x0f] = 0,
-            _ => self.v[0x0f] = 1,
+        let sum = self.idxr as usize + self.v[x] as usize;
+        if self.quirks.index_overflow_sets_vf {
+            self.v[0x0f] = match sum < self.ram_size() {
+                true => 0,
+                false => 1,
+            };
         }
+        self.idxr = (sum % self.ram_size()) as u16;
         self.pc += 2;
     }
 
     // Sets I to the location of the sprite for the character in VX
     // Sprites are assumed to start at 0x0000 in ram and are 5 bytes long
     fn op_fx29(&mut self, x: usize) {
-        self.idxr = self.v[x] as u16 * 5;
+        self.idxr = (self.v[x] & 0x0f) as u16 * 5;
+        self.pc += 2;
+    }
+
+    // Sets I to the location of the big sprite for the character in VX
+    fn op_fx30(&mut self, x: usize) {
+        self.idxr = (FONT_BIG_START + (self.v[x] & 0x0f) as usize * 10) as u16;
         self.pc += 2;
     }
 
     // Stores the binary-coded decimal representation of VX, with the most
     // significant of three digits at the address in I, the middle digit at I
     // plus 1, and the least significant digit at I plus 2
-    fn op_fx33(&mut self, x: usize) {
-        self.ram[self.idxr as usize] = self.v[x] / 100;
-        self.ram[self.idxr as usize + 1] = (self.v[x] % 100) / 10;
-        self.ram[self.idxr as usize + 2] = self.v[x] % 10;
+    fn op_fx33(&mut self, x: usize, opcode: u16) -> Result<(), Chip8Error> {
+        let digits = [self.v[x] / 100, (self.v[x] % 100) / 10, self.v[x] % 10];
+        self.write_ram(opcode, self.idxr as usize, &digits)?;
         self.pc += 2;
+        Ok(())
     }
 
     // Stores V0 to VX (including VX) in memory starting at address I
-    fn op_fx55(&mut self, x: usize) {
-        for i in 0..=x {
-            self.ram[self.idxr as usize + i] = self.v[i];
-        }
+    fn op_fx55(&mut self, x: usize, opcode: u16) -> Result<(), Chip8Error> {
+        let registers = self.v;
+        self.write_ram(opcode, self.idxr as usize, &registers[..=x])?;
+        self.idxr = self
+            .idxr
+            .wrapping_add(self.quirks.load_store_increment.amount(x));
         self.pc += 2;
+        Ok(())
     }
 
     // Fills V0 to VX (including VX) with values from memory starting at address I
-    fn op_fx65(&mut self, x: usize) {
-        for i in 0..=x {
-            self.v[i] = self.ram[self.idxr as usize + i];
-        }
+    fn op_fx65(&mut self, x: usize, opcode: u16) -> Result<(), Chip8Error> {
+        let bytes = self.read_ram(opcode, self.idxr as usize, x + 1)?.to_vec();
+        self.v[..=x].copy_from_slice(&bytes);
+        self.idxr = self
+            .idxr
+            .wrapping_add(self.quirks.load_store_increment.amount(x));
+        self.pc += 2;
+        Ok(())
+    }
+
+    // Steps over the next instruction, XO-CHIP's F000 NNNN is twice as long
+    // as the others
+    fn skip(&mut self) {
+        let long = self.platform >= Platform::XoChip
+            && self.ram.get(self.pc + 2) == Some(&0xf0)
+            && self.ram.get(self.pc + 3) == Some(&0x00);
+        self.pc += match long {
+            true => 6,
+            false => 4,
+        };
+    }
+
+    // Stores V0 to VX (including VX) in the RPL user flags
+    fn op_fx75(&mut self, x: usize) {
+        self.rpl[..=x].copy_from_slice(&self.v[..=x]);
+        self.pc += 2;
+    }
+
+    // Fills V0 to VX (including VX) from the RPL user flags
+    fn op_fx85(&mut self, x: usize) {
+        self.v[..=x].copy_from_slice(&self.rpl[..=x]);
         self.pc += 2;
     }
 }
 
-// An opcode is two bytes long (four nibbles).
-//
-// /------- byte 1 -------\  /------- byte 2 -------\
-// /----n1----||----n2----\  /----n3----||----n4----\
-// / op_major ||     x    \  /    y     || op_minor \
-fn decode_opcode(opcode: u16) -> (u8, usize, usize, u8) {
-    let op_major = ((opcode & 0xf000) >> 12) as u8;
-    let x = ((opcode & 0x0f00) >> 8) as usize;
-    let y = ((opcode & 0x00f0) >> 4) as usize;
-    let op_minor = (opcode & 0x000f) as u8;
-
-    (op_major, x, y, op_minor)
+// Packs the keypad into a bitmask with key 0 in the lowest bit
+pub fn keys_to_bits(keys: [bool; 16]) -> u16 {
+    keys.iter()
+        .enumerate()
+        .fold(0, |bits, (i, &key)| bits | (key as u16) << i)
+}
+
+pub fn keys_from_bits(bits: u16) -> [bool; 16] {
+    let mut keys = [false; 16];
+    for (i, key) in keys.iter_mut().enumerate() {
+        *key = bits >> i & 1 == 1;
+    }
+    keys
+}
+
+// Registers from X to Y inclusive, counting down if X is larger
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    match x <= y {
+        true => Box::new(x..=y),
+        false => Box::new((y..=x).rev()),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::font::FONT_STANDARD;
-    use crate::processor::Processor;
+    use crate::font::{FONT_BIG, FONT_STANDARD};
+    use crate::platform::Platform;
+    use crate::processor::{Chip8Error, Processor, RomError, MAX_ROM_SIZE};
+    use crate::quirks::{self, IndexIncrement, Quirks};
+    use crate::random::RandomSource;
 
     // Convenience variables to pass input states into the processor on each cycle
     const KEYS: [bool; 16] = [false; 16];
@@ -444,7 +1038,7 @@ mod tests {
         cpu.ram[0x200] = 0x1a;
         cpu.ram[0x201] = 0xaa;
 
-        cpu.run_cycle(KEYS);
+        cpu.run_cycle(KEYS).unwrap();
         assert_eq!(cpu.pc, 0xaaa);
     }
 
@@ -454,7 +1048,7 @@ mod tests {
         cpu.ram[0x200] = 0x25;
         cpu.ram[0x201] = 0x55;
 
-        cpu.run_cycle(KEYS);
+        cpu.run_cycle(KEYS).unwrap();
         assert_eq!(cpu.pc, 0x555);
         assert_eq!(cpu.stack[0], 0x202);
     }
@@ -465,7 +1059,7 @@ mod tests {
         cpu.ram[0x200] = 0xa1;
         cpu.ram[0x201] = 0x23;
 
-        cpu.run_cycle(KEYS);
+        cpu.run_cycle(KEYS).unwrap();
         assert_eq!(cpu.idxr, 0x123);
     }
 
@@ -477,22 +1071,22 @@ mod tests {
         cpu.ram[0x202] = 0x1a;
         cpu.ram[0x203] = 0xaa;
 
-        cpu.run_cycle(KEYS);
-        assert_eq!(cpu.waiting_for_key, true);
+        cpu.run_cycle(KEYS).unwrap();
+        assert!(cpu.waiting_for_key);
         assert_eq!(cpu.key_register, 5);
         assert_eq!(cpu.pc, 0x202);
 
-        cpu.run_cycle(KEYS); // waiting on input
-        assert_eq!(cpu.waiting_for_key, true);
+        cpu.run_cycle(KEYS).unwrap(); // waiting on input
+        assert!(cpu.waiting_for_key);
         assert_eq!(cpu.key_register, 5);
 
-        cpu.run_cycle(KEYS_3); // Input passed
-        assert_eq!(cpu.waiting_for_key, false);
+        cpu.run_cycle(KEYS_3).unwrap(); // Input passed
+        assert!(!cpu.waiting_for_key);
         assert_eq!(cpu.key_register, 5);
         assert_eq!(cpu.v[cpu.key_register], 3); // Check correct key was stored
 
-        cpu.run_cycle(KEYS); // Run next instruction
-        assert_eq!(cpu.waiting_for_key, false);
+        cpu.run_cycle(KEYS).unwrap(); // Run next instruction
+        assert!(!cpu.waiting_for_key);
         assert_eq!(cpu.pc, 0xaaa);
     }
 
@@ -506,7 +1100,7 @@ mod tests {
         cpu.ram[0x200] = 0xff;
         cpu.ram[0x201] = 0x55;
 
-        cpu.run_cycle(KEYS);
+        cpu.run_cycle(KEYS).unwrap();
         let expected: [u8; 16] = [7; 16];
         assert_eq!(cpu.ram[0x300..0x310], expected);
     }
@@ -521,7 +1115,7 @@ mod tests {
         cpu.ram[0x200] = 0xfa;
         cpu.ram[0x201] = 0x65;
 
-        cpu.run_cycle(KEYS);
+        cpu.run_cycle(KEYS).unwrap();
         let expected: [u8; 16] = [7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 0, 0, 0, 0, 0, 0];
         assert_eq!(cpu.v, expected);
     }
@@ -534,7 +1128,7 @@ mod tests {
         cpu.v[2] = 123;
         cpu.idxr = 0x500;
 
-        cpu.run_cycle(KEYS);
+        cpu.run_cycle(KEYS).unwrap();
         assert_eq!(cpu.ram[0x500], 1);
         assert_eq!(cpu.ram[0x501], 2);
         assert_eq!(cpu.ram[0x502], 3);
@@ -548,4 +1142,552 @@ mod tests {
         assert_eq!(cpu.ram[0x013], FONT_STANDARD[0x013]);
         assert_eq!(cpu.ram[0x03a], FONT_STANDARD[0x03a]);
     }
+
+    #[test]
+    fn load_rom() {
+        let mut cpu = Processor::initialize();
+        cpu.load_rom(&[0x12, 0x34, 0x56]).unwrap();
+        assert_eq!(cpu.ram[0x200..0x203], [0x12, 0x34, 0x56]);
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    #[test]
+    fn load_rom_fills_memory() {
+        let mut cpu = Processor::initialize();
+        cpu.load_rom(&[0xaa; MAX_ROM_SIZE]).unwrap();
+        assert_eq!(cpu.ram[0xfff], 0xaa);
+    }
+
+    #[test]
+    fn load_rom_too_large() {
+        let mut cpu = Processor::initialize();
+        match cpu.load_rom(&[0; MAX_ROM_SIZE + 1]) {
+            Err(RomError::TooLarge { size, max }) => {
+                assert_eq!(size, MAX_ROM_SIZE + 1);
+                assert_eq!(max, MAX_ROM_SIZE);
+            }
+            _ => panic!("expected RomError::TooLarge"),
+        }
+    }
+
+    #[test]
+    fn load_rom_empty() {
+        let mut cpu = Processor::initialize();
+        assert!(matches!(cpu.load_rom(&[]), Err(RomError::Empty)));
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        let mut cpu = Processor::initialize();
+        cpu.ram[0x200] = 0x8a;
+        cpu.ram[0x201] = 0xbf;
+
+        assert_eq!(
+            cpu.run_cycle(KEYS),
+            Err(Chip8Error::UnknownOpcode {
+                pc: 0x200,
+                opcode: 0x8abf
+            })
+        );
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let mut cpu = Processor::initialize();
+        // Call 0x200 forever
+        cpu.ram[0x200] = 0x22;
+        cpu.ram[0x201] = 0x00;
+
+        for _ in 0..16 {
+            cpu.run_cycle(KEYS).unwrap();
+        }
+        assert_eq!(
+            cpu.run_cycle(KEYS),
+            Err(Chip8Error::StackOverflow {
+                pc: 0x200,
+                opcode: 0x2200
+            })
+        );
+        assert_eq!(cpu.sp, 16);
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let mut cpu = Processor::initialize();
+        cpu.ram[0x200] = 0x00;
+        cpu.ram[0x201] = 0xee;
+
+        assert_eq!(
+            cpu.run_cycle(KEYS),
+            Err(Chip8Error::StackUnderflow {
+                pc: 0x200,
+                opcode: 0x00ee
+            })
+        );
+        assert_eq!(cpu.sp, 0);
+    }
+
+    #[test]
+    fn memory_out_of_bounds() {
+        let mut cpu = Processor::initialize();
+        cpu.idxr = 0xffa;
+        cpu.ram[0x200] = 0xff;
+        cpu.ram[0x201] = 0x55;
+
+        assert_eq!(
+            cpu.run_cycle(KEYS),
+            Err(Chip8Error::MemoryOutOfBounds {
+                pc: 0x200,
+                opcode: 0xff55,
+                address: 0xffa
+            })
+        );
+        assert_eq!(cpu.ram[0xffa..0x1000], [0; 6]);
+
+        // Sprite data running off the end of ram
+        cpu.ram[0x200] = 0xd0;
+        cpu.ram[0x201] = 0x0f;
+        assert!(matches!(
+            cpu.run_cycle(KEYS),
+            Err(Chip8Error::MemoryOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn pc_out_of_bounds() {
+        let mut cpu = Processor::initialize();
+        cpu.ram[0x200] = 0x1f;
+        cpu.ram[0x201] = 0xff;
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(
+            cpu.run_cycle(KEYS),
+            Err(Chip8Error::PcOutOfBounds { pc: 0xfff })
+        );
+    }
+
+    #[test]
+    fn tick_timers() {
+        let mut cpu = Processor::initialize();
+        cpu.delay_timer = 2;
+        cpu.sound_timer = 1;
+
+        cpu.tick_timers();
+        assert_eq!(cpu.delay_timer, 1);
+        assert_eq!(cpu.sound_timer, 0);
+
+        cpu.tick_timers();
+        cpu.tick_timers();
+        assert_eq!(cpu.delay_timer, 0);
+        assert_eq!(cpu.sound_timer, 0);
+    }
+
+    #[test]
+    fn quirk_shift_vy() {
+        let mut cpu = Processor::initialize();
+        cpu.ram[0x200] = 0x81;
+        cpu.ram[0x201] = 0x26;
+        cpu.ram[0x202] = 0x81;
+        cpu.ram[0x203] = 0x2e;
+        cpu.v[1] = 0x10;
+        cpu.v[2] = 0x81;
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.v[1], 0x08);
+        assert_eq!(cpu.v[0x0f], 0);
+
+        cpu = Processor::initialize();
+        cpu.set_quirks(quirks::COSMAC_VIP);
+        cpu.ram[0x200] = 0x81;
+        cpu.ram[0x201] = 0x26;
+        cpu.ram[0x202] = 0x81;
+        cpu.ram[0x203] = 0x2e;
+        cpu.v[1] = 0x10;
+        cpu.v[2] = 0x81;
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.v[1], 0x40);
+        assert_eq!(cpu.v[0x0f], 1);
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.v[1], 0x02);
+        assert_eq!(cpu.v[0x0f], 1);
+    }
+
+    #[test]
+    fn quirk_load_store_increment() {
+        let cases = [
+            (IndexIncrement::ByXPlusOne, 0x304, 0x306),
+            (IndexIncrement::ByX, 0x303, 0x304),
+            (IndexIncrement::Unchanged, 0x300, 0x300),
+        ];
+        for &(increment, after_store, after_load) in cases.iter() {
+            let mut cpu = Processor::initialize();
+            cpu.set_quirks(Quirks {
+                load_store_increment: increment,
+                ..Quirks::default()
+            });
+            cpu.idxr = 0x300;
+            cpu.ram[0x200] = 0xf3;
+            cpu.ram[0x201] = 0x55;
+            cpu.ram[0x202] = 0xf1;
+            cpu.ram[0x203] = 0x65;
+
+            cpu.run_cycle(KEYS).unwrap();
+            assert_eq!(cpu.idxr, after_store);
+            cpu.run_cycle(KEYS).unwrap();
+            assert_eq!(cpu.idxr, after_load);
+        }
+    }
+
+    #[test]
+    fn quirk_jump_vx() {
+        let mut cpu = Processor::initialize();
+        cpu.set_quirks(quirks::SUPER_CHIP);
+        cpu.ram[0x200] = 0xb3;
+        cpu.ram[0x201] = 0x00;
+        cpu.v[0] = 0x10;
+        cpu.v[3] = 0x20;
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.pc, 0x320);
+
+        cpu = Processor::initialize();
+        cpu.ram[0x200] = 0xb3;
+        cpu.ram[0x201] = 0x00;
+        cpu.v[0] = 0x10;
+        cpu.v[3] = 0x20;
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.pc, 0x310);
+    }
+
+    #[test]
+    fn quirk_logic_resets_vf() {
+        for (opcode, reset) in [(0x8121, false), (0x8122, true), (0x8123, true)].iter() {
+            let mut cpu = Processor::initialize();
+            cpu.set_quirks(Quirks {
+                logic_resets_vf: *reset,
+                ..Quirks::default()
+            });
+            cpu.ram[0x200] = (opcode >> 8) as u8;
+            cpu.ram[0x201] = (opcode & 0xff) as u8;
+            cpu.v[0x0f] = 1;
+
+            cpu.run_cycle(KEYS).unwrap();
+            assert_eq!(cpu.v[0x0f], !reset as u8);
+        }
+    }
+
+    #[test]
+    fn quirk_clip_sprites() {
+        for clip in [false, true].iter() {
+            let mut cpu = Processor::initialize();
+            cpu.set_quirks(Quirks {
+                clip_sprites: *clip,
+                ..Quirks::default()
+            });
+            cpu.ram[0x200] = 0xd0;
+            cpu.ram[0x201] = 0x12;
+            cpu.v[0] = 60;
+            cpu.v[1] = 31;
+            cpu.idxr = 0x300;
+            cpu.ram[0x300] = 0xff;
+            cpu.ram[0x301] = 0xff;
+            cpu.vram[0] = 1;
+
+            cpu.run_cycle(KEYS).unwrap();
+            assert!(cpu.vram[31 * 64 + 60..32 * 64].iter().all(|&p| p == 1));
+            match clip {
+                true => {
+                    assert_eq!(cpu.vram[31 * 64], 0);
+                    assert_eq!(cpu.vram[0], 1);
+                    assert_eq!(cpu.v[0x0f], 0);
+                }
+                false => {
+                    assert_eq!(cpu.vram[31 * 64..31 * 64 + 4], [1, 1, 1, 1]);
+                    assert_eq!(cpu.vram[..5], [0, 1, 1, 1, 0]);
+                    assert_eq!(cpu.v[0x0f], 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quirk_collision_rows() {
+        for hires in [false, true].iter() {
+            let mut cpu = schip();
+            cpu.set_quirks(quirks::SUPER_CHIP);
+            cpu.hires = *hires;
+            let (width, height) = cpu.resolution();
+            // A 4 row sprite hitting pixels on two rows with its last row
+            // clipped off the bottom
+            cpu.ram[0x200] = 0xd0;
+            cpu.ram[0x201] = 0x14;
+            cpu.v[0] = 0;
+            cpu.v[1] = (height - 3) as u8;
+            cpu.idxr = 0x300;
+            cpu.ram[0x300..0x304].copy_from_slice(&[0x80; 4]);
+            cpu.vram[(height - 3) * width] = 1;
+            cpu.vram[(height - 1) * width] = 1;
+
+            cpu.run_cycle(KEYS).unwrap();
+            match hires {
+                true => assert_eq!(cpu.v[0x0f], 3),
+                false => assert_eq!(cpu.v[0x0f], 1),
+            }
+        }
+    }
+
+    fn schip() -> Processor {
+        let mut cpu = Processor::initialize();
+        cpu.set_platform(Platform::SuperChip);
+        cpu
+    }
+
+    #[test]
+    fn schip_only_opcodes() {
+        let mut cpu = Processor::initialize();
+        cpu.ram[0x200] = 0x00;
+        cpu.ram[0x201] = 0xff;
+
+        assert!(matches!(
+            cpu.run_cycle(KEYS),
+            Err(Chip8Error::UnknownOpcode { .. })
+        ));
+    }
+
+    #[test]
+    fn hires() {
+        let mut cpu = schip();
+        cpu.ram[0x200] = 0x00;
+        cpu.ram[0x201] = 0xff;
+        cpu.ram[0x202] = 0x00;
+        cpu.ram[0x203] = 0xfe;
+        cpu.vram[0] = 1;
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.resolution(), (128, 64));
+        assert_eq!(cpu.vram[0], 0);
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.resolution(), (64, 32));
+    }
+
+    #[test]
+    fn scroll() {
+        let mut cpu = schip();
+        cpu.ram[0x200] = 0x00;
+        cpu.ram[0x201] = 0xc2;
+        cpu.ram[0x202] = 0x00;
+        cpu.ram[0x203] = 0xfb;
+        cpu.ram[0x204] = 0x00;
+        cpu.ram[0x205] = 0xfc;
+        cpu.vram[3] = 1;
+
+        // Down 2 rows
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.vram[3], 0);
+        assert_eq!(cpu.vram[3 + 2 * 64], 1);
+
+        // Right 4 pixels
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.vram[3 + 2 * 64], 0);
+        assert_eq!(cpu.vram[7 + 2 * 64], 1);
+
+        // Left 4 pixels
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.vram[7 + 2 * 64], 0);
+        assert_eq!(cpu.vram[3 + 2 * 64], 1);
+    }
+
+    #[test]
+    fn big_sprite() {
+        let mut cpu = schip();
+        for i in 0..32 {
+            cpu.ram[0x300 + i] = 0xff;
+        }
+        cpu.idxr = 0x300;
+        cpu.ram[0x200] = 0xd0;
+        cpu.ram[0x201] = 0x00;
+
+        cpu.run_cycle(KEYS).unwrap();
+        for row in 0..16 {
+            assert!(cpu.vram[row * 64..row * 64 + 16].iter().all(|&p| p == 1));
+            assert_eq!(cpu.vram[row * 64 + 16], 0);
+        }
+        assert_eq!(cpu.vram[16 * 64], 0);
+    }
+
+    #[test]
+    fn big_font() {
+        let mut cpu = schip();
+        cpu.ram[0x200] = 0xf3;
+        cpu.ram[0x201] = 0x30;
+        cpu.v[3] = 7;
+
+        cpu.run_cycle(KEYS).unwrap();
+        let idxr = cpu.idxr as usize;
+        assert_eq!(cpu.ram[idxr..idxr + 10], FONT_BIG[70..80]);
+    }
+
+    #[test]
+    fn rpl_flags() {
+        let mut cpu = schip();
+        cpu.ram[0x200] = 0xf2;
+        cpu.ram[0x201] = 0x75;
+        cpu.ram[0x202] = 0xf2;
+        cpu.ram[0x203] = 0x85;
+        cpu.v[0] = 1;
+        cpu.v[1] = 2;
+        cpu.v[2] = 3;
+
+        cpu.run_cycle(KEYS).unwrap();
+        cpu.v = [0; 16];
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.v[..4], [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn exit() {
+        let mut cpu = schip();
+        cpu.ram[0x200] = 0x00;
+        cpu.ram[0x201] = 0xfd;
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert!(cpu.has_exited());
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.pc, 0x200);
+    }
+
+    fn xo_chip() -> Processor {
+        let mut cpu = Processor::initialize();
+        cpu.set_platform(Platform::XoChip);
+        cpu
+    }
+
+    #[test]
+    fn xo_chip_ram() {
+        let mut cpu = xo_chip();
+        cpu.load_rom(&[0xaa; 0x10000 - 0x200]).unwrap();
+        assert_eq!(cpu.ram[0xffff], 0xaa);
+    }
+
+    #[test]
+    fn long_index_load() {
+        let mut cpu = xo_chip();
+        cpu.load_rom(&[0xf0, 0x00, 0x12, 0x34, 0xf0, 0x00]).unwrap();
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.idxr, 0x1234);
+        assert_eq!(cpu.pc, 0x204);
+    }
+
+    #[test]
+    fn skip_long_instruction() {
+        let mut cpu = xo_chip();
+        cpu.load_rom(&[0x30, 0x00, 0xf0, 0x00, 0x12, 0x34, 0x00, 0xe0])
+            .unwrap();
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.pc, 0x206);
+    }
+
+    #[test]
+    fn register_range() {
+        let mut cpu = xo_chip();
+        cpu.load_rom(&[0x53, 0x12, 0x51, 0x33]).unwrap();
+        cpu.idxr = 0x400;
+        cpu.v[1] = 1;
+        cpu.v[2] = 2;
+        cpu.v[3] = 3;
+
+        // Save V3 down to V1
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.ram[0x400..0x403], [3, 2, 1]);
+        assert_eq!(cpu.idxr, 0x400);
+
+        // Load V1 up to V3
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.v[1..4], [3, 2, 1]);
+    }
+
+    #[test]
+    fn bitplanes() {
+        let mut cpu = xo_chip();
+        // Select both planes and draw a 1 row sprite, then clear plane 1
+        cpu.load_rom(&[0xf3, 0x01, 0xd0, 0x01, 0xf1, 0x01, 0x00, 0xe0])
+            .unwrap();
+        cpu.idxr = 0x400;
+        cpu.ram[0x400] = 0b1000_0000; // plane 1
+        cpu.ram[0x401] = 0b1100_0000; // plane 2
+
+        cpu.run_cycle(KEYS).unwrap();
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.vram[..3], [0b11, 0b10, 0b00]);
+
+        cpu.run_cycle(KEYS).unwrap();
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.vram[..3], [0b10, 0b10, 0b00]);
+    }
+
+    #[test]
+    fn scroll_up() {
+        let mut cpu = xo_chip();
+        cpu.load_rom(&[0x00, 0xd1]).unwrap();
+        cpu.vram[64] = 0b11;
+
+        cpu.run_cycle(KEYS).unwrap();
+        // Only plane 1 is selected
+        assert_eq!(cpu.vram[0], 0b01);
+        assert_eq!(cpu.vram[64], 0b10);
+    }
+
+    #[test]
+    fn audio_pattern() {
+        let mut cpu = xo_chip();
+        cpu.load_rom(&[0xf0, 0x02, 0xf4, 0x3a]).unwrap();
+        cpu.idxr = 0x400;
+        for i in 0..16 {
+            cpu.ram[0x400 + i] = i as u8;
+        }
+        cpu.v[4] = 112;
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.audio_pattern()[15], 15);
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.pitch(), 112);
+    }
+
+    #[test]
+    fn seeded_random() {
+        let run = |seed| {
+            let mut cpu = Processor::initialize();
+            cpu.set_seed(seed);
+            cpu.load_rom(&[0xc0, 0xff, 0xc1, 0xff, 0xc2, 0xff, 0xc3, 0xff])
+                .unwrap();
+            for _ in 0..4 {
+                cpu.run_cycle(KEYS).unwrap();
+            }
+            cpu.v
+        };
+
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+
+    #[test]
+    fn custom_random() {
+        struct Fixed;
+        impl RandomSource for Fixed {
+            fn next_u8(&mut self) -> u8 {
+                0xab
+            }
+        }
+
+        let mut cpu = Processor::initialize();
+        cpu.set_rng(Box::new(Fixed), 0);
+        cpu.load_rom(&[0xc0, 0x0f]).unwrap();
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.v[0], 0x0b);
+    }
 }
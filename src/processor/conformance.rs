@@ -0,0 +1,905 @@
+// Table driven tests running every opcode in `execute_opcode` under every
+// quirk preset. Each case loads a short program at 0x200, sets up the
+// processor, runs one cycle and checks the result, which may depend on the
+// quirks in use.
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::platform::Platform;
+use crate::processor::{keys_from_bits, Chip8Error, Processor, PROGRAM_START};
+use crate::quirks::{Quirks, MODERN, PRESETS};
+
+const NEXT: usize = PROGRAM_START + 2;
+const SKIPPED: usize = PROGRAM_START + 4;
+
+struct Case {
+    name: &'static str,
+    platform: Platform,
+    program: &'static [u16],
+    // Keypad bitmask held during the cycle
+    keys: u16,
+    setup: fn(&mut Processor),
+    check: fn(&Processor, Quirks),
+}
+
+fn case(
+    name: &'static str,
+    program: &'static [u16],
+    setup: fn(&mut Processor),
+    check: fn(&Processor, Quirks),
+) -> Case {
+    Case {
+        name,
+        platform: Platform::Chip8,
+        program,
+        keys: 0,
+        setup,
+        check,
+    }
+}
+
+impl Case {
+    fn on(self, platform: Platform) -> Self {
+        Case { platform, ..self }
+    }
+
+    fn keys(self, keys: u16) -> Self {
+        Case { keys, ..self }
+    }
+}
+
+fn pixel(cpu: &Processor, x: usize, y: usize) -> u8 {
+    let (width, _) = cpu.resolution();
+    cpu.vram[x + y * width]
+}
+
+fn set_pixel(cpu: &mut Processor, x: usize, y: usize, value: u8) {
+    let (width, _) = cpu.resolution();
+    cpu.vram[x + y * width] = value;
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        // 0NNN
+        case(
+            "00E0 clears the screen",
+            &[0x00e0],
+            |cpu| cpu.vram.iter_mut().for_each(|pixel| *pixel = 1),
+            |cpu, _| {
+                assert!(cpu.vram.iter().all(|&pixel| pixel == 0));
+                assert_eq!(cpu.pc, NEXT);
+            },
+        ),
+        case(
+            "00EE returns",
+            &[0x00ee],
+            |cpu| {
+                cpu.stack[0] = 0x300;
+                cpu.sp = 1;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.pc, 0x300);
+                assert_eq!(cpu.sp, 0);
+            },
+        ),
+        case(
+            "00CN scrolls down",
+            &[0x00c3],
+            |cpu| set_pixel(cpu, 5, 0, 1),
+            |cpu, _| {
+                assert_eq!(pixel(cpu, 5, 0), 0);
+                assert_eq!(pixel(cpu, 5, 3), 1);
+            },
+        )
+        .on(Platform::SuperChip),
+        case(
+            "00DN scrolls up",
+            &[0x00d2],
+            |cpu| set_pixel(cpu, 5, 4, 1),
+            |cpu, _| {
+                assert_eq!(pixel(cpu, 5, 4), 0);
+                assert_eq!(pixel(cpu, 5, 2), 1);
+            },
+        )
+        .on(Platform::XoChip),
+        case(
+            "00FB scrolls right",
+            &[0x00fb],
+            |cpu| set_pixel(cpu, 5, 1, 1),
+            |cpu, _| assert_eq!(pixel(cpu, 9, 1), 1),
+        )
+        .on(Platform::SuperChip),
+        case(
+            "00FC scrolls left",
+            &[0x00fc],
+            |cpu| set_pixel(cpu, 5, 1, 1),
+            |cpu, _| assert_eq!(pixel(cpu, 1, 1), 1),
+        )
+        .on(Platform::SuperChip),
+        case(
+            "00FD exits",
+            &[0x00fd],
+            |_| {},
+            |cpu, _| assert!(cpu.exited),
+        )
+        .on(Platform::SuperChip),
+        case(
+            "00FE switches to low resolution",
+            &[0x00fe],
+            |cpu| cpu.hires = true,
+            |cpu, _| assert_eq!(cpu.resolution(), (64, 32)),
+        )
+        .on(Platform::SuperChip),
+        case(
+            "00FF switches to high resolution",
+            &[0x00ff],
+            |_| {},
+            |cpu, _| {
+                assert_eq!(cpu.resolution(), (128, 64));
+                assert_eq!(cpu.pc, NEXT);
+            },
+        )
+        .on(Platform::SuperChip),
+        // Jumps and calls
+        case(
+            "1NNN jumps",
+            &[0x1abc],
+            |_| {},
+            |cpu, _| assert_eq!(cpu.pc, 0xabc),
+        ),
+        case(
+            "2NNN calls",
+            &[0x2abc],
+            |_| {},
+            |cpu, _| {
+                assert_eq!(cpu.pc, 0xabc);
+                assert_eq!(cpu.stack(), &[NEXT]);
+            },
+        ),
+        case(
+            "BNNN jumps to NNN plus V0, or XNN plus VX",
+            &[0xb210],
+            |cpu| {
+                cpu.v[0] = 0x01;
+                cpu.v[2] = 0x20;
+            },
+            |cpu, quirks| match quirks.jump_vx {
+                true => assert_eq!(cpu.pc, 0x230),
+                false => assert_eq!(cpu.pc, 0x211),
+            },
+        ),
+        // Skips
+        case(
+            "3XNN skips when equal",
+            &[0x3142],
+            |cpu| cpu.v[1] = 0x42,
+            |cpu, _| assert_eq!(cpu.pc, SKIPPED),
+        ),
+        case(
+            "3XNN doesn't skip when different",
+            &[0x3142],
+            |cpu| cpu.v[1] = 0x43,
+            |cpu, _| assert_eq!(cpu.pc, NEXT),
+        ),
+        case(
+            "4XNN skips when different",
+            &[0x4142],
+            |cpu| cpu.v[1] = 0x43,
+            |cpu, _| assert_eq!(cpu.pc, SKIPPED),
+        ),
+        case(
+            "4XNN doesn't skip when equal",
+            &[0x4142],
+            |cpu| cpu.v[1] = 0x42,
+            |cpu, _| assert_eq!(cpu.pc, NEXT),
+        ),
+        case(
+            "5XY0 skips when equal",
+            &[0x5120],
+            |cpu| {
+                cpu.v[1] = 7;
+                cpu.v[2] = 7;
+            },
+            |cpu, _| assert_eq!(cpu.pc, SKIPPED),
+        ),
+        case(
+            "5XY0 doesn't skip when different",
+            &[0x5120],
+            |cpu| cpu.v[1] = 7,
+            |cpu, _| assert_eq!(cpu.pc, NEXT),
+        ),
+        case(
+            "9XY0 skips when different",
+            &[0x9120],
+            |cpu| cpu.v[1] = 7,
+            |cpu, _| assert_eq!(cpu.pc, SKIPPED),
+        ),
+        case(
+            "9XY0 doesn't skip when equal",
+            &[0x9120],
+            |_| {},
+            |cpu, _| assert_eq!(cpu.pc, NEXT),
+        ),
+        case(
+            "skips step over F000 NNNN",
+            &[0x3100, 0xf000, 0x1234],
+            |_| {},
+            |cpu, _| assert_eq!(cpu.pc, SKIPPED + 2),
+        )
+        .on(Platform::XoChip),
+        case(
+            "EX9E skips when the key is down",
+            &[0xe19e],
+            |cpu| cpu.v[1] = 0xa,
+            |cpu, _| assert_eq!(cpu.pc, SKIPPED),
+        )
+        .keys(1 << 0xa),
+        case(
+            "EX9E doesn't skip when the key is up",
+            &[0xe19e],
+            |cpu| cpu.v[1] = 0xa,
+            |cpu, _| assert_eq!(cpu.pc, NEXT),
+        )
+        .keys(1 << 0xb),
+        case(
+            "EXA1 skips when the key is up",
+            &[0xe1a1],
+            |cpu| cpu.v[1] = 0xa,
+            |cpu, _| assert_eq!(cpu.pc, SKIPPED),
+        ),
+        case(
+            "EXA1 doesn't skip when the key is down",
+            &[0xe1a1],
+            |cpu| cpu.v[1] = 0xa,
+            |cpu, _| assert_eq!(cpu.pc, NEXT),
+        )
+        .keys(1 << 0xa),
+        // Register loads and arithmetic
+        case(
+            "6XNN loads",
+            &[0x6a42],
+            |_| {},
+            |cpu, _| assert_eq!(cpu.v[0xa], 0x42),
+        ),
+        case(
+            "7XNN adds",
+            &[0x7a02],
+            |cpu| cpu.v[0xa] = 0x40,
+            |cpu, _| assert_eq!(cpu.v[0xa], 0x42),
+        ),
+        case(
+            "7XNN wraps without touching VF",
+            &[0x7a02],
+            |cpu| {
+                cpu.v[0xa] = 0xff;
+                cpu.v[0xf] = 0x55;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[0xa], 0x01);
+                assert_eq!(cpu.v[0xf], 0x55);
+            },
+        ),
+        case(
+            "8XY0 copies",
+            &[0x8120],
+            |cpu| cpu.v[2] = 0x42,
+            |cpu, _| assert_eq!(cpu.v[1], 0x42),
+        ),
+        case(
+            "8XY1 ors",
+            &[0x8121],
+            |cpu| {
+                cpu.v[1] = 0x0c;
+                cpu.v[2] = 0x0a;
+                cpu.v[0xf] = 0x55;
+            },
+            |cpu, quirks| {
+                assert_eq!(cpu.v[1], 0x0e);
+                assert_eq!(cpu.v[0xf], (!quirks.logic_resets_vf) as u8 * 0x55);
+            },
+        ),
+        case(
+            "8XY2 ands",
+            &[0x8122],
+            |cpu| {
+                cpu.v[1] = 0x0c;
+                cpu.v[2] = 0x0a;
+                cpu.v[0xf] = 0x55;
+            },
+            |cpu, quirks| {
+                assert_eq!(cpu.v[1], 0x08);
+                assert_eq!(cpu.v[0xf], (!quirks.logic_resets_vf) as u8 * 0x55);
+            },
+        ),
+        case(
+            "8XY3 xors",
+            &[0x8123],
+            |cpu| {
+                cpu.v[1] = 0x0c;
+                cpu.v[2] = 0x0a;
+                cpu.v[0xf] = 0x55;
+            },
+            |cpu, quirks| {
+                assert_eq!(cpu.v[1], 0x06);
+                assert_eq!(cpu.v[0xf], (!quirks.logic_resets_vf) as u8 * 0x55);
+            },
+        ),
+        case(
+            "8XY4 adds without carry",
+            &[0x8124],
+            |cpu| {
+                cpu.v[1] = 0x10;
+                cpu.v[2] = 0x20;
+                cpu.v[0xf] = 0x55;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[1], 0x30);
+                assert_eq!(cpu.v[0xf], 0);
+            },
+        ),
+        case(
+            "8XY4 adds with carry",
+            &[0x8124],
+            |cpu| {
+                cpu.v[1] = 0xf0;
+                cpu.v[2] = 0x20;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[1], 0x10);
+                assert_eq!(cpu.v[0xf], 1);
+            },
+        ),
+        case(
+            "8XY4 into VF keeps the carry",
+            &[0x8f14],
+            |cpu| {
+                cpu.v[0xf] = 0xf0;
+                cpu.v[1] = 0x20;
+            },
+            |cpu, _| assert_eq!(cpu.v[0xf], 1),
+        ),
+        case(
+            "8XY4 reads VF as VY before setting it",
+            &[0x81f4],
+            |cpu| {
+                cpu.v[1] = 0x20;
+                cpu.v[0xf] = 0xf0;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[1], 0x10);
+                assert_eq!(cpu.v[0xf], 1);
+            },
+        ),
+        case(
+            "8XY5 subtracts without borrow",
+            &[0x8125],
+            |cpu| {
+                cpu.v[1] = 0x30;
+                cpu.v[2] = 0x10;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[1], 0x20);
+                assert_eq!(cpu.v[0xf], 1);
+            },
+        ),
+        case(
+            "8XY5 subtracts with borrow",
+            &[0x8125],
+            |cpu| {
+                cpu.v[1] = 0x10;
+                cpu.v[2] = 0x30;
+                cpu.v[0xf] = 0x55;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[1], 0xe0);
+                assert_eq!(cpu.v[0xf], 0);
+            },
+        ),
+        case(
+            "8XY5 with VX equal to VY doesn't borrow",
+            &[0x8125],
+            |cpu| {
+                cpu.v[1] = 0x20;
+                cpu.v[2] = 0x20;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[1], 0);
+                assert_eq!(cpu.v[0xf], 1);
+            },
+        ),
+        case(
+            "8XY5 reads VF as VY before setting it",
+            &[0x81f5],
+            |cpu| {
+                cpu.v[1] = 0x30;
+                cpu.v[0xf] = 0x10;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[1], 0x20);
+                assert_eq!(cpu.v[0xf], 1);
+            },
+        ),
+        case(
+            "8XY5 into VF keeps the flag",
+            &[0x8f15],
+            |cpu| {
+                cpu.v[0xf] = 0x10;
+                cpu.v[1] = 0x30;
+            },
+            |cpu, _| assert_eq!(cpu.v[0xf], 0),
+        ),
+        case(
+            "8XY6 shifts right",
+            &[0x8126],
+            |cpu| {
+                cpu.v[1] = 0x05;
+                cpu.v[2] = 0x0c;
+            },
+            |cpu, quirks| match quirks.shift_vy {
+                true => {
+                    assert_eq!(cpu.v[1], 0x06);
+                    assert_eq!(cpu.v[0xf], 0);
+                }
+                false => {
+                    assert_eq!(cpu.v[1], 0x02);
+                    assert_eq!(cpu.v[0xf], 1);
+                }
+            },
+        ),
+        case(
+            "8XY6 into VF keeps the flag",
+            &[0x8ff6],
+            |cpu| cpu.v[0xf] = 0x03,
+            |cpu, _| assert_eq!(cpu.v[0xf], 1),
+        ),
+        case(
+            "8XY7 subtracts VX from VY without borrow",
+            &[0x8127],
+            |cpu| {
+                cpu.v[1] = 0x10;
+                cpu.v[2] = 0x30;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[1], 0x20);
+                assert_eq!(cpu.v[0xf], 1);
+            },
+        ),
+        case(
+            "8XY7 subtracts VX from VY with borrow",
+            &[0x8127],
+            |cpu| {
+                cpu.v[1] = 0x30;
+                cpu.v[2] = 0x10;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[1], 0xe0);
+                assert_eq!(cpu.v[0xf], 0);
+            },
+        ),
+        case(
+            "8XY7 with VX equal to VY doesn't borrow",
+            &[0x8127],
+            |cpu| {
+                cpu.v[1] = 0x20;
+                cpu.v[2] = 0x20;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.v[1], 0);
+                assert_eq!(cpu.v[0xf], 1);
+            },
+        ),
+        case(
+            "8XYE shifts left",
+            &[0x812e],
+            |cpu| {
+                cpu.v[1] = 0x81;
+                cpu.v[2] = 0x41;
+            },
+            |cpu, quirks| match quirks.shift_vy {
+                true => {
+                    assert_eq!(cpu.v[1], 0x82);
+                    assert_eq!(cpu.v[0xf], 0);
+                }
+                false => {
+                    assert_eq!(cpu.v[1], 0x02);
+                    assert_eq!(cpu.v[0xf], 1);
+                }
+            },
+        ),
+        case(
+            "CXNN masks a random number with NN",
+            &[0xc10f],
+            |cpu| cpu.v[1] = 0xff,
+            |cpu, _| assert_eq!(cpu.v[1] & 0xf0, 0),
+        ),
+        case(
+            "CXNN with NN of zero is zero",
+            &[0xc100],
+            |cpu| cpu.v[1] = 0xff,
+            |cpu, _| assert_eq!(cpu.v[1], 0),
+        ),
+        // Index register
+        case(
+            "ANNN loads I",
+            &[0xaabc],
+            |_| {},
+            |cpu, _| assert_eq!(cpu.idxr, 0xabc),
+        ),
+        case(
+            "FX1E adds VX to I",
+            &[0xf11e],
+            |cpu| {
+                cpu.idxr = 0x300;
+                cpu.v[1] = 0x42;
+                cpu.v[0xf] = 0x55;
+            },
+            |cpu, quirks| {
+                assert_eq!(cpu.idxr, 0x342);
+                match quirks.index_overflow_sets_vf {
+                    true => assert_eq!(cpu.v[0xf], 0),
+                    false => assert_eq!(cpu.v[0xf], 0x55),
+                }
+            },
+        ),
+        case(
+            "FX1E wraps I past 0xFFF",
+            &[0xf11e],
+            |cpu| {
+                cpu.idxr = 0xff0;
+                cpu.v[1] = 0x20;
+                cpu.v[0xf] = 0x55;
+            },
+            |cpu, quirks| {
+                assert_eq!(cpu.idxr, 0x010);
+                match quirks.index_overflow_sets_vf {
+                    true => assert_eq!(cpu.v[0xf], 1),
+                    false => assert_eq!(cpu.v[0xf], 0x55),
+                }
+            },
+        ),
+        case(
+            "FX1E uses all 16 bits of I on XO-CHIP",
+            &[0xf11e],
+            |cpu| {
+                cpu.idxr = 0xff0;
+                cpu.v[1] = 0x20;
+                cpu.v[0xf] = 0x55;
+            },
+            |cpu, quirks| {
+                assert_eq!(cpu.idxr, 0x1010);
+                match quirks.index_overflow_sets_vf {
+                    true => assert_eq!(cpu.v[0xf], 0),
+                    false => assert_eq!(cpu.v[0xf], 0x55),
+                }
+            },
+        )
+        .on(Platform::XoChip),
+        case(
+            "FX29 points I at a small font character",
+            &[0xf129],
+            |cpu| cpu.v[1] = 0xa,
+            |cpu, _| assert_eq!(cpu.idxr, 50),
+        ),
+        case(
+            "FX29 only uses the low nibble of VX",
+            &[0xf129],
+            |cpu| cpu.v[1] = 0x3a,
+            |cpu, _| assert_eq!(cpu.idxr, 50),
+        ),
+        case(
+            "FX30 points I at a big font character",
+            &[0xf130],
+            |cpu| cpu.v[1] = 0x2,
+            |cpu, _| assert_eq!(cpu.idxr, 80 + 20),
+        )
+        .on(Platform::SuperChip),
+        case(
+            "F000 NNNN loads a 16 bit address into I",
+            &[0xf000, 0xabcd],
+            |_| {},
+            |cpu, _| {
+                assert_eq!(cpu.idxr, 0xabcd);
+                assert_eq!(cpu.pc, SKIPPED);
+            },
+        )
+        .on(Platform::XoChip),
+        // Drawing
+        case(
+            "DXYN draws at VX, VY",
+            &[0xd125],
+            |cpu| {
+                cpu.v[1] = 10;
+                cpu.v[2] = 5;
+                cpu.idxr = 0;
+            },
+            |cpu, _| {
+                // Top row of the font's 0
+                assert_eq!(pixel(cpu, 10, 5), 1);
+                assert_eq!(pixel(cpu, 13, 5), 1);
+                assert_eq!(pixel(cpu, 14, 5), 0);
+                assert_eq!(pixel(cpu, 11, 6), 0);
+                assert_eq!(cpu.v[0xf], 0);
+                assert!(cpu.draw_flag);
+            },
+        ),
+        case(
+            "DXYN sets VF on collision",
+            &[0xd121],
+            |cpu| {
+                cpu.v[1] = 10;
+                cpu.v[2] = 5;
+                cpu.idxr = 0;
+                set_pixel(cpu, 11, 5, 1);
+            },
+            |cpu, _| assert_eq!(cpu.v[0xf], 1),
+        ),
+        case(
+            "DXYN wraps the start position",
+            &[0xd121],
+            |cpu| {
+                cpu.v[1] = 64 + 10;
+                cpu.v[2] = 32 + 5;
+                cpu.idxr = 0;
+            },
+            |cpu, _| assert_eq!(pixel(cpu, 10, 5), 1),
+        ),
+        case(
+            "DXYN XORs pixels off again",
+            &[0xd121],
+            |cpu| {
+                cpu.idxr = 0;
+                set_pixel(cpu, 0, 0, 1);
+                set_pixel(cpu, 4, 0, 1);
+            },
+            |cpu, _| {
+                assert_eq!(pixel(cpu, 0, 0), 0);
+                assert_eq!(pixel(cpu, 3, 0), 1);
+                assert_eq!(pixel(cpu, 4, 0), 1);
+            },
+        ),
+        case(
+            "DXYN clips or wraps past the edges",
+            &[0xd125],
+            |cpu| {
+                cpu.v[1] = 62;
+                cpu.v[2] = 30;
+                cpu.idxr = 0;
+            },
+            |cpu, quirks| {
+                assert_eq!(pixel(cpu, 62, 30), 1);
+                assert_eq!(pixel(cpu, 63, 31), 0);
+                let wrapped = [pixel(cpu, 0, 30), pixel(cpu, 62, 0), pixel(cpu, 1, 2)];
+                match quirks.clip_sprites {
+                    true => assert_eq!(wrapped, [0, 0, 0]),
+                    false => assert_eq!(wrapped, [1, 1, 1]),
+                }
+            },
+        ),
+        case(
+            "DXY0 draws a 16x16 sprite",
+            &[0xd120],
+            |cpu| {
+                cpu.v[1] = 100;
+                cpu.v[2] = 40;
+                cpu.hires = true;
+                cpu.idxr = 0x300;
+                cpu.ram[0x300..0x320]
+                    .iter_mut()
+                    .for_each(|byte| *byte = 0xff);
+            },
+            |cpu, _| {
+                assert_eq!(pixel(cpu, 100, 40), 1);
+                assert_eq!(pixel(cpu, 115, 55), 1);
+                assert_eq!(pixel(cpu, 116, 55), 0);
+            },
+        )
+        .on(Platform::SuperChip),
+        case(
+            "FN01 selects the planes",
+            &[0xf201],
+            |_| {},
+            |cpu, _| assert_eq!(cpu.planes, 0b10),
+        )
+        .on(Platform::XoChip),
+        // Timers and keys
+        case(
+            "FX07 reads the delay timer",
+            &[0xf107],
+            |cpu| cpu.delay_timer = 0x42,
+            |cpu, _| assert_eq!(cpu.v[1], 0x42),
+        ),
+        case(
+            "FX0A waits for a key",
+            &[0xf10a],
+            |_| {},
+            |cpu, _| {
+                assert!(cpu.waiting_for_key);
+                assert_eq!(cpu.key_register, 1);
+            },
+        ),
+        case(
+            "FX15 sets the delay timer",
+            &[0xf115],
+            |cpu| cpu.v[1] = 0x42,
+            |cpu, _| assert_eq!(cpu.delay_timer, 0x42),
+        ),
+        case(
+            "FX18 sets the sound timer",
+            &[0xf118],
+            |cpu| cpu.v[1] = 0x42,
+            |cpu, _| assert_eq!(cpu.sound_timer, 0x42),
+        ),
+        // Memory
+        case(
+            "FX33 stores BCD",
+            &[0xf133],
+            |cpu| {
+                cpu.v[1] = 234;
+                cpu.idxr = 0x300;
+            },
+            |cpu, _| assert_eq!(cpu.ram[0x300..0x303], [2, 3, 4]),
+        ),
+        case(
+            "FX55 stores V0 to VX",
+            &[0xf255],
+            |cpu| {
+                cpu.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+                cpu.idxr = 0x300;
+            },
+            |cpu, quirks| {
+                assert_eq!(cpu.ram[0x300..0x304], [1, 2, 3, 0]);
+                assert_eq!(cpu.idxr, 0x300 + quirks.load_store_increment.amount(2));
+            },
+        ),
+        case(
+            "FX65 loads V0 to VX",
+            &[0xf265],
+            |cpu| {
+                cpu.ram[0x300..0x304].copy_from_slice(&[1, 2, 3, 4]);
+                cpu.idxr = 0x300;
+            },
+            |cpu, quirks| {
+                assert_eq!(cpu.v[..4], [1, 2, 3, 0]);
+                assert_eq!(cpu.idxr, 0x300 + quirks.load_store_increment.amount(2));
+            },
+        ),
+        case(
+            "5XY2 stores VX to VY",
+            &[0x5312],
+            |cpu| {
+                cpu.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+                cpu.idxr = 0x300;
+            },
+            |cpu, _| {
+                assert_eq!(cpu.ram[0x300..0x304], [4, 3, 2, 0]);
+                assert_eq!(cpu.idxr, 0x300);
+            },
+        )
+        .on(Platform::XoChip),
+        case(
+            "5XY3 loads VX to VY",
+            &[0x5133],
+            |cpu| {
+                cpu.ram[0x300..0x304].copy_from_slice(&[1, 2, 3, 4]);
+                cpu.idxr = 0x300;
+            },
+            |cpu, _| assert_eq!(cpu.v[..5], [0, 1, 2, 3, 0]),
+        )
+        .on(Platform::XoChip),
+        case(
+            "FX75 saves the RPL flags",
+            &[0xf275],
+            |cpu| cpu.v[..4].copy_from_slice(&[1, 2, 3, 4]),
+            |cpu, _| assert_eq!(cpu.rpl[..4], [1, 2, 3, 0]),
+        )
+        .on(Platform::SuperChip),
+        case(
+            "FX85 loads the RPL flags",
+            &[0xf285],
+            |cpu| cpu.rpl[..4].copy_from_slice(&[1, 2, 3, 4]),
+            |cpu, _| assert_eq!(cpu.v[..4], [1, 2, 3, 0]),
+        )
+        .on(Platform::SuperChip),
+        // XO-CHIP audio
+        case(
+            "F002 loads the audio pattern",
+            &[0xf002],
+            |cpu| {
+                cpu.ram[0x300..0x310]
+                    .iter_mut()
+                    .for_each(|byte| *byte = 0xaa);
+                cpu.idxr = 0x300;
+            },
+            |cpu, _| assert_eq!(cpu.audio_pattern, [0xaa; 16]),
+        )
+        .on(Platform::XoChip),
+        case(
+            "FX3A sets the pitch",
+            &[0xf13a],
+            |cpu| cpu.v[1] = 0x42,
+            |cpu, _| assert_eq!(cpu.pitch, 0x42),
+        )
+        .on(Platform::XoChip),
+    ]
+}
+
+fn run(case: &Case, quirks: Quirks) -> Processor {
+    let mut cpu = Processor::initialize();
+    cpu.set_platform(case.platform);
+    cpu.set_quirks(quirks);
+    let program: Vec<u8> = case
+        .program
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect();
+    cpu.load_rom(&program).unwrap();
+    (case.setup)(&mut cpu);
+    cpu.run_cycle(keys_from_bits(case.keys))
+        .unwrap_or_else(|err| panic!("{}: {}", case.name, err));
+    cpu
+}
+
+#[test]
+fn every_case_under_every_preset() {
+    let mut failures = Vec::new();
+    // The presets plus the quirks none of them turn on
+    let mut presets = PRESETS.to_vec();
+    presets.push((
+        "modern+vf",
+        Quirks {
+            index_overflow_sets_vf: true,
+            ..MODERN
+        },
+    ));
+    for (preset, quirks) in presets.iter() {
+        for case in cases().iter() {
+            let cpu = run(case, *quirks);
+            let result = panic::catch_unwind(AssertUnwindSafe(|| (case.check)(&cpu, *quirks)));
+            if result.is_err() {
+                failures.push(format!("{} ({} quirks)", case.name, preset));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "failed cases:\n{}",
+        failures.join("\n")
+    );
+}
+
+// Groups opcodes that run the same handler
+fn family(opcode: u16) -> u16 {
+    match opcode >> 12 {
+        0x0 => match opcode & 0xfff0 {
+            0x00c0 | 0x00d0 => opcode & 0xfff0,
+            _ => opcode,
+        },
+        0x5 | 0x8 | 0x9 => opcode & 0xf00f,
+        0xe | 0xf => opcode & 0xf0ff,
+        _ => opcode & 0xf000,
+    }
+}
+
+#[test]
+fn every_opcode_has_a_case() {
+    let covered: Vec<u16> = cases().iter().map(|case| family(case.program[0])).collect();
+    // Every handler can be reached with X and Y of 0 or 1
+    for high in 0x00..=0xff_u16 {
+        if high & 0x0e != 0 {
+            continue;
+        }
+        for low in 0x00..=0xff_u16 {
+            let opcode = high << 8 | low;
+            let mut cpu = Processor::initialize();
+            cpu.set_platform(Platform::XoChip);
+            cpu.ram[PROGRAM_START..PROGRAM_START + 2].copy_from_slice(&opcode.to_be_bytes());
+            match cpu.run_cycle([false; 16]) {
+                Err(Chip8Error::UnknownOpcode { .. }) => (),
+                _ => assert!(
+                    covered.contains(&family(opcode)),
+                    "no case for {:04x}",
+                    opcode
+                ),
+            }
+        }
+    }
+}
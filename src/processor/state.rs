@@ -0,0 +1,299 @@
+use std::{error::Error, fmt, fs, io, path::Path};
+
+use super::{keys_from_bits, keys_to_bits, Processor, VRAM, XO_RAM};
+use crate::{
+    bytes::{Malformed, Reader},
+    platform::Platform,
+    quirks::Quirks,
+};
+
+// Save state layout, all numbers little endian:
+//
+// magic "C8ST", version u16, rom hash u64, then the processor state in the
+// order it is written by `save_state`
+const MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u16 = 1;
+
+#[derive(Debug)]
+pub enum StateError {
+    Io(io::Error),
+    // Doesn't start with the magic bytes
+    NotAState,
+    UnsupportedVersion(u16),
+    // Made while running a different rom, `expected` is the rom it was made
+    // for and `found` the running one
+    WrongRom { expected: u64, found: u64 },
+    // Truncated or contains impossible values
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::Io(err) => write!(f, "{}", err),
+            StateError::NotAState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::WrongRom { expected, found } => write!(
+                f,
+                "save state is for rom {:016x}, not the running rom {:016x}",
+                expected, found
+            ),
+            StateError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl Error for StateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StateError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<Malformed> for StateError {
+    fn from(_: Malformed) -> Self {
+        StateError::Corrupt
+    }
+}
+
+impl From<io::Error> for StateError {
+    fn from(err: io::Error) -> Self {
+        StateError::Io(err)
+    }
+}
+
+impl Processor {
+    // Serializes everything needed to carry on running from this point later
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(XO_RAM + VRAM + 256);
+        state.extend_from_slice(MAGIC);
+        state.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        state.extend_from_slice(&self.rom_hash.to_le_bytes());
+
+        state.push(self.platform.id());
+        state.push(self.quirks.to_bits());
+        state.extend_from_slice(&self.v);
+        state.extend_from_slice(&self.idxr.to_le_bytes());
+        state.extend_from_slice(&(self.pc as u32).to_le_bytes());
+        state.extend_from_slice(&self.ram);
+        state.extend_from_slice(&self.vram);
+        state.push(self.hires as u8);
+        state.push(self.planes);
+        for &address in self.stack.iter() {
+            state.extend_from_slice(&(address as u32).to_le_bytes());
+        }
+        state.push(self.sp as u8);
+        state.extend_from_slice(&keys_to_bits(self.keys).to_le_bytes());
+        state.push(self.waiting_for_key as u8);
+        state.push(self.key_register as u8);
+        state.push(self.delay_timer);
+        state.push(self.sound_timer);
+        state.extend_from_slice(&self.audio_pattern);
+        state.push(self.pitch);
+        state.extend_from_slice(&self.rpl);
+        state.push(self.exited as u8);
+        state.extend_from_slice(&self.seed.to_le_bytes());
+        match self.rng.state() {
+            Some(rng) => {
+                state.push(1);
+                state.extend_from_slice(&rng.to_le_bytes());
+            }
+            None => {
+                state.push(0);
+                state.extend_from_slice(&0u64.to_le_bytes());
+            }
+        }
+        state
+    }
+
+    // Restores a state from `save_state`. States made while running a
+    // different rom are refused unless `force` is set. Nothing is changed if
+    // the state can't be loaded.
+    pub fn load_state(&mut self, state: &[u8], force: bool) -> Result<(), StateError> {
+        let mut reader = Reader::new(state);
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(StateError::NotAState);
+        }
+        let version = reader.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let rom_hash = reader.u64()?;
+        if rom_hash != self.rom_hash && !force {
+            return Err(StateError::WrongRom {
+                expected: rom_hash,
+                found: self.rom_hash,
+            });
+        }
+
+        let platform = Platform::from_id(reader.u8()?).ok_or(StateError::Corrupt)?;
+        let quirks = Quirks::from_bits(reader.u8()?);
+        let v = reader.array::<16>()?;
+        let idxr = reader.u16()?;
+        let pc = reader.u32()? as usize;
+        let ram = reader.array::<XO_RAM>()?;
+        let vram = reader.array::<VRAM>()?;
+        let hires = reader.bool()?;
+        let planes = reader.u8()?;
+        let mut stack = [0; 16];
+        for address in stack.iter_mut() {
+            *address = reader.u32()? as usize;
+        }
+        let sp = reader.u8()? as usize;
+        let key_bits = reader.u16()?;
+        let waiting_for_key = reader.bool()?;
+        let key_register = reader.u8()? as usize;
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let audio_pattern = reader.array::<16>()?;
+        let pitch = reader.u8()?;
+        let rpl = reader.array::<16>()?;
+        let exited = reader.bool()?;
+        let seed = reader.u64()?;
+        let has_rng_state = reader.bool()?;
+        let rng_state = reader.u64()?;
+
+        if pc >= XO_RAM || sp > stack.len() || key_register > 0x0f || planes > 0b11 {
+            return Err(StateError::Corrupt);
+        }
+
+        self.rom_hash = rom_hash;
+        self.platform = platform;
+        self.quirks = quirks;
+        self.v = v;
+        self.idxr = idxr;
+        self.pc = pc;
+        self.ram = ram;
+        self.vram = vram;
+        self.draw_flag = true;
+        self.hires = hires;
+        self.planes = planes;
+        self.stack = stack;
+        self.sp = sp;
+        self.keys = keys_from_bits(key_bits);
+        self.waiting_for_key = waiting_for_key;
+        self.key_register = key_register;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.rpl = rpl;
+        self.exited = exited;
+        self.seed = seed;
+        if has_rng_state {
+            self.rng.set_state(rng_state);
+        }
+        Ok(())
+    }
+
+    pub fn save_state_file<P: AsRef<Path>>(&self, path: P) -> Result<(), StateError> {
+        fs::write(path, self.save_state())?;
+        Ok(())
+    }
+
+    pub fn load_state_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        force: bool,
+    ) -> Result<(), StateError> {
+        let state = fs::read(path)?;
+        self.load_state(&state, force)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::platform::Platform;
+    use crate::processor::{state::StateError, Processor};
+    use crate::quirks;
+
+    const KEYS: [bool; 16] = [false; 16];
+
+    // A rom that calls a subroutine and keeps drawing random sprites
+    const ROM: [u8; 14] = [
+        0x22, 0x04, // call 0x204
+        0xf0, 0x0a, // wait for key
+        0xc1, 0xff, // v1 = random
+        0xa0, 0x00, // I = 0
+        0xd1, 0x15, // draw
+        0xf1, 0x15, // delay timer = v1
+        0x12, 0x04, // jump 0x204
+    ];
+
+    fn running() -> Processor {
+        let mut cpu = Processor::initialize();
+        cpu.set_platform(Platform::SuperChip);
+        cpu.set_quirks(quirks::SUPER_CHIP);
+        cpu.set_seed(99);
+        cpu.load_rom(&ROM).unwrap();
+        for _ in 0..4 {
+            cpu.run_cycle(KEYS).unwrap();
+        }
+        cpu
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut cpu = running();
+        let state = cpu.save_state();
+
+        let mut restored = Processor::initialize();
+        restored.load_rom(&ROM).unwrap();
+        restored.load_state(&state, false).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.platform(), Platform::SuperChip);
+        assert_eq!(restored.quirks(), quirks::SUPER_CHIP);
+        assert_eq!(restored.seed(), 99);
+
+        // Both carry on identically, including the random numbers
+        for _ in 0..3 {
+            cpu.run_cycle(KEYS).unwrap();
+            restored.run_cycle(KEYS).unwrap();
+        }
+        assert_eq!(restored.save_state(), cpu.save_state());
+    }
+
+    #[test]
+    fn wrong_rom() {
+        let state = running().save_state();
+        let mut other = Processor::initialize();
+        other.load_rom(&[0x12, 0x00]).unwrap();
+        let before = other.save_state();
+
+        match other.load_state(&state, false) {
+            Err(StateError::WrongRom { expected, found }) => {
+                assert_eq!(expected, running().rom_hash());
+                assert_eq!(found, other.rom_hash());
+            }
+            _ => panic!("loaded a state made for another rom"),
+        }
+        assert_eq!(other.save_state(), before);
+
+        other.load_state(&state, true).unwrap();
+        assert_eq!(other.save_state(), state);
+    }
+
+    #[test]
+    fn bad_states() {
+        let mut cpu = running();
+        let mut state = cpu.save_state();
+
+        assert!(matches!(
+            cpu.load_state(b"nope", false),
+            Err(StateError::NotAState)
+        ));
+        assert!(matches!(
+            cpu.load_state(&state[..state.len() - 1], false),
+            Err(StateError::Corrupt)
+        ));
+        state[4] = 99;
+        assert!(matches!(
+            cpu.load_state(&state, false),
+            Err(StateError::UnsupportedVersion(99))
+        ));
+    }
+}
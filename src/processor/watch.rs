@@ -0,0 +1,135 @@
+use crate::processor::{Chip8Error, Processor};
+
+// A range of ram to keep an eye on, reads and writes by instructions that
+// touch it are reported through `Processor::watch_hit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watch {
+    pub start: usize,
+    pub len: usize,
+    pub read: bool,
+    pub write: bool,
+}
+
+// A watched access by the instruction at `pc`, `address` is the first watched
+// byte it touched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub pc: usize,
+    pub address: usize,
+    pub write: bool,
+}
+
+impl Watch {
+    fn hit_by(&self, address: usize, len: usize, write: bool) -> bool {
+        let wanted = match write {
+            true => self.write,
+            false => self.read,
+        };
+        wanted && address < self.start + self.len && self.start < address + len
+    }
+}
+
+// Instructions read and write ram through these rather than indexing it, so
+// the bounds checks and watches apply to every access
+impl Processor {
+    pub fn watches(&self) -> &[Watch] {
+        &self.watches
+    }
+
+    pub fn watches_mut(&mut self) -> &mut Vec<Watch> {
+        &mut self.watches
+    }
+
+    // The watched access made by the last instruction, if there was one
+    pub fn watch_hit(&self) -> Option<WatchHit> {
+        self.watch_hit
+    }
+
+    pub(super) fn read_ram(
+        &mut self,
+        opcode: u16,
+        address: usize,
+        len: usize,
+    ) -> Result<&[u8], Chip8Error> {
+        self.check_ram(opcode, address, len)?;
+        self.notify(address, len, false);
+        Ok(&self.ram[address..address + len])
+    }
+
+    pub(super) fn write_ram(
+        &mut self,
+        opcode: u16,
+        address: usize,
+        bytes: &[u8],
+    ) -> Result<(), Chip8Error> {
+        self.check_ram(opcode, address, bytes.len())?;
+        self.notify(address, bytes.len(), true);
+        self.ram[address..address + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn notify(&mut self, address: usize, len: usize, write: bool) {
+        let hit = self
+            .watches
+            .iter()
+            .find(|watch| watch.hit_by(address, len, write));
+        if let Some(watch) = hit {
+            self.watch_hit = Some(WatchHit {
+                pc: self.pc,
+                address: address.max(watch.start),
+                write,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::processor::{Processor, Watch, WatchHit};
+
+    const KEYS: [bool; 16] = [false; 16];
+
+    #[test]
+    fn watches() {
+        let mut cpu = Processor::initialize();
+        // 0x200: I = 0x300, store V0-V3, load V0-V1, draw a 1 row sprite
+        cpu.load_rom(&[0xa3, 0x00, 0xf3, 0x55, 0xf1, 0x65, 0xd0, 0x01])
+            .unwrap();
+        cpu.watches_mut().push(Watch {
+            start: 0x303,
+            len: 2,
+            read: false,
+            write: true,
+        });
+        cpu.watches_mut().push(Watch {
+            start: 0x300,
+            len: 1,
+            read: true,
+            write: false,
+        });
+
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.watch_hit(), None);
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(
+            cpu.watch_hit(),
+            Some(WatchHit {
+                pc: 0x202,
+                address: 0x303,
+                write: true
+            })
+        );
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(
+            cpu.watch_hit(),
+            Some(WatchHit {
+                pc: 0x204,
+                address: 0x300,
+                write: false
+            })
+        );
+        // Sprite data is read through the same path
+        cpu.run_cycle(KEYS).unwrap();
+        assert_eq!(cpu.watch_hit().map(|hit| hit.pc), Some(0x206));
+    }
+}
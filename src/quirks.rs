@@ -0,0 +1,179 @@
+// Instructions that behave differently between chip-8 interpreters. Roms
+// written for one interpreter often break when run with another's behavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6 and 8XYE shift VY and store the result in VX, rather than shifting
+    // VX in place
+    pub shift_vy: bool,
+    // How far FX55 and FX65 move I
+    pub load_store_increment: IndexIncrement,
+    // BNNN jumps to NNN plus VX (where X is the highest nibble of NNN) rather
+    // than NNN plus V0
+    pub jump_vx: bool,
+    // 8XY1, 8XY2 and 8XY3 set VF to 0
+    pub logic_resets_vf: bool,
+    // DXYN cuts sprites off at the edge of the screen rather than wrapping
+    // them around to the other side
+    pub clip_sprites: bool,
+    // FX1E sets VF to 1 when I runs past the end of memory and to 0 when it
+    // doesn't, like the Amiga interpreter Spacefight 2091! relies on
+    pub index_overflow_sets_vf: bool,
+    // DXYN in high resolution sets VF to the number of sprite rows that
+    // collided or were clipped off the bottom, like SUPER-CHIP 1.1, rather
+    // than just 1 for any collision
+    pub collision_rows: bool,
+}
+
+// Where FX55 and FX65 leave I after storing or loading V0 to VX
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    // Where it started
+    Unchanged,
+    // On the last register, like CHIP-48 which adds X
+    ByX,
+    // After the last register, like the VIP which adds X plus 1
+    ByXPlusOne,
+}
+
+impl IndexIncrement {
+    // What to add to I after storing or loading V0 to VX
+    pub fn amount(self, x: usize) -> u16 {
+        match self {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::ByX => x as u16,
+            IndexIncrement::ByXPlusOne => x as u16 + 1,
+        }
+    }
+}
+
+// The original interpreter on the COSMAC VIP
+pub const COSMAC_VIP: Quirks = Quirks {
+    shift_vy: true,
+    load_store_increment: IndexIncrement::ByXPlusOne,
+    jump_vx: false,
+    logic_resets_vf: true,
+    clip_sprites: true,
+    index_overflow_sets_vf: false,
+    collision_rows: false,
+};
+
+// CHIP-48 on the HP-48 calculators
+pub const CHIP_48: Quirks = Quirks {
+    shift_vy: false,
+    load_store_increment: IndexIncrement::ByX,
+    jump_vx: true,
+    logic_resets_vf: false,
+    clip_sprites: true,
+    index_overflow_sets_vf: false,
+    collision_rows: false,
+};
+
+// SUPER-CHIP 1.1
+pub const SUPER_CHIP: Quirks = Quirks {
+    shift_vy: false,
+    load_store_increment: IndexIncrement::Unchanged,
+    jump_vx: true,
+    logic_resets_vf: false,
+    clip_sprites: true,
+    index_overflow_sets_vf: false,
+    collision_rows: true,
+};
+
+// What most modern interpreters and newer roms expect
+pub const MODERN: Quirks = Quirks {
+    shift_vy: false,
+    load_store_increment: IndexIncrement::Unchanged,
+    jump_vx: false,
+    logic_resets_vf: false,
+    clip_sprites: false,
+    index_overflow_sets_vf: false,
+    collision_rows: false,
+};
+
+pub const PRESETS: [(&str, Quirks); 4] = [
+    ("vip", COSMAC_VIP),
+    ("chip48", CHIP_48),
+    ("schip", SUPER_CHIP),
+    ("modern", MODERN),
+];
+
+// Bit for each quirk when packed into a byte, the load/store increment takes
+// two: one for adding X and one for adding 1 on top of that
+const SHIFT_VY: u8 = 0b0000_0001;
+const LOAD_STORE_ADDS_X: u8 = 0b0000_0010;
+const JUMP_VX: u8 = 0b0000_0100;
+const LOGIC_RESETS_VF: u8 = 0b0000_1000;
+const CLIP_SPRITES: u8 = 0b0001_0000;
+const LOAD_STORE_ADDS_ONE: u8 = 0b0010_0000;
+const INDEX_OVERFLOW_SETS_VF: u8 = 0b0100_0000;
+const COLLISION_ROWS: u8 = 0b1000_0000;
+
+impl Default for Quirks {
+    fn default() -> Self {
+        MODERN
+    }
+}
+
+impl Quirks {
+    pub fn preset(name: &str) -> Option<Quirks> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, quirks)| *quirks)
+    }
+
+    // Packs the quirks into a byte for saving to a file
+    pub fn to_bits(self) -> u8 {
+        let flags = [
+            (self.shift_vy, SHIFT_VY),
+            (
+                self.load_store_increment != IndexIncrement::Unchanged,
+                LOAD_STORE_ADDS_X,
+            ),
+            (self.jump_vx, JUMP_VX),
+            (self.logic_resets_vf, LOGIC_RESETS_VF),
+            (self.clip_sprites, CLIP_SPRITES),
+            (
+                self.load_store_increment == IndexIncrement::ByXPlusOne,
+                LOAD_STORE_ADDS_ONE,
+            ),
+            (self.index_overflow_sets_vf, INDEX_OVERFLOW_SETS_VF),
+            (self.collision_rows, COLLISION_ROWS),
+        ];
+        flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .fold(0, |bits, (_, bit)| bits | bit)
+    }
+
+    pub fn from_bits(bits: u8) -> Quirks {
+        Quirks {
+            shift_vy: bits & SHIFT_VY != 0,
+            load_store_increment: match (
+                bits & LOAD_STORE_ADDS_X != 0,
+                bits & LOAD_STORE_ADDS_ONE != 0,
+            ) {
+                (false, _) => IndexIncrement::Unchanged,
+                (true, false) => IndexIncrement::ByX,
+                (true, true) => IndexIncrement::ByXPlusOne,
+            },
+            jump_vx: bits & JUMP_VX != 0,
+            logic_resets_vf: bits & LOGIC_RESETS_VF != 0,
+            clip_sprites: bits & CLIP_SPRITES != 0,
+            index_overflow_sets_vf: bits & INDEX_OVERFLOW_SETS_VF != 0,
+            collision_rows: bits & COLLISION_ROWS != 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::quirks::{Quirks, PRESETS};
+
+    #[test]
+    fn bits_round_trip() {
+        for (_, quirks) in PRESETS.iter() {
+            assert_eq!(Quirks::from_bits(quirks.to_bits()), *quirks);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+// Where CXNN gets its random numbers from
+pub trait RandomSource {
+    fn next_u8(&mut self) -> u8;
+
+    // Everything needed to carry on the same sequence later, for save states.
+    // Sources that can't be captured return None.
+    fn state(&self) -> Option<u64> {
+        None
+    }
+
+    fn set_state(&mut self, _state: u64) {}
+}
+
+// Used until something else is seeded, so runs are repeatable by default
+pub const DEFAULT_SEED: u64 = 0x00c0_ffee;
+
+// SplitMix64, small and fast with a single word of state
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn state(&self) -> Option<u64> {
+        Some(self.state)
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::random::{RandomSource, SplitMix64};
+
+    #[test]
+    fn repeatable() {
+        let mut a = SplitMix64::new(42);
+        let mut b = SplitMix64::new(42);
+        let a: Vec<u8> = (0..32).map(|_| a.next_u8()).collect();
+        let b: Vec<u8> = (0..32).map(|_| b.next_u8()).collect();
+        assert_eq!(a, b);
+        assert!(a.iter().any(|&n| n != a[0]));
+    }
+
+    #[test]
+    fn restore_state() {
+        let mut rng = SplitMix64::new(7);
+        rng.next_u8();
+        let state = rng.state().unwrap();
+        let expected: Vec<u8> = (0..8).map(|_| rng.next_u8()).collect();
+
+        rng.set_state(state);
+        let actual: Vec<u8> = (0..8).map(|_| rng.next_u8()).collect();
+        assert_eq!(actual, expected);
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+
+use crate::processor::Processor;
+
+// Default length of the rewind buffer
+pub const DEFAULT_REWIND_SECONDS: u32 = 10;
+
+// Keeps the last `capacity` frames so the emulation can be played backwards.
+//
+// Only the newest state is kept whole. Every older frame is stored as the XOR
+// of it and the frame after it, run length encoded. Between two frames almost
+// all of memory stays the same so the XOR is mostly zeros and packs down to a
+// few bytes.
+pub struct Rewind {
+    capacity: usize,
+    // Newest recorded state
+    current: Option<Vec<u8>>,
+    // Oldest first, `deltas.back()` turns `current` into the frame before it
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Self {
+        Rewind {
+            capacity,
+            current: None,
+            deltas: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    // Records the state at the end of a frame
+    pub fn record(&mut self, cpu: &Processor) {
+        let state = cpu.save_state();
+        if let Some(current) = &self.current {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(encode_delta(current, &state));
+        }
+        self.current = Some(state);
+    }
+
+    // Puts `cpu` back to the frame before the newest recorded one. Returns
+    // false once the buffer has run out.
+    pub fn step_back(&mut self, cpu: &mut Processor) -> bool {
+        let (current, delta) = match (&mut self.current, self.deltas.pop_back()) {
+            (Some(current), Some(delta)) => (current, delta),
+            _ => return false,
+        };
+        apply_delta(current, &delta);
+        // The state came from this processor so it always matches the rom
+        cpu.load_state(current, true)
+            .expect("rewind buffer holds a bad state");
+        true
+    }
+
+    // Bytes used by the stored frames
+    #[cfg(test)]
+    fn size(&self) -> usize {
+        let current = self.current.as_ref().map_or(0, Vec::len);
+        current + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+    }
+}
+
+// Delta encoding: a list of runs, each a varint count of unchanged bytes,
+// a varint count of changed bytes, then the changed bytes XORed together
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(old.len(), new.len());
+    let xor: Vec<u8> = old.iter().zip(new).map(|(a, b)| a ^ b).collect();
+    let mut delta = Vec::new();
+    let mut i = 0;
+    while i < xor.len() {
+        let same = xor[i..].iter().take_while(|&&b| b == 0).count();
+        i += same;
+        let changed = xor[i..].iter().take_while(|&&b| b != 0).count();
+        write_varint(&mut delta, same);
+        write_varint(&mut delta, changed);
+        delta.extend_from_slice(&xor[i..i + changed]);
+        i += changed;
+    }
+    delta
+}
+
+// Undoes `encode_delta`, turning `state` into the other side of the delta
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut delta = delta;
+    let mut i = 0;
+    while !delta.is_empty() {
+        i += read_varint(&mut delta);
+        let changed = read_varint(&mut delta);
+        for (byte, xor) in state[i..i + changed].iter_mut().zip(&delta[..changed]) {
+            *byte ^= xor;
+        }
+        delta = &delta[changed..];
+        i += changed;
+    }
+}
+
+// LEB128, 7 bits per byte with the top bit set on all but the last
+fn write_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> usize {
+    let mut n = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[0];
+        *bytes = &bytes[1..];
+        n |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return n;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::processor::Processor;
+    use crate::rewind::{apply_delta, encode_delta, Rewind};
+
+    const KEYS: [bool; 16] = [false; 16];
+
+    #[test]
+    fn deltas() {
+        let old = vec![0u8; 1000];
+        let mut new = old.clone();
+        new[0] = 1;
+        new[500..700].iter_mut().for_each(|b| *b = 0xaa);
+        new[999] = 2;
+
+        let delta = encode_delta(&old, &new);
+        assert!(delta.len() < 220);
+        let mut state = old.clone();
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, new);
+        apply_delta(&mut state, &delta);
+        assert_eq!(state, old);
+
+        assert!(encode_delta(&old, &old).len() <= 3);
+    }
+
+    #[test]
+    fn step_back() {
+        let mut cpu = Processor::initialize();
+        // 0x200: v0 += 1, jump to 0x200
+        cpu.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut rewind = Rewind::new(4);
+        assert!(!rewind.step_back(&mut cpu));
+
+        let mut states = Vec::new();
+        for _ in 0..6 {
+            cpu.run_cycle(KEYS).unwrap();
+            cpu.run_cycle(KEYS).unwrap();
+            rewind.record(&cpu);
+            states.push(cpu.save_state());
+        }
+        // Whole states would take 4 times the size of one
+        assert!(rewind.size() < states[0].len() + 100);
+
+        for state in states.iter().rev().skip(1).take(4) {
+            assert!(rewind.step_back(&mut cpu));
+            assert_eq!(&cpu.save_state(), state);
+        }
+        assert!(!rewind.step_back(&mut cpu));
+        assert_eq!(&cpu.save_state(), &states[1]);
+
+        // Recording carries on from the rewound frame
+        cpu.run_cycle(KEYS).unwrap();
+        rewind.record(&cpu);
+        assert!(rewind.step_back(&mut cpu));
+        assert_eq!(&cpu.save_state(), &states[1]);
+    }
+}
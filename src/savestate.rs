@@ -0,0 +1,57 @@
+use std::path::{Path, PathBuf};
+
+use crate::processor::{Processor, StateError};
+
+// Slots sit next to the rom, e.g. `pong.ch8` saves to `pong.state1`
+pub fn slot_path(rom: &Path, slot: u8) -> PathBuf {
+    rom.with_extension(format!("state{}", slot))
+}
+
+pub fn save_slot(cpu: &Processor, rom: &Path, slot: u8) -> Result<PathBuf, StateError> {
+    let path = slot_path(rom, slot);
+    cpu.save_state_file(&path)?;
+    Ok(path)
+}
+
+pub fn load_slot(
+    cpu: &mut Processor,
+    rom: &Path,
+    slot: u8,
+    force: bool,
+) -> Result<PathBuf, StateError> {
+    let path = slot_path(rom, slot);
+    cpu.load_state_file(&path, force)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path};
+
+    use crate::processor::Processor;
+    use crate::savestate::{load_slot, save_slot, slot_path};
+
+    #[test]
+    fn slot_paths() {
+        let rom = Path::new("roms/pong.ch8");
+        assert_eq!(slot_path(rom, 1), Path::new("roms/pong.state1"));
+        assert_eq!(slot_path(rom, 9), Path::new("roms/pong.state9"));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let rom = env::temp_dir().join(format!("chip8-slots-{}.ch8", std::process::id()));
+        let mut cpu = Processor::initialize();
+        cpu.load_rom(&[0x60, 0x42, 0x12, 0x02]).unwrap();
+        cpu.run_cycle([false; 16]).unwrap();
+
+        let path = save_slot(&cpu, &rom, 3).unwrap();
+        let mut restored = Processor::initialize();
+        restored.load_rom(&[0x60, 0x42, 0x12, 0x02]).unwrap();
+        load_slot(&mut restored, &rom, 3, false).unwrap();
+        assert_eq!(restored.save_state(), cpu.save_state());
+        fs::remove_file(path).unwrap();
+
+        assert!(load_slot(&mut restored, &rom, 4, false).is_err());
+    }
+}
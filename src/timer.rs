@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use crate::processor::Processor;
+
+// The delay and sound timers always count down at 60Hz, no matter how fast
+// the cpu is running
+pub const TIMER_HZ: u32 = 60;
+const TICK: Duration = Duration::from_nanos(1_000_000_000 / TIMER_HZ as u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerSource {
+    // Derive ticks from the number of executed cycles at the given cpu speed,
+    // so a run is the same every time regardless of how fast the host is
+    Cycles { cpu_hz: u32 },
+    // Derive ticks from elapsed wall-clock time
+    RealTime,
+}
+
+pub struct TimerClock {
+    source: TimerSource,
+    // Executed cycles scaled by `TIMER_HZ` that haven't added up to a tick yet
+    cycles: u64,
+    // Elapsed time that hasn't added up to a tick yet
+    elapsed: Duration,
+    last: Instant,
+}
+
+impl TimerClock {
+    pub fn new(source: TimerSource) -> Self {
+        TimerClock {
+            source,
+            cycles: 0,
+            elapsed: Duration::from_secs(0),
+            last: Instant::now(),
+        }
+    }
+
+    // Ticks the processor timers as often as they are due after `cycles` more
+    // instructions have run, returns the number of ticks
+    pub fn run(&mut self, cpu: &mut Processor, cycles: u32) -> u32 {
+        let ticks = self.advance(cycles);
+        for _ in 0..ticks {
+            cpu.tick_timers();
+        }
+        ticks
+    }
+
+    // Returns how many timer ticks are due after `cycles` more instructions
+    pub fn advance(&mut self, cycles: u32) -> u32 {
+        match self.source {
+            TimerSource::Cycles { cpu_hz } => {
+                // Work in cycles * TIMER_HZ so no fraction of a tick is lost
+                let cpu_hz = cpu_hz.max(1) as u64;
+                self.cycles += cycles as u64 * TIMER_HZ as u64;
+                let ticks = self.cycles / cpu_hz;
+                self.cycles %= cpu_hz;
+                ticks as u32
+            }
+            TimerSource::RealTime => self.advance_to(Instant::now()),
+        }
+    }
+
+    // Returns how many timer ticks are due between the last call and `now`
+    pub fn advance_to(&mut self, now: Instant) -> u32 {
+        self.elapsed += now.saturating_duration_since(self.last);
+        self.last = now;
+
+        let mut ticks = 0;
+        while self.elapsed >= TICK {
+            self.elapsed -= TICK;
+            ticks += 1;
+        }
+        ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::timer::{TimerClock, TimerSource};
+
+    #[test]
+    fn cycle_ticks() {
+        let mut clock = TimerClock::new(TimerSource::Cycles { cpu_hz: 600 });
+        assert_eq!(clock.advance(9), 0);
+        assert_eq!(clock.advance(1), 1);
+        assert_eq!(clock.advance(600), 60);
+    }
+
+    #[test]
+    fn cycle_ticks_keep_remainder() {
+        // 700 / 60 isn't a whole number of cycles per tick
+        let mut clock = TimerClock::new(TimerSource::Cycles { cpu_hz: 700 });
+        let ticks: u32 = (0..700).map(|_| clock.advance(1)).sum();
+        assert_eq!(ticks, 60);
+    }
+
+    #[test]
+    fn realtime_ticks() {
+        let mut clock = TimerClock::new(TimerSource::RealTime);
+        let start = Instant::now();
+        clock.last = start;
+
+        assert_eq!(clock.advance_to(start + Duration::from_millis(10)), 0);
+        assert_eq!(clock.advance_to(start + Duration::from_millis(20)), 1);
+        assert_eq!(clock.advance_to(start + Duration::from_secs(1)), 59);
+    }
+}
@@ -0,0 +1,202 @@
+// Runs the roms in tests/roms without a window and compares the final screen
+// to the PBM images in tests/golden. After a change that is meant to alter
+// the output, run with UPDATE_GOLDEN=1 to write the images again and check
+// the diff.
+use std::{env, fs, path::PathBuf};
+
+use chip_8::{
+    dump,
+    emulator::Emulator,
+    platform::Platform,
+    processor::Processor,
+    quirks::{self, Quirks},
+    timer::TimerSource,
+};
+
+const SPEED: u32 = 1000;
+
+struct Case {
+    // Name of the golden image
+    name: &'static str,
+    rom: &'static str,
+    platform: Platform,
+    quirks: Quirks,
+    frames: u32,
+    // Hex key held down for frames `from..to`
+    keys: &'static [(usize, u32, u32)],
+}
+
+fn keys(case: &Case, frame: u32) -> [bool; 16] {
+    let mut keys = [false; 16];
+    for &(key, from, to) in case.keys.iter() {
+        keys[key] |= (from..to).contains(&frame);
+    }
+    keys
+}
+
+fn run(case: &Case) -> Processor {
+    let mut cpu = Processor::initialize();
+    cpu.set_platform(case.platform);
+    cpu.set_quirks(case.quirks);
+    cpu.load_rom_file(fixture("roms", case.rom)).unwrap();
+    let mut emulator = Emulator::new(cpu, SPEED, TimerSource::Cycles { cpu_hz: SPEED });
+    for frame in 0..case.frames {
+        emulator.run_frame(keys(case, frame)).unwrap();
+    }
+    emulator.cpu
+}
+
+fn fixture(dir: &str, name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", dir, name]
+        .iter()
+        .collect()
+}
+
+fn check(case: Case) {
+    let cpu = run(&case);
+    let actual = dump::pbm(&cpu);
+    let path = fixture("golden", &format!("{}.pbm", case.name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected =
+        fs::read_to_string(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    if actual != expected {
+        panic!(
+            "screen doesn't match {}\n\nexpected:\n{}\nfound:\n{}",
+            path.display(),
+            pbm_to_ascii(&expected),
+            dump::ascii(&cpu)
+        );
+    }
+}
+
+// Draws a plain PBM the way `dump::ascii` does, to show failures
+fn pbm_to_ascii(pbm: &str) -> String {
+    pbm.lines()
+        .skip(2)
+        .map(|line| {
+            let row: String = line
+                .split_whitespace()
+                .map(|pixel| match pixel {
+                    "1" => '#',
+                    _ => '.',
+                })
+                .collect();
+            row + "\n"
+        })
+        .collect()
+}
+
+// "CHIP-8" in 8 pixel wide sprites between two lines, drawn at register
+// coordinates
+#[test]
+fn logo() {
+    check(Case {
+        name: "logo",
+        rom: "logo.ch8",
+        platform: Platform::Chip8,
+        quirks: quirks::MODERN,
+        frames: 10,
+        keys: &[],
+    });
+}
+
+// A check or a cross for each of the arithmetic tests: ADD, SUB and SUBN with
+// and without carry, SHR and SHL, VF as the target and as an operand, 7XNN
+// wrapping without touching VF, logic ops and BCD. The flags don't depend on
+// the quirks so every preset shares the one image.
+fn flags(quirks: Quirks) {
+    check(Case {
+        name: "flags",
+        rom: "flags.ch8",
+        platform: Platform::Chip8,
+        quirks,
+        frames: 60,
+        keys: &[],
+    });
+}
+
+#[test]
+fn flags_vip() {
+    flags(quirks::COSMAC_VIP);
+}
+
+#[test]
+fn flags_chip48() {
+    flags(quirks::CHIP_48);
+}
+
+#[test]
+fn flags_schip() {
+    flags(quirks::SUPER_CHIP);
+}
+
+#[test]
+fn flags_modern() {
+    flags(quirks::MODERN);
+}
+
+// A check for each quirk that is on and a cross for each one that is off, in
+// the order: BXNN uses VX, logic ops reset VF, shifts read VY, FX55/FX65
+// move I past VX like the VIP, sprites clip at the edges
+fn quirks(name: &'static str, platform: Platform, quirks: Quirks) {
+    check(Case {
+        name,
+        rom: "quirks.ch8",
+        platform,
+        quirks,
+        frames: 10,
+        keys: &[],
+    });
+}
+
+#[test]
+fn quirks_vip() {
+    quirks("quirks-vip", Platform::Chip8, quirks::COSMAC_VIP);
+}
+
+#[test]
+fn quirks_chip48() {
+    quirks("quirks-chip48", Platform::Chip8, quirks::CHIP_48);
+}
+
+#[test]
+fn quirks_schip() {
+    quirks("quirks-schip", Platform::SuperChip, quirks::SUPER_CHIP);
+}
+
+#[test]
+fn quirks_modern() {
+    quirks("quirks-modern", Platform::Chip8, quirks::MODERN);
+}
+
+// FX0A shows the keys A, 3 and F as they are pressed, then EX9E and EXA1
+// wait for key 5 to be pressed and released
+#[test]
+fn keypad() {
+    check(Case {
+        name: "keypad",
+        rom: "keypad.ch8",
+        platform: Platform::Chip8,
+        quirks: quirks::MODERN,
+        frames: 60,
+        keys: &[(0xa, 5, 8), (0x3, 15, 18), (0xf, 25, 28), (0x5, 35, 45)],
+    });
+}
+
+// SUPER-CHIP high resolution: big font digits, a 16x16 sprite, scrolling
+// right, down and left and a sprite clipped at the bottom edge
+#[test]
+fn scroll() {
+    check(Case {
+        name: "scroll",
+        rom: "scroll.ch8",
+        platform: Platform::SuperChip,
+        quirks: quirks::SUPER_CHIP,
+        frames: 10,
+        keys: &[],
+    });
+}
@@ -0,0 +1,28 @@
+// Disassembles the roms in tests/roms and assembles the listings again, which
+// has to give back the same bytes whichever platform the listing is for.
+use std::{fs, path::PathBuf};
+
+use chip_8::{asm, disasm::Disassembly, platform::PLATFORMS};
+
+#[test]
+fn disassembled_roms_assemble_to_the_same_bytes() {
+    let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "roms"]
+        .iter()
+        .collect();
+    let mut roms = 0;
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("ch8") {
+            continue;
+        }
+        let rom = fs::read(&path).unwrap();
+        for &(name, platform) in PLATFORMS.iter() {
+            let listing = Disassembly::new(&rom, platform).listing();
+            let program = asm::assemble(&listing, platform)
+                .unwrap_or_else(|err| panic!("{} on {}: {}", path.display(), name, err));
+            assert_eq!(program.bytes, rom, "{} on {}", path.display(), name);
+        }
+        roms += 1;
+    }
+    assert!(roms > 0);
+}